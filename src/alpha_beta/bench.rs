@@ -0,0 +1,141 @@
+//! A reproducible benchmark of the alpha-beta search for spotting
+//! performance regressions. The positions are generated from a fixed
+//! seed, so runs on the same build search the same trees.
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::alpha_beta::alphabeta_with_stats;
+use crate::alpha_beta::heuristic::HeuristicPolicy;
+use crate::game::PositionsTracker;
+use crate::game::board::Board;
+use crate::game_tree::{GameSummary, GameTreeNode, SelectionPolicy};
+
+/// The seed the benchmark positions are generated from
+const BENCH_SEED: u64 = 0;
+
+/// How many random moves are played from the starting position to
+/// reach each benchmark position
+const BENCH_PLIES: usize = 10;
+
+/// The work done by a benchmark run
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BenchReport {
+    pub positions: usize,
+    /// The number of positions reached by the searches
+    pub nodes: u64,
+    /// The number of calls to the heuristic, including those made to
+    /// order moves
+    pub evals: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    pub fn nodes_per_second(&self) -> f64 {
+        self.nodes as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn evals_per_second(&self) -> f64 {
+        self.evals as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl Display for BenchReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Positions: {}", self.positions)?;
+        writeln!(
+            f,
+            "Nodes: {} ({:.0} per second)",
+            self.nodes,
+            self.nodes_per_second()
+        )?;
+        writeln!(
+            f,
+            "Evals: {} ({:.0} per second)",
+            self.evals,
+            self.evals_per_second()
+        )?;
+        write!(f, "Total time: {:?}", self.elapsed)
+    }
+}
+
+/// The heuristic, counting how often it is asked for an evaluation
+#[derive(Default)]
+struct CountingPolicy {
+    evals: Cell<u64>,
+}
+
+impl SelectionPolicy for CountingPolicy {
+    type TreeNode = GameTreeNode;
+
+    fn eval_attacker(&self, child: &GameTreeNode) -> i64 {
+        self.evals.set(self.evals.get() + 1);
+        HeuristicPolicy::default().eval_attacker(child)
+    }
+
+    fn eval_defender(&self, child: &GameTreeNode) -> i64 {
+        self.evals.set(self.evals.get() + 1);
+        HeuristicPolicy::default().eval_defender(child)
+    }
+
+    fn compare_children(
+        &self,
+        parent: &GameTreeNode,
+        child1: &GameTreeNode,
+        child2: &GameTreeNode,
+    ) -> Ordering {
+        self.evals.set(self.evals.get() + 2);
+        HeuristicPolicy::default().compare_children(parent, child1, child2)
+    }
+}
+
+/// Search `positions` positions to `depth` with the heuristic and report
+/// the throughput. The heuristic keeps evaluations of boards between
+/// searches, so only the first run in a process starts cold.
+pub fn bench(depth: usize, positions: usize) -> BenchReport {
+    let mut rng = StdRng::seed_from_u64(BENCH_SEED);
+    let roots = (0..positions)
+        .map(|_| {
+            let (board, turn) = Board::random_legal_position(&mut rng, BENCH_PLIES);
+            GameTreeNode {
+                current_board: board,
+                ..GameTreeNode::new(PositionsTracker::Counter(0)).with_turn(turn)
+            }
+        })
+        .collect::<Vec<_>>();
+    let policy = CountingPolicy::default();
+    let mut report = BenchReport {
+        positions,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    for root in &roots {
+        let (_, stats) = alphabeta_with_stats::<GameSummary, _, _>(root, &policy, depth);
+        report.nodes += stats.visited;
+    }
+    report.elapsed = start.elapsed();
+    report.evals = policy.evals.get();
+    report
+}
+
+#[cfg(test)]
+mod test_bench {
+    use super::*;
+
+    /// Test that a small benchmark searches and evaluates positions
+    #[test]
+    fn test_bench() {
+        let report = bench(1, 2);
+        assert_eq!(report.positions, 2);
+        assert!(report.nodes > 0);
+        assert!(report.evals > 0);
+        assert!(report.evals <= report.nodes);
+        assert!(report.elapsed > Duration::ZERO);
+        assert!(report.nodes_per_second() > 0.0);
+        assert!(report.to_string().contains("Nodes: "));
+    }
+}
@@ -1,11 +1,13 @@
 use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
 use std::cmp::Ordering;
 use std::sync::Mutex;
 
+use crate::alpha_beta::ScoredFromSideToMove;
 use crate::game::board::Board;
-use crate::game::heuristics::{escape_routes, fewest_turns_to_escape};
-use crate::game::space::{Role, Space, Square};
-use crate::game::{NormalizedBoardMap, Status};
+use crate::game::heuristics::{escape_routes, fewest_turns_to_escape, sliding_escape_routes};
+use crate::game::rules::Rules;
+use crate::game::space::Role;
 use crate::game_tree::{GameTreeNode, SelectionPolicy};
 use crate::mcts::{float_to_scaled_i64, scaled_i64_to_float};
 
@@ -13,142 +15,170 @@ use crate::mcts::{float_to_scaled_i64, scaled_i64_to_float};
 /// of that portion of the score.
 const UNREACHABLE_ESCAPE_SCORE: u8 = 8;
 
-/// A global table of the heuristic evaluations of board positions from the attacker's standpoint
-static BOARD_EVALUATIONS: Lazy<Mutex<NormalizedBoardMap<i64>>> =
-    Lazy::new(|| Mutex::new(NormalizedBoardMap::default()));
-
-/// A heuristic evaluation of a game state. It takes into account
-/// the following:
-///  * If the King can escape
-///  * The distance of the king to the nearest escape square
-///  * The number of squares needed to be occupied by attackers
-///    to block the king from all escapes
-///  * The material difference
-pub fn heuristic(game: &GameTreeNode) -> i64 {
-    match game.status {
-        Status::AttackersWin => {
-            return float_to_scaled_i64(match game.turn {
-                Role::Attacker => 10000.0,
-                Role::Defender => -10000.0,
-            });
-        }
-        Status::DefendersWin => {
-            return float_to_scaled_i64(match game.turn {
-                Role::Attacker => -10000.0,
-                Role::Defender => 10000.0,
-            });
-        }
-        Status::Draw => return 0,
-        Status::Ongoing => {
-            if let Some(val) = BOARD_EVALUATIONS.lock().unwrap().get(&game.current_board) {
-                return match game.turn {
-                    Role::Attacker => *val,
-                    Role::Defender => -*val,
-                };
-            }
-        }
-    }
+/// The most squares the attackers need to block the king off from the
+/// corners that the evaluation tells apart. A king who can't be blocked
+/// at all counts the same.
+const BLOCK_SQUARES_CAP: u8 = 8;
+
+/// Only the first few safe moves count towards the evaluation, so that
+/// sides running out of them are punished without rewarding mobility.
+const SAFE_MOVES_CAP: usize = 4;
+
+/// The heuristic evaluations of board positions from the attacker's
+/// standpoint with one set of weights and rules. The evaluation doesn't
+/// change under the board's symmetries, so boards are keyed by their
+/// [`Board::canonical_zobrist`] hash.
+type BoardEvaluations = ((HeuristicWeights, Rules), FxHashMap<u64, i64>);
+
+/// A global table of the evaluations for each set of weights and rules in use
+static BOARD_EVALUATIONS: Lazy<Mutex<Vec<BoardEvaluations>>> = Lazy::new(|| Mutex::new(vec![]));
 
+/// The heuristic evaluation of a game state with the default weights,
+/// see [`HeuristicPolicy::heuristic`]
+pub fn heuristic(game: &GameTreeNode) -> ScoredFromSideToMove {
+    HeuristicPolicy::default().heuristic(game)
+}
+
+/// The heuristic evaluation of an ongoing game for the attackers
+fn attacker_score(board: &Board, weights: &HeuristicWeights, rules: &Rules) -> i64 {
     // a number between 0 and 8
-    let escapes = escape_routes(&game.current_board) as i64;
-    let escape_dist =
-        fewest_turns_to_escape(&game.current_board).unwrap_or(UNREACHABLE_ESCAPE_SCORE) as i64;
+    let escapes = escape_routes(board) as i64;
+    // a number between 0 and 4
+    let sliding_escapes = sliding_escape_routes(board) as i64;
+    let escape_dist = fewest_turns_to_escape(board).unwrap_or(UNREACHABLE_ESCAPE_SCORE) as i64;
     // attackers want to maximize this metric
-    let piece_diff =
-        (game.current_board.attackers() as i64 - game.current_board.defenders() as i64) - 11;
-    let attacker_score = scaled_i64_to_float(piece_diff + escape_dist - escapes)
-        + attacker_corner_penalties(&game.current_board);
-    BOARD_EVALUATIONS
-        .lock()
-        .unwrap()
-        .insert(&game.current_board, float_to_scaled_i64(attacker_score));
-    float_to_scaled_i64(match game.turn {
-        Role::Attacker => attacker_score,
-        Role::Defender => -attacker_score,
-    })
+    let piece_diff = (board.attackers() as i64 - board.defenders() as i64) - 11;
+    let safe_moves = |role: Role| {
+        board
+            .forced_moves(&role, rules)
+            .take(SAFE_MOVES_CAP)
+            .count() as i64
+    };
+    let safe_diff = safe_moves(Role::Attacker) - safe_moves(Role::Defender);
+    let king_threats = king_threats(board);
+    // fewer squares to block than in the starting position, which needs the cap
+    let blocks_saved = (BLOCK_SQUARES_CAP - board.min_block_squares(BLOCK_SQUARES_CAP)) as i64;
+    float_to_scaled_i64(
+        scaled_i64_to_float(
+            piece_diff + escape_dist - escapes - sliding_escapes + safe_diff + king_threats,
+        ) + board.attacker_corner_penalties(weights.corner_penalty)
+            + weights.block_squares * scaled_i64_to_float(blocks_saved),
+    )
 }
 
-/// For each attacker next to a corner which is vulnerable
-/// to capture, add a penalty.
-fn attacker_corner_penalties(board: &Board) -> f64 {
-    const PENALTY_AMOUNT: f64 = 0.5;
-    let mut penalty = 0f64;
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 1, y: 0 }) {
-        if !board.is_occupied(&Square { x: 2, y: 0 }) {
-            penalty -= PENALTY_AMOUNT;
-        }
-    }
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 0, y: 1 }) {
-        if !board.is_occupied(&Square { x: 0, y: 2 }) {
-            penalty -= PENALTY_AMOUNT;
-        }
-    }
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 9, y: 0 }) {
-        if !board.is_occupied(&Square { x: 8, y: 0 }) {
-            penalty -= PENALTY_AMOUNT;
-        }
-    }
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 10, y: 1 }) {
-        if !board.is_occupied(&Square { x: 10, y: 2 }) {
-            penalty -= PENALTY_AMOUNT;
-        }
-    }
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 1, y: 10 }) {
-        if !board.is_occupied(&Square { x: 2, y: 10 }) {
-            penalty -= PENALTY_AMOUNT;
-        }
-    }
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 0, y: 9 }) {
-        if !board.is_occupied(&Square { x: 0, y: 8 }) {
-            penalty -= PENALTY_AMOUNT;
-        }
-    }
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 9, y: 10 }) {
-        if !board.is_occupied(&Square { x: 8, y: 10 }) {
-            penalty -= PENALTY_AMOUNT;
-        }
-    }
-    if let Space::Occupied(Role::Attacker) = board.get(&Square { x: 10, y: 9 }) {
-        if !board.is_occupied(&Square { x: 10, y: 8 }) {
-            penalty -= PENALTY_AMOUNT;
+/// The number of sides of the king with an attacker next to it or able
+/// to slide next to it, between 0 and 4
+fn king_threats(board: &Board) -> i64 {
+    let Some(king) = board.king_square() else {
+        return 0;
+    };
+    board
+        .nearest_attacker_along_lines(king)
+        .iter()
+        .filter(|distance| distance.is_some())
+        .count() as i64
+}
+
+/// The weights of the terms of the heuristic evaluation
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeuristicWeights {
+    /// Taken off for each attacker next to a corner which is
+    /// vulnerable to capture
+    pub corner_penalty: f64,
+    /// How much the squares the attackers would need to occupy to seal
+    /// the king off from the corners count against them, relative to
+    /// the king's escape routes
+    pub block_squares: f64,
+}
+
+impl Default for HeuristicWeights {
+    fn default() -> Self {
+        Self {
+            corner_penalty: 0.5,
+            block_squares: 1.0,
         }
     }
-    penalty
 }
 
+/// The weights are never NaN
+impl Eq for HeuristicWeights {}
+
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
-pub struct HeuristicPolicy;
+pub struct HeuristicPolicy {
+    pub weights: HeuristicWeights,
+}
+
+impl HeuristicPolicy {
+    /// A heuristic evaluation of a game state. It takes into account
+    /// the following:
+    ///  * If the King can escape
+    ///  * How many corners the king can slide into along two
+    ///    independent paths
+    ///  * The distance of the king to the nearest escape square
+    ///  * The number of squares needed to be occupied by attackers
+    ///    to block the king from all escapes
+    ///  * The material difference
+    ///  * If either side is close to running out of moves which don't
+    ///    lose immediately
+    ///  * How many sides of the king an attacker could slide in from
+    pub fn heuristic(&self, game: &GameTreeNode) -> ScoredFromSideToMove {
+        if let Some(value) = self.terminal_value(game) {
+            return ScoredFromSideToMove(value);
+        }
+        let key = (self.weights, game.rules);
+        let hash = game.current_board.canonical_zobrist();
+        let cached = BOARD_EVALUATIONS
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(table, _)| *table == key)
+            .and_then(|(_, evaluations)| evaluations.get(&hash).copied());
+        let attacker_score = cached.unwrap_or_else(|| {
+            let score = attacker_score(&game.current_board, &self.weights, &game.rules);
+            let mut tables = BOARD_EVALUATIONS.lock().unwrap();
+            match tables.iter_mut().find(|(table, _)| *table == key) {
+                Some((_, evaluations)) => {
+                    evaluations.insert(hash, score);
+                }
+                None => tables.push((key, FxHashMap::from_iter([(hash, score)]))),
+            }
+            score
+        });
+        ScoredFromSideToMove::from_attackers(attacker_score, game.turn)
+    }
+}
 
 impl SelectionPolicy for HeuristicPolicy {
     type TreeNode = GameTreeNode;
 
     fn eval_attacker(&self, child: &Self::TreeNode) -> i64 {
-        heuristic(child)
+        self.heuristic(child).0
     }
 
     fn eval_defender(&self, child: &Self::TreeNode) -> i64 {
-        heuristic(child)
+        self.heuristic(child).0
     }
 
     fn compare_children(
         &self,
-        parent: &Self::TreeNode,
+        _parent: &Self::TreeNode,
         child1: &Self::TreeNode,
         child2: &Self::TreeNode,
     ) -> Ordering {
-        match parent.turn {
-            Role::Attacker => self.eval_defender(child2).cmp(&self.eval_defender(child1)),
-            Role::Defender => self.eval_attacker(child1).cmp(&self.eval_attacker(child2)),
-        }
+        // each child is scored for the side to move in it, the parent's
+        // opponent, so the parent's score is its negation
+        let for_parent = |child: &GameTreeNode| -ScoredFromSideToMove(self.eval(child));
+        for_parent(child1).cmp(&for_parent(child2))
     }
 }
 
 #[cfg(test)]
 mod test_heuristic {
     use super::*;
-    use crate::alpha_beta::alphabeta_inner;
-    use crate::game::{EngineRole, LiveGame, Play};
-    use crate::game_tree::GameSummary;
+    use crate::alpha_beta::{alphabeta, alphabeta_inner};
+    use crate::game::space::Square;
+    use crate::game::{EngineRole, LiveGame, Play, PositionsTracker};
+    use crate::game_tree::{GameSummary, Threats};
     use rustc_hash::FxHashMap;
     use std::str::FromStr;
 
@@ -189,10 +219,12 @@ mod test_heuristic {
         let root = GameTreeNode::from(&mut non_block);
         let res = alphabeta_inner::<GameSummary, _, _>(
             &root,
-            &HeuristicPolicy,
+            &HeuristicPolicy::default(),
             &mut alphas,
             &mut betas,
             3,
+            (i64::MIN, i64::MAX),
+            &mut Default::default(),
         );
         assert_eq!(res, float_to_scaled_i64(-10000.0));
 
@@ -210,11 +242,144 @@ mod test_heuristic {
         let root = GameTreeNode::from(&mut non_block);
         let best_res = alphabeta_inner::<GameSummary, _, _>(
             &root,
-            &HeuristicPolicy,
+            &HeuristicPolicy::default(),
             &mut alphas,
             &mut betas,
             3,
+            (i64::MIN, i64::MAX),
+            &mut Default::default(),
         );
         assert!(best_res > float_to_scaled_i64(-10000.0));
     }
+
+    /// Test that scores are positive for the side to move when they are
+    /// winning, whichever side that is
+    #[test]
+    fn test_score_signs() {
+        let node = |board: [&str; 11], turn: Role| GameTreeNode {
+            turn,
+            current_board: Board::try_from(board).expect("Test failed"),
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        // the attackers capture the king next move
+        let capture = node(
+            [
+                "...........",
+                "...........",
+                "...O.......",
+                "..OKO......",
+                "...........",
+                "...........",
+                "...O.......",
+                "...........",
+                "...........",
+                ".........X.",
+                "...........",
+            ],
+            Role::Attacker,
+        );
+        // the king escapes next move
+        let escape = node(
+            [
+                "...........",
+                "...........",
+                "...........",
+                "...........",
+                "...........",
+                "...........",
+                "...........",
+                "...O.......",
+                "K..........",
+                "...........",
+                "...........",
+            ],
+            Role::Defender,
+        );
+        for winning in [capture, escape] {
+            let score = alphabeta::<GameSummary, _, _>(&winning, &HeuristicPolicy::default(), 1);
+            assert_eq!(score, ScoredFromSideToMove(float_to_scaled_i64(10000.0)));
+            // once the winning move is made, the loser is to move
+            let (_, won) = winning
+                .get_plays()
+                .into_iter()
+                .find(|(_, child)| child.is_terminal())
+                .expect("Test failed");
+            assert_eq!(heuristic(&won), -score);
+        }
+    }
+
+    /// Test that both sides choose the move the heuristic likes best
+    /// for them, not the one it likes best for their opponent
+    #[test]
+    fn test_sides_select_their_best_child() {
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "..K........",
+            "...........",
+            "......X....",
+            "...........",
+            "........O..",
+            "...........",
+            "....O......",
+            "...........",
+        ])
+        .expect("Test failed");
+        for turn in [Role::Attacker, Role::Defender] {
+            let root = GameTreeNode {
+                current_board: board.clone(),
+                ..GameTreeNode::new(PositionsTracker::Counter(0))
+            }
+            .with_turn(turn);
+            assert_eq!(root.threats(), Threats::Quiet);
+            let children = root.get_children();
+            // the children are scored for the opponent, who moves next
+            let scores = children
+                .iter()
+                .map(|child| heuristic(child).0)
+                .collect::<Vec<_>>();
+            let best = *scores.iter().min().unwrap();
+            let worst = *scores.iter().max().unwrap();
+            assert!(best < worst);
+            let chosen = heuristic(&root.select_child(&HeuristicPolicy::default())).0;
+            assert_eq!(chosen, best);
+            assert_ne!(chosen, worst);
+        }
+    }
+
+    /// Test that the evaluation uses the weights of the policy
+    #[test]
+    fn test_policy_weights() {
+        // an attacker next to a corner with nothing beyond it
+        let board = Board::try_from([
+            ".O.........",
+            "...........",
+            "...........",
+            "...........",
+            ".....X.....",
+            "....XKX....",
+            ".....X.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let game = GameTreeNode {
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        let weighted = |corner_penalty| {
+            HeuristicPolicy {
+                weights: HeuristicWeights {
+                    corner_penalty,
+                    ..Default::default()
+                },
+            }
+            .heuristic(&game)
+        };
+        assert_eq!(weighted(0.5), heuristic(&game));
+        assert_eq!(weighted(0.0).0 - weighted(2.0).0, float_to_scaled_i64(2.0));
+    }
 }
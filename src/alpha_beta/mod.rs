@@ -1,13 +1,55 @@
+pub mod bench;
 pub mod heuristic;
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::hash::Hash;
 use std::marker::PhantomData;
+use std::ops::Neg;
+use std::time::{Duration, Instant};
 
 use rustc_hash::FxHashMap;
 
 use crate::game::space::Role;
 use crate::game_tree::{ChildIterator, GameSummary, GameTreeNode, SelectionPolicy};
 
+/// An evaluation of a position for the side to move: positive scores are
+/// good for them and negative ones good for their opponent. Every score
+/// crossing the boundary of the search uses this convention, so the score
+/// of a position for the player who just moved into it is its negation.
+///
+/// Internally, the search works with scores for the attackers, which they
+/// maximize and the defenders minimize.
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ScoredFromSideToMove(pub i64);
+
+impl ScoredFromSideToMove {
+    /// Take a score for the attackers from the point of view of `turn`
+    fn from_attackers(score: i64, turn: Role) -> Self {
+        match turn {
+            Role::Attacker => Self(score),
+            Role::Defender => Self(-score),
+        }
+    }
+
+    /// The score for the attackers when `turn` is to move
+    fn for_attackers(self, turn: Role) -> i64 {
+        match turn {
+            Role::Attacker => self.0,
+            Role::Defender => -self.0,
+        }
+    }
+}
+
+/// The score of the same position for the other side
+impl Neg for ScoredFromSideToMove {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
 /// A node in the alpha beta tree that also can iterate over
 /// its children statefully.
 pub trait InternalNode<N>: Iterator<Item = N> {
@@ -110,10 +152,6 @@ where
     I: InternalNode<N>,
     N: GameNode<Convert = I>,
 {
-    fn turn(&self) -> Role {
-        self.internal_node.node().turn()
-    }
-
     fn node(&self) -> &N {
         self.internal_node.node()
     }
@@ -148,12 +186,11 @@ where
         self.node().is_terminal() || !self.peek()
     }
 
-    /// Evaluate this node given the provided heuristic
-    fn eval(&self, policy: &impl SelectionPolicy<TreeNode = N>) -> i64 {
-        match self.turn() {
-            Role::Attacker => policy.eval_attacker(self.node()),
-            Role::Defender => policy.eval_defender(self.node()),
-        }
+    /// Evaluate this node given the provided heuristic, as a score for
+    /// the attackers
+    fn eval(&self, policy: &impl SelectionPolicy<TreeNode = N>, stats: &mut SearchStats) -> i64 {
+        stats.nodes += 1;
+        eval_for_attackers(self.node(), policy)
     }
 
     fn is_leaf(&self) -> bool {
@@ -161,25 +198,220 @@ where
     }
 }
 
+/// Wraps a policy, remembering its evaluations of positions so that
+/// several searches over overlapping trees only evaluate each one once
+pub struct CachedPolicy<'a, S> {
+    policy: &'a S,
+    attacker_evals: RefCell<FxHashMap<GameSummary, i64>>,
+    defender_evals: RefCell<FxHashMap<GameSummary, i64>>,
+}
+
+impl<'a, S> CachedPolicy<'a, S> {
+    pub fn new(policy: &'a S) -> Self {
+        Self {
+            policy,
+            attacker_evals: Default::default(),
+            defender_evals: Default::default(),
+        }
+    }
+}
+
+impl<S: SelectionPolicy<TreeNode = GameTreeNode>> SelectionPolicy for CachedPolicy<'_, S> {
+    type TreeNode = GameTreeNode;
+
+    fn eval_attacker(&self, child: &GameTreeNode) -> i64 {
+        *self
+            .attacker_evals
+            .borrow_mut()
+            .entry(child.into())
+            .or_insert_with(|| self.policy.eval_attacker(child))
+    }
+
+    fn eval_defender(&self, child: &GameTreeNode) -> i64 {
+        *self
+            .defender_evals
+            .borrow_mut()
+            .entry(child.into())
+            .or_insert_with(|| self.policy.eval_defender(child))
+    }
+
+    fn compare_children(
+        &self,
+        parent: &GameTreeNode,
+        child1: &GameTreeNode,
+        child2: &GameTreeNode,
+    ) -> Ordering {
+        self.policy.compare_children(parent, child1, child2)
+    }
+}
+
+/// Statistics gathered while running an alpha-beta search
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// The number of positions evaluated by the heuristic
+    pub nodes: u64,
+    /// The number of positions the search reached, whether they were
+    /// evaluated or searched further
+    pub visited: u64,
+    /// The number of times the remaining children of a node were pruned
+    pub cutoffs: u64,
+    /// The number of times an aspiration window was missed and the
+    /// search repeated with a wider one
+    pub researches: u64,
+    pub elapsed: Duration,
+}
+
+/// The policy's evaluation of a position for the side to move,
+/// as a score for the attackers
+fn eval_for_attackers<N: GameNode>(node: &N, policy: &impl SelectionPolicy<TreeNode = N>) -> i64 {
+    ScoredFromSideToMove(policy.eval(node)).for_attackers(node.turn())
+}
+
+/// Search the tree below `root` to the given depth and score it for the
+/// side to move in `root`
 pub fn alphabeta<P, N, I>(
     root: &N,
     policy: &impl SelectionPolicy<TreeNode = N>,
     depth: usize,
-) -> i64
+) -> ScoredFromSideToMove
 where
     for<'a> P: ParentNode<'a, N>,
     I: InternalNode<N>,
     N: GameNode<Convert = I>,
 {
-    if depth == 0 {
-        return match root.turn() {
-            Role::Attacker => policy.eval_attacker(root),
-            Role::Defender => policy.eval_defender(root),
-        };
+    alphabeta_with_stats::<P, N, I>(root, policy, depth).0
+}
+
+/// Like [`alphabeta`], but also report how much work the search did
+pub fn alphabeta_with_stats<P, N, I>(
+    root: &N,
+    policy: &impl SelectionPolicy<TreeNode = N>,
+    depth: usize,
+) -> (ScoredFromSideToMove, SearchStats)
+where
+    for<'a> P: ParentNode<'a, N>,
+    I: InternalNode<N>,
+    N: GameNode<Convert = I>,
+{
+    alphabeta_window_with_stats::<P, N, I>(
+        root,
+        policy,
+        depth,
+        ScoredFromSideToMove(-i64::MAX),
+        ScoredFromSideToMove(i64::MAX),
+    )
+}
+
+/// [`alphabeta_window_with_stats`] without the statistics
+#[cfg(test)]
+pub fn alphabeta_window<P, N, I>(
+    root: &N,
+    policy: &impl SelectionPolicy<TreeNode = N>,
+    depth: usize,
+    alpha: ScoredFromSideToMove,
+    beta: ScoredFromSideToMove,
+) -> ScoredFromSideToMove
+where
+    for<'a> P: ParentNode<'a, N>,
+    I: InternalNode<N>,
+    N: GameNode<Convert = I>,
+{
+    alphabeta_window_with_stats::<P, N, I>(root, policy, depth, alpha, beta).0
+}
+
+/// Like [`alphabeta`], but only tell apart scores for the side to move
+/// strictly between `alpha` and `beta`, and also report how much work the
+/// search did. A score of at most `alpha` means the true score is no better,
+/// and one of at least `beta` that the true score is no worse.
+pub fn alphabeta_window_with_stats<P, N, I>(
+    root: &N,
+    policy: &impl SelectionPolicy<TreeNode = N>,
+    depth: usize,
+    alpha: ScoredFromSideToMove,
+    beta: ScoredFromSideToMove,
+) -> (ScoredFromSideToMove, SearchStats)
+where
+    for<'a> P: ParentNode<'a, N>,
+    I: InternalNode<N>,
+    N: GameNode<Convert = I>,
+{
+    let start = Instant::now();
+    let mut stats = SearchStats {
+        visited: 1,
+        ..Default::default()
+    };
+    // the window for the attackers, who the search scores for
+    let window = match root.turn() {
+        Role::Attacker => (alpha.0, beta.0),
+        Role::Defender => (-beta.0, -alpha.0),
+    };
+    let score = if depth == 0 {
+        stats.nodes += 1;
+        eval_for_attackers(root, policy)
+    } else {
+        let mut alphas: FxHashMap<P, i64> = FxHashMap::default();
+        let mut betas: FxHashMap<P, i64> = FxHashMap::default();
+        alphabeta_inner(
+            root,
+            policy,
+            &mut alphas,
+            &mut betas,
+            depth,
+            window,
+            &mut stats,
+        )
+    };
+    stats.elapsed = start.elapsed();
+    tracing::debug!(
+        stats.nodes,
+        stats.visited,
+        stats.cutoffs,
+        ?stats.elapsed,
+        score,
+        "alpha-beta search finished"
+    );
+    (
+        ScoredFromSideToMove::from_attackers(score, root.turn()),
+        stats,
+    )
+}
+
+/// Search within `margin` of `guess`, typically the score from searching
+/// one ply less deep. If the score falls outside of the window, search
+/// again with the window opened up on that side. The statistics add up
+/// all of the searches.
+pub fn aspiration_search<P, N, I>(
+    root: &N,
+    policy: &impl SelectionPolicy<TreeNode = N>,
+    depth: usize,
+    guess: ScoredFromSideToMove,
+    margin: i64,
+) -> (ScoredFromSideToMove, SearchStats)
+where
+    for<'a> P: ParentNode<'a, N>,
+    I: InternalNode<N>,
+    N: GameNode<Convert = I>,
+{
+    let mut alpha = ScoredFromSideToMove(guess.0.saturating_sub(margin).max(-i64::MAX));
+    let mut beta = ScoredFromSideToMove(guess.0.saturating_add(margin));
+    let mut total = SearchStats::default();
+    loop {
+        let (score, stats) =
+            alphabeta_window_with_stats::<P, N, I>(root, policy, depth, alpha, beta);
+        total.nodes += stats.nodes;
+        total.visited += stats.visited;
+        total.cutoffs += stats.cutoffs;
+        total.elapsed += stats.elapsed;
+        if score <= alpha && alpha.0 != -i64::MAX {
+            alpha = ScoredFromSideToMove(-i64::MAX);
+        } else if score >= beta && beta.0 != i64::MAX {
+            beta = ScoredFromSideToMove(i64::MAX);
+        } else {
+            return (score, total);
+        }
+        total.researches += 1;
+        tracing::debug!(?alpha, ?beta, "aspiration window failed, searching again");
     }
-    let mut alphas: FxHashMap<P, i64> = FxHashMap::default();
-    let mut betas: FxHashMap<P, i64> = FxHashMap::default();
-    alphabeta_inner(root, policy, &mut alphas, &mut betas, depth)
 }
 
 fn alphabeta_inner<P, N, I>(
@@ -188,19 +420,23 @@ fn alphabeta_inner<P, N, I>(
     alphas: &mut FxHashMap<P, i64>,
     betas: &mut FxHashMap<P, i64>,
     depth: usize,
+    (alpha, beta): (i64, i64),
+    stats: &mut SearchStats,
 ) -> i64
 where
     for<'a> P: ParentNode<'a, N>,
     I: InternalNode<N>,
     N: GameNode<Convert = I>,
 {
-    alphas.insert(P::from(root), i64::MIN);
-    betas.insert(P::from(root), i64::MAX);
+    let _span = tracing::debug_span!("alphabeta", depth).entered();
+    alphas.insert(P::from(root), alpha);
+    betas.insert(P::from(root), beta);
 
     let mut queue = vec![];
     for child in root.get_children() {
-        alphas.insert(P::from(&child), i64::MIN);
-        betas.insert(P::from(&child), i64::MAX);
+        stats.visited += 1;
+        alphas.insert(P::from(&child), alpha);
+        betas.insert(P::from(&child), beta);
         queue.push(AlphaBetaNode {
             parent: P::from(root),
             internal_node: child.convert(),
@@ -212,10 +448,8 @@ where
 
     // handle the case when the root is also a leaf
     if queue.is_empty() {
-        return match root.turn() {
-            Role::Attacker => policy.eval_attacker(root),
-            Role::Defender => policy.eval_defender(root),
-        };
+        stats.nodes += 1;
+        return eval_for_attackers(root, policy);
     }
     let mut last_tree_depth = depth;
     while let Some(mut ab_node) = queue.pop() {
@@ -224,13 +458,13 @@ where
         // child subtree
         if ab_node.depth > last_tree_depth || ab_node.is_leaf() {
             // update the parents alpha/ beta values based on last explored subtree
-            let cutoff = match ab_node.parent.turn() {
+            let (cutoff, eval) = match ab_node.parent.turn() {
                 Role::Attacker => {
                     let parent_eval = alphas
                         .get_mut(&ab_node.parent)
                         .expect("A child cannot be visited before its parent");
                     let eval = if ab_node.is_leaf() {
-                        ab_node.eval(policy)
+                        ab_node.eval(policy, stats)
                     } else {
                         *betas
                             .get(&P::from(ab_node.node()))
@@ -241,14 +475,14 @@ where
                     if *parent_eval >= eval || ab_node.exhausted() {
                         *parent_eval = std::cmp::max(*parent_eval, eval);
                     }
-                    *parent_eval >= eval
+                    (*parent_eval >= eval, eval)
                 }
                 Role::Defender => {
                     let parent_eval = betas
                         .get_mut(&ab_node.parent)
                         .expect("A child cannot be visited before its parent");
                     let eval = if ab_node.is_leaf() {
-                        ab_node.eval(policy)
+                        ab_node.eval(policy, stats)
                     } else {
                         *alphas
                             .get(&P::from(ab_node.node()))
@@ -257,13 +491,20 @@ where
                     if *parent_eval <= eval || ab_node.exhausted() {
                         *parent_eval = std::cmp::min(*parent_eval, eval);
                     }
-                    *parent_eval <= eval
+                    (*parent_eval <= eval, eval)
                 }
             };
             // we check if all subtrees have been explored. If not, put this node back on the stack
             if !cutoff && !ab_node.is_leaf() && !ab_node.exhausted() {
                 queue.push(ab_node);
             } else {
+                if cutoff && !ab_node.is_leaf() && !ab_node.exhausted() {
+                    stats.cutoffs += 1;
+                    tracing::trace!(depth = ab_node.depth, eval, "cutoff");
+                }
+                if ab_node.depth + 1 == depth {
+                    tracing::debug!(score = eval, "finished exploring root move");
+                }
                 // we will not visit this node again so it is safe to remove data about it
                 let node_key = P::from(ab_node.node());
                 alphas.remove(&node_key);
@@ -273,6 +514,7 @@ where
             // we are moving down the tree
 
             if let Some(child) = ab_node.next_child() {
+                stats.visited += 1;
                 // initialize the alpha / beta value for this node in the table if necessary
                 let child_key = P::from(child.node());
 
@@ -315,6 +557,8 @@ mod test_alphabeta {
         label: usize,
         is_left: bool,
         max_level: usize,
+        /// Iterate over the right child before the left one
+        right_first: bool,
     }
 
     #[derive(Debug, Copy, Clone)]
@@ -349,20 +593,10 @@ mod test_alphabeta {
             if self.is_terminal() {
                 vec![]
             } else {
-                vec![
-                    Self {
-                        level: self.level + 1,
-                        label: (self.label << 1) + 1,
-                        is_left: false,
-                        max_level: self.max_level,
-                    },
-                    Self {
-                        level: self.level + 1,
-                        label: self.label << 1,
-                        is_left: true,
-                        max_level: self.max_level,
-                    },
-                ]
+                // the search visits these in reverse order
+                let mut children = self.convert().collect::<Vec<_>>();
+                children.reverse();
+                children
             }
         }
     }
@@ -379,7 +613,7 @@ mod test_alphabeta {
 
     impl From<&TestTreeNode> for TestTreeNode {
         fn from(value: &TestTreeNode) -> Self {
-            value.clone()
+            *value
         }
     }
 
@@ -387,22 +621,18 @@ mod test_alphabeta {
         type Item = TestTreeNode;
 
         fn next(&mut self) -> Option<Self::Item> {
-            if self.next_child.take()? {
+            let first = self.next_child.take()?;
+            if first {
                 self.next_child = Some(false);
-                Some(TestTreeNode {
-                    level: self.node.level + 1,
-                    label: self.node.label << 1,
-                    is_left: true,
-                    max_level: self.node.max_level,
-                })
-            } else {
-                Some(TestTreeNode {
-                    level: self.node.level + 1,
-                    label: (self.node.label << 1) + 1,
-                    is_left: false,
-                    max_level: self.node.max_level,
-                })
             }
+            let is_left = first != self.node.right_first;
+            Some(TestTreeNode {
+                level: self.node.level + 1,
+                label: (self.node.label << 1) + usize::from(!is_left),
+                is_left,
+                max_level: self.node.max_level,
+                right_first: self.node.right_first,
+            })
         }
     }
 
@@ -426,10 +656,11 @@ mod test_alphabeta {
             self.evaluations[child.label]
         }
 
+        /// The evaluations are for the attackers
         fn eval_defender(&self, child: &Self::TreeNode) -> i64 {
             let mut queries = self.queries.borrow_mut();
             queries.insert(child.label);
-            self.evaluations[child.label]
+            -self.evaluations[child.label]
         }
 
         fn compare_children(
@@ -452,6 +683,7 @@ mod test_alphabeta {
             label: 0,
             is_left: false,
             max_level: 0,
+            right_first: false,
         };
         let policy = PolicyVector {
             queries: Default::default(),
@@ -459,13 +691,22 @@ mod test_alphabeta {
         };
         let mut alphas: FxHashMap<TestTreeNode, i64> = FxHashMap::default();
         let mut betas: FxHashMap<TestTreeNode, i64> = FxHashMap::default();
-        let res = alphabeta_inner(&root, &policy, &mut alphas, &mut betas, 3);
+        let res = alphabeta_inner(
+            &root,
+            &policy,
+            &mut alphas,
+            &mut betas,
+            3,
+            (i64::MIN, i64::MAX),
+            &mut Default::default(),
+        );
         assert_eq!(res, 10);
         let root = TestTreeNode {
             level: 0,
             label: 0,
             is_left: false,
             max_level: 1,
+            right_first: false,
         };
         let policy = PolicyVector {
             queries: Default::default(),
@@ -473,7 +714,15 @@ mod test_alphabeta {
         };
         let mut alphas: FxHashMap<TestTreeNode, i64> = FxHashMap::default();
         let mut betas: FxHashMap<TestTreeNode, i64> = FxHashMap::default();
-        let res = alphabeta_inner(&root, &policy, &mut alphas, &mut betas, 3);
+        let res = alphabeta_inner(
+            &root,
+            &policy,
+            &mut alphas,
+            &mut betas,
+            3,
+            (i64::MIN, i64::MAX),
+            &mut Default::default(),
+        );
         assert_eq!(res, 2);
     }
 
@@ -484,6 +733,7 @@ mod test_alphabeta {
             label: 0,
             is_left: false,
             max_level: 5,
+            right_first: false,
         };
 
         let policy = PolicyVector {
@@ -493,7 +743,15 @@ mod test_alphabeta {
 
         let mut alphas: FxHashMap<TestTreeNode, i64> = FxHashMap::default();
         let mut betas: FxHashMap<TestTreeNode, i64> = FxHashMap::default();
-        let res = alphabeta_inner(&root, &policy, &mut alphas, &mut betas, 3);
+        let res = alphabeta_inner(
+            &root,
+            &policy,
+            &mut alphas,
+            &mut betas,
+            3,
+            (i64::MIN, i64::MAX),
+            &mut Default::default(),
+        );
 
         assert_eq!(res, 3);
         let mut expected = HashSet::from([0, 1, 2, 4, 5]);
@@ -508,4 +766,141 @@ mod test_alphabeta {
         assert_eq!(val, i64::MAX);
         assert!(betas.is_empty());
     }
+
+    /// A writer collecting everything logged into a shared buffer
+    #[derive(Clone, Default)]
+    struct LogBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Test that pruning the tree is reported when tracing
+    #[test]
+    fn test_cutoff_events() {
+        let root = TestTreeNode {
+            level: 0,
+            label: 0,
+            is_left: false,
+            max_level: 5,
+            right_first: false,
+        };
+        let policy = PolicyVector {
+            queries: Default::default(),
+            evaluations: vec![-1, 3, 5, 7, -6, -4, -8, -9],
+        };
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let res = tracing::subscriber::with_default(subscriber, || {
+            alphabeta::<TestTreeNode, _, _>(&root, &policy, 3)
+        });
+        assert_eq!(res, ScoredFromSideToMove(3));
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).expect("Test failed");
+        assert!(logs.contains("cutoff"));
+        assert!(logs.contains("finished exploring root move score=3"));
+        assert!(logs.contains("alpha-beta search finished"));
+    }
+
+    /// Test that the statistics count the visited and evaluated positions and
+    /// that searching the better moves first evaluates fewer of them
+    #[test]
+    fn test_search_stats() {
+        let mut root = TestTreeNode {
+            level: 0,
+            label: 0,
+            is_left: false,
+            max_level: 5,
+            right_first: false,
+        };
+        let policy = PolicyVector {
+            queries: Default::default(),
+            evaluations: vec![-1, 3, 5, 7, -6, -4, -8, -9],
+        };
+        let (res, stats) = alphabeta_with_stats::<TestTreeNode, _, _>(&root, &policy, 3);
+        assert_eq!(res, ScoredFromSideToMove(3));
+        assert_eq!(stats.nodes, policy.queries.borrow().len() as u64);
+        assert_eq!(stats.nodes, 5);
+        // the root, both of its children, three of their children and
+        // the evaluated leaves
+        assert_eq!(stats.visited, 11);
+        assert_eq!(stats.cutoffs, 2);
+
+        // the mirror image of the above tree, where visiting the
+        // right child first is the better ordering
+        let policy = PolicyVector {
+            queries: Default::default(),
+            evaluations: vec![-9, -8, -4, -6, 7, 5, 3, -1],
+        };
+        let (unordered_res, unordered) =
+            alphabeta_with_stats::<TestTreeNode, _, _>(&root, &policy, 3);
+        assert_eq!(unordered.nodes, policy.queries.borrow().len() as u64);
+        root.right_first = true;
+        let (ordered_res, ordered) = alphabeta_with_stats::<TestTreeNode, _, _>(&root, &policy, 3);
+        assert_eq!(unordered_res, ScoredFromSideToMove(3));
+        assert_eq!(ordered_res, ScoredFromSideToMove(3));
+        assert_eq!(ordered.nodes, 5);
+        assert!(ordered.nodes < unordered.nodes);
+    }
+
+    /// Test that a window containing the score finds the same score as a
+    /// full width search and that missing the window searches again
+    #[test]
+    fn test_aspiration_window() {
+        let root = TestTreeNode {
+            level: 0,
+            label: 0,
+            is_left: false,
+            max_level: 5,
+            right_first: false,
+        };
+        let policy = PolicyVector {
+            queries: Default::default(),
+            evaluations: vec![-1, 3, 5, 7, -6, -4, -8, -9],
+        };
+        let full = alphabeta::<TestTreeNode, _, _>(&root, &policy, 3);
+        let res = alphabeta_window::<TestTreeNode, _, _>(
+            &root,
+            &policy,
+            3,
+            ScoredFromSideToMove(0),
+            ScoredFromSideToMove(5),
+        );
+        assert_eq!(res, full);
+        let (res, stats) =
+            aspiration_search::<TestTreeNode, _, _>(&root, &policy, 3, ScoredFromSideToMove(2), 2);
+        assert_eq!(res, full);
+        assert_eq!(stats.researches, 0);
+
+        // the score is below the window
+        let res = alphabeta_window::<TestTreeNode, _, _>(
+            &root,
+            &policy,
+            3,
+            ScoredFromSideToMove(5),
+            ScoredFromSideToMove(10),
+        );
+        assert!(res <= ScoredFromSideToMove(5));
+        let (res, stats) =
+            aspiration_search::<TestTreeNode, _, _>(&root, &policy, 3, ScoredFromSideToMove(7), 1);
+        assert_eq!(res, full);
+        assert_eq!(stats.researches, 1);
+
+        // the score is above the window
+        let (res, stats) =
+            aspiration_search::<TestTreeNode, _, _>(&root, &policy, 3, ScoredFromSideToMove(-5), 1);
+        assert_eq!(res, full);
+        assert_eq!(stats.researches, 1);
+    }
 }
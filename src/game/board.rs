@@ -1,13 +1,17 @@
+use rand::Rng;
 use serde::ser::SerializeTuple;
 use serde::{Deserialize, Serialize, Serializer};
 use std::cmp::Ordering;
 use std::collections::{HashSet, VecDeque};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
+use crate::game::rules::Rules;
 use crate::game::space::{
     BOARD_LETTERS, EXIT_SQUARES, RESTRICTED_SQUARES, Role, Space, Square, SquareSet, THRONE,
 };
 use crate::game::symmetries::{D8, D8Generator};
+use crate::game::zobrist;
 use crate::game::{Play, PlayError, PositionsTracker, Status};
 
 pub const STARTING_POSITION: [&str; 11] = [
@@ -24,10 +28,84 @@ pub const STARTING_POSITION: [&str; 11] = [
     "...OOOOO...",
 ];
 
-#[derive(Clone, Eq, Hash, PartialEq, Deserialize)]
+/// The number of pieces each side has in the [`STARTING_POSITION`],
+/// including the king for the defenders.
+pub const STARTING_MATERIAL: (u8, u8) = (24, 13);
+
+/// The squares next to the corners, each paired with the square beyond
+/// it along the edge. An attacker on the first square with the second
+/// empty can be captured against the corner.
+pub const CORNER_GUARDS: [(Square, Square); 8] = [
+    (Square { x: 1, y: 0 }, Square { x: 2, y: 0 }),
+    (Square { x: 0, y: 1 }, Square { x: 0, y: 2 }),
+    (Square { x: 9, y: 0 }, Square { x: 8, y: 0 }),
+    (Square { x: 10, y: 1 }, Square { x: 10, y: 2 }),
+    (Square { x: 1, y: 10 }, Square { x: 2, y: 10 }),
+    (Square { x: 0, y: 9 }, Square { x: 0, y: 8 }),
+    (Square { x: 9, y: 10 }, Square { x: 8, y: 10 }),
+    (Square { x: 10, y: 9 }, Square { x: 10, y: 8 }),
+];
+
+/// A game is drawn once this many positions have been played
+pub const MOVE_LIMIT: usize = 100;
+
+/// The first two bits of a bitboard when the board has no king. They
+/// encode an attacker on the corner A11.
+const NO_KING_SENTINEL: u8 = 1 << 6;
+
+/// How a piece came to be captured
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum CaptureMechanism {
+    /// Sandwiched between the moved piece and a hostile square
+    Custodial { flankers: [Square; 2] },
+    /// Part of a row of pieces on the edge enclosed by the moved piece
+    /// and the hostile square at the other end of the row
+    ShieldWall { anchor: Square },
+}
+
+/// A captured piece along with the cause of its capture
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Capture {
+    pub square: Square,
+    pub mechanism: CaptureMechanism,
+}
+
+/// Every capture caused by a move. A piece captured in more
+/// than one way is listed once for each.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CaptureReport {
+    pub captures: Vec<Capture>,
+}
+
+/// A coarse classification of how far along a game is, based on
+/// how many pieces remain on the board.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum GamePhase {
+    /// At least 90% of the starting pieces remain
+    Opening,
+    Midgame,
+    /// At most 40% of the starting pieces remain
+    Endgame,
+}
+
+#[derive(Clone, Eq, PartialEq)]
 pub struct Board {
-    #[serde(deserialize_with = "deserialize_space_array")]
-    pub spaces: [Space; 11 * 11],
+    spaces: [Space; 11 * 11],
+    /// The square of the king, kept up to date by [`Board::set`]
+    king: Option<Square>,
+    /// The number of attackers and defenders (including the king),
+    /// kept up to date by [`Board::set`]
+    material: (u8, u8),
+    /// The Zobrist hash of the board, kept up to date by [`Board::set`]
+    zobrist: u64,
+}
+
+/// Equal boards have equal Zobrist hashes, which are far cheaper to hash
+/// than every square
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.zobrist().hash(state)
+    }
 }
 
 impl Serialize for Board {
@@ -43,6 +121,15 @@ impl Serialize for Board {
     }
 }
 
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        Ok(Self::from_spaces(deserialize_space_array(deserializer)?))
+    }
+}
+
 fn deserialize_space_array<'de, D>(deserializer: D) -> Result<[Space; 121], D::Error>
 where
     D: serde::de::Deserializer<'de>,
@@ -56,6 +143,7 @@ where
         Ok(spaces.try_into().unwrap())
     }
 }
+
 impl Default for Board {
     fn default() -> Self {
         STARTING_POSITION.try_into().unwrap()
@@ -69,12 +157,7 @@ impl fmt::Debug for Board {
             write!(f, r#"""#)?;
 
             for x in 0..11 {
-                match self.spaces[(y * 11) + x] {
-                    Space::Occupied(Role::Defender) => write!(f, "X")?,
-                    Space::Empty => write!(f, ".")?,
-                    Space::King => write!(f, "K")?,
-                    Space::Occupied(Role::Attacker) => write!(f, "O")?,
-                }
+                write!(f, "{}", char::from(self.get(&Square { x, y })))?;
             }
             writeln!(f, r#"""#)?;
         }
@@ -100,11 +183,11 @@ impl fmt::Display for Board {
                     || (y, x) == (0, 10)
                     || (y, x) == (10, 10)
                     || (y, x) == (5, 5))
-                    && self.spaces[y * 11 + x] == Space::Empty
+                    && self.get(&Square { x, y }) == Space::Empty
                 {
                     write!(f, "⌘")?;
                 } else {
-                    write!(f, "{}", self.spaces[y * 11 + x])?;
+                    write!(f, "{}", self.get(&Square { x, y }))?;
                 }
             }
             writeln!(f, "│{y_label:2}")?;
@@ -141,23 +224,51 @@ impl TryFrom<[&str; 11]> for Board {
                     }
                 }
 
-                spaces[y * 11 + x] = space;
+                spaces[Square { x, y }.to_index()] = space;
             }
         }
 
-        Ok(Self { spaces })
+        Ok(Self::from_spaces(spaces))
     }
 }
 
 impl Board {
+    /// The rows of the board in the format accepted by [`Board::from_ascii`]
+    /// and `TryFrom<[&str; 11]>`, e.g. `"...OXKXO..."`
+    pub fn to_ascii(&self) -> [String; 11] {
+        std::array::from_fn(|y| {
+            (0..11)
+                .map(|x| char::from(self.get(&Square { x, y })))
+                .collect()
+        })
+    }
+
+    /// Read a board from rows as produced by [`Board::to_ascii`]
+    pub fn from_ascii(rows: &[String; 11]) -> anyhow::Result<Self> {
+        Self::try_from(rows.each_ref().map(String::as_str))
+    }
+
+    /// The squares occupied by player `role`'s pieces, including the king
+    /// for the defenders
+    pub fn pieces(&self, role: &Role) -> SquareSet {
+        self.occupied_squares(*role).map(|sq| (sq, ())).collect()
+    }
+
+    /// Iterate over the squares currently occupied by player `role`'s
+    /// pieces in board order, including the king for the defenders
+    pub fn occupied_squares(&self, role: Role) -> impl Iterator<Item = Square> + '_ {
+        self.spaces
+            .iter()
+            .enumerate()
+            .filter(move |(_, space)| space.is_ally(&role))
+            .map(|(ix, _)| Square::from_index(ix))
+    }
+
     /// Check if a given player can make a legal move
     #[must_use]
     pub fn a_legal_move_exists(&self, turn: &Role) -> bool {
-        for src in Square::iter().filter(|sq| self.get(sq).is_ally(turn)) {
-            for dest in [src.left(), src.right(), src.up(), src.down()]
-                .into_iter()
-                .flatten()
-            {
+        for src in self.occupied_squares(*turn) {
+            for dest in src.orthogonal_neighbors() {
                 if !dest.is_restricted() && self.get(&dest) == Space::Empty {
                     return true;
                 }
@@ -166,17 +277,228 @@ impl Board {
         false
     }
 
+    /// The start and end squares of every move player `role` can make,
+    /// ignoring repetitions. Pieces slide through empty squares, but only
+    /// the king may stop on a restricted square.
+    pub fn moves(&self, role: &Role) -> impl Iterator<Item = (Square, Square)> + '_ {
+        self.occupied_squares(*role).flat_map(move |from| {
+            let is_king = self.get(&from) == Space::King;
+            [(0, -1), (-1, 0), (1, 0), (0, 1)]
+                .into_iter()
+                .flat_map(move |(dx, dy)| {
+                    std::iter::successors(from.step(dx, dy), move |to| to.step(dx, dy))
+                        .take_while(|to| self.get(to) == Space::Empty)
+                })
+                .filter(move |to| is_king || !to.is_restricted())
+                .map(move |to| (from, to))
+        })
+    }
+
+    /// Call `f` with the start and end squares of every move player `role`
+    /// can make, ignoring repetitions
+    pub fn for_each_move<F>(&self, role: &Role, mut f: F)
+    where
+        F: FnMut(Square, Square),
+    {
+        self.moves(role).for_each(|(from, to)| f(from, to));
+    }
+
+    /// The number of moves player `role` can make, ignoring repetitions
+    pub fn mobility(&self, role: &Role) -> usize {
+        let mut count = 0;
+        self.for_each_move(role, |_, _| count += 1);
+        count
+    }
+
+    /// Every move player `role` can make, ignoring repetitions, in the
+    /// order given by [`Play::sort_key`]
+    pub fn legal_moves_sorted(&self, role: &Role) -> Vec<Play> {
+        let mut plays = vec![];
+        self.for_each_move(role, |from, to| {
+            plays.push(Play {
+                role: *role,
+                from,
+                to,
+            })
+        });
+        plays.sort_unstable_by_key(Play::sort_key);
+        plays
+    }
+
     pub fn empty() -> Self {
-        Self {
-            spaces: [Space::Empty; 11 * 11],
+        Self::from_spaces([Space::Empty; 11 * 11])
+    }
+
+    /// Set up a board with the king on the throne and the other pieces on
+    /// the given squares. Errors if a piece is placed on a restricted
+    /// square or two pieces are placed on the same square.
+    pub fn from_role_setup(
+        attackers: impl IntoIterator<Item = Square>,
+        defenders: impl IntoIterator<Item = Square>,
+    ) -> anyhow::Result<Self> {
+        let mut board = Self::empty();
+        board.set(&THRONE, Space::King);
+        let pieces = attackers
+            .into_iter()
+            .map(|sq| (sq, Role::Attacker))
+            .chain(defenders.into_iter().map(|sq| (sq, Role::Defender)));
+        for (sq, role) in pieces {
+            if sq.is_restricted() {
+                return Err(anyhow::Error::msg(
+                    "Only the king is allowed on restricted squares!",
+                ));
+            }
+            if board.get(&sq) != Space::Empty {
+                return Err(anyhow::Error::msg(format!(
+                    "More than one piece was placed on {sq}"
+                )));
+            }
+            board.set(&sq, Space::Occupied(role));
+        }
+        Ok(board)
+    }
+
+    /// Perturb the standard setup for variety in self-play. Each of the
+    /// attackers on one edge may step sideways or inwards, and the same
+    /// changes are made on the other three edges so that the position stays
+    /// symmetric. The defenders and king keep their places.
+    pub fn random_start(rng: &mut impl Rng) -> Self {
+        // the region around the attackers on the top edge. Its images under
+        // the rotations of the board do not overlap
+        let in_region = |sq: &Square| sq.y <= 2 && (3..=7).contains(&sq.x);
+        let mut edge = Role::Attacker
+            .starting_squares()
+            .filter(in_region)
+            .collect::<Vec<_>>();
+        for ix in 0..edge.len() {
+            let sq = edge[ix];
+            let candidates = [Some(sq), sq.left(), sq.right(), sq.down()]
+                .into_iter()
+                .flatten()
+                .filter(|c| *c == sq || (in_region(c) && !edge.contains(c)))
+                .collect::<Vec<_>>();
+            edge[ix] = candidates[rng.random_range(0..candidates.len())];
+        }
+        // the identity and the rotations
+        let attackers = [D8[0], D8[5], D8[6], D8[7]]
+            .into_iter()
+            .flat_map(|rotation| edge.iter().map(move |sq| rotation.apply_square(sq)))
+            .collect::<Vec<_>>();
+        let board = Self::from_role_setup(attackers, Role::Defender.starting_squares())
+            .expect("The edges of the board do not overlap");
+        debug_assert!(board.validate_playable().is_ok());
+        board
+    }
+
+    /// Play up to `plies` random legal moves from the starting position,
+    /// stopping early rather than ending the game. Returns the board and
+    /// the player to move.
+    pub fn random_legal_position(rng: &mut impl Rng, plies: usize) -> (Self, Role) {
+        let mut board = Self::default();
+        let mut role = Role::Attacker;
+        let previous_boards = PositionsTracker::Counter(0);
+        for _ in 0..plies {
+            let plays = board.legal_moves_sorted(&role);
+            if plays.is_empty() {
+                break;
+            }
+            let play = &plays[rng.random_range(0..plays.len())];
+            let Ok((next, _, Status::Ongoing)) =
+                board.play_internal(play, &Status::Ongoing, &previous_boards)
+            else {
+                break;
+            };
+            board = next;
+            role = role.opposite();
+        }
+        (board, role)
+    }
+
+    /// Check that a game can be played from this board. There must be exactly
+    /// one king, only he may be on a restricted square, the game must not
+    /// already be won and both sides must be able to move.
+    pub fn validate_playable(&self) -> anyhow::Result<()> {
+        let kings = self.spaces.iter().filter(|sp| **sp == Space::King).count();
+        if kings != 1 {
+            return Err(anyhow::Error::msg(format!(
+                "There must be exactly one king, found {kings}"
+            )));
+        }
+        if RESTRICTED_SQUARES
+            .iter()
+            .any(|sq| matches!(self.get(sq), Space::Occupied(_)))
+        {
+            return Err(anyhow::Error::msg(
+                "Only the king is allowed on restricted squares!",
+            ));
+        }
+        if self.king_square().is_some_and(|king| king.is_exit()) {
+            return Err(anyhow::Error::msg("The king has already escaped"));
+        }
+        if self.capture_the_king(&Default::default()) || self.flood_fill_attackers_win() {
+            return Err(anyhow::Error::msg("The attackers have already won"));
+        }
+        for role in [Role::Attacker, Role::Defender] {
+            if !self.a_legal_move_exists(&role) {
+                return Err(anyhow::Error::msg(format!("The {role}s cannot move")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Work out the status of a position under `rules` with `turn` to move
+    /// without playing a move, e.g. for a board loaded from notation
+    pub fn compute_status(&self, turn: Role, tracker: &PositionsTracker, rules: &Rules) -> Status {
+        let Some(king) = self.king_square() else {
+            return Status::AttackersWin;
+        };
+        if king.is_exit() || (rules.exit_forts && self.is_exit_fort()) {
+            return Status::DefendersWin;
+        }
+        if self.capture_the_king(rules) || self.flood_fill_attackers_win() {
+            return Status::AttackersWin;
+        }
+        if !self.a_legal_move_exists(&turn) {
+            return turn.opposite().victory();
+        }
+        if tracker.len() >= MOVE_LIMIT {
+            return Status::Draw;
         }
+        Status::Ongoing
+    }
+
+    /// Create a board, computing the king's square, the material and the hash
+    fn from_spaces(spaces: [Space; 11 * 11]) -> Self {
+        let mut board = Self {
+            spaces,
+            king: None,
+            material: (0, 0),
+            zobrist: 0,
+        };
+        board.king = board.find_the_king();
+        board.material = board.count_material();
+        board.zobrist = board.compute_zobrist();
+        board
     }
 
     /// Rotate and / or flip the board so that the king is as close to the origin
     /// as possible and is below the line y = x. This helps reduce the branching
     /// at each stage of the game.
+    ///
+    /// Once the king is captured, there is no king to fix the orientation,
+    /// so the board is instead rotated and flipped to whichever of its
+    /// symmetries has the smallest bitboard.
     pub fn normalize(&mut self) {
-        let Some(king) = self.find_the_king() else {
+        let Some(king) = self.king_square() else {
+            *self = D8
+                .into_iter()
+                .map(|d8| {
+                    let mut board = self.clone();
+                    d8.apply(&mut board);
+                    board
+                })
+                .min_by_key(Board::as_bitboard)
+                .unwrap();
             return;
         };
 
@@ -200,7 +522,7 @@ impl Board {
         if king.y > 5 {
             D8Generator::F.apply(self);
         }
-        let Some(king) = self.find_the_king() else {
+        let Some(king) = self.king_square() else {
             return;
         };
 
@@ -210,6 +532,32 @@ impl Board {
         }
     }
 
+    /// Check if two boards are the same position up to rotating and flipping.
+    /// For one-off comparisons this is cheaper than comparing symmetric
+    /// hashes, as usually normalizing both boards is enough.
+    pub fn same_up_to_symmetry(&self, other: &Board) -> bool {
+        if self.material() != other.material() {
+            return false;
+        }
+        let mut normalized = self.clone();
+        normalized.normalize();
+        let mut other = other.clone();
+        other.normalize();
+        if normalized == other {
+            return true;
+        }
+        match normalized.king_square() {
+            // the king's position fixes the orientation unless he
+            // is on one of the lines of symmetry
+            Some(king) if king.x != 5 && king.y != 5 && king.x != king.y => false,
+            _ => D8.into_iter().any(|d8| {
+                let mut board = other.clone();
+                d8.apply(&mut board);
+                board == normalized
+            }),
+        }
+    }
+
     /// Get all equivalent boards after rotating and flipping
     pub fn symmetries(&self) -> HashSet<Self> {
         let mut syms = HashSet::new();
@@ -221,13 +569,31 @@ impl Board {
         syms
     }
 
+    /// The number of distinct boards equivalent to this one under
+    /// rotations and flips. Boards with self-symmetry have fewer than eight.
+    pub fn symmetry_class_size(&self) -> usize {
+        self.symmetries().len()
+    }
+
+    /// The board with the attackers and defenders swapped. The king stays put.
+    #[must_use]
+    pub fn flip_perspective(&self) -> Self {
+        let mut board = self.clone();
+        for square in Square::iter() {
+            if let Space::Occupied(role) = self.get(&square) {
+                board.set(&square, Space::Occupied(role.opposite()));
+            }
+        }
+        board
+    }
+
     pub fn as_bitboard(&self) -> [u8; 30] {
         let mut bitboard = [0u8; 30];
         for (ix, sp) in self.spaces.iter().enumerate() {
             // there is no need to encode the throne. If the king is
             // not present elsewhere in the bitboard, we know he is on
             // the throne
-            let index = match ix.cmp(&60) {
+            let index = match ix.cmp(&THRONE.to_index()) {
                 Ordering::Greater => ix - 1,
                 Ordering::Less => ix,
                 Ordering::Equal => continue,
@@ -245,65 +611,103 @@ impl Board {
             let pos = (2 * index) & 7;
             bitboard[slot] += value << (6 - pos);
         }
+        // so that a board without a king is not taken to have him on the
+        // throne, mark it with an attacker on the first corner, where no
+        // attacker can stand
+        if self.king_square().is_none() {
+            bitboard[0] |= NO_KING_SENTINEL;
+        }
         bitboard
     }
 
+    /// A compact encoding of the board in 30 bytes, see
+    /// [`Board::as_bitboard`]
+    #[cfg(test)]
+    pub fn to_bytes(&self) -> [u8; 30] {
+        self.as_bitboard()
+    }
+
+    /// Decode a board encoded by [`Board::to_bytes`]. If no king is
+    /// encoded, he is on the throne, unless the board is marked as
+    /// having no king.
+    #[cfg(test)]
+    pub fn from_bytes(bytes: &[u8; 30]) -> Self {
+        let mut board = Self::empty();
+        let no_king = bytes[0] & 0b1100_0000 == NO_KING_SENTINEL;
+        for index in 0..11 * 11 - 1 {
+            if index == 0 && no_king {
+                continue;
+            }
+            let value = (bytes[(2 * index) / 8] >> (6 - ((2 * index) & 7))) & 3;
+            let space = match value {
+                1 => Space::Occupied(Role::Attacker),
+                2 => Space::Occupied(Role::Defender),
+                3 => Space::King,
+                _ => continue,
+            };
+            // the throne is skipped in the encoding
+            let ix = if index >= THRONE.to_index() {
+                index + 1
+            } else {
+                index
+            };
+            board.set(&Square::from_index(ix), space);
+        }
+        if !no_king && board.king_square().is_none() {
+            board.set(&THRONE, Space::King);
+        }
+        board
+    }
+
     /// Find which non-King pieces are captured when player `side` moves
     /// to square `dest`.
-    #[allow(clippy::collapsible_if)]
-    fn captures(&self, dest: &Square, side: &Role) -> Vec<Square> {
+    fn captures(&self, dest: &Square, side: &Role, rules: &Rules) -> Vec<Square> {
+        self.custodial_captures(dest, side, rules)
+            .into_iter()
+            .map(|capture| capture.square)
+            .collect()
+    }
+
+    /// Like [`Board::captures`], but with the squares flanking each capture
+    fn custodial_captures(&self, dest: &Square, side: &Role, rules: &Rules) -> Vec<Capture> {
         let mut captures = vec![];
-        // the throne can only be used in captures if not occupied by the king
-        let throne_capture = match self.find_the_king() {
+        // an unarmed king captures nothing by moving
+        if !rules.king_can_capture && self.get(dest) == Space::King {
+            return captures;
+        }
+        // the throne can be used in captures if the rules allow it to capture
+        // the opponent's pieces, which depends on whether the king is on it
+        let throne_hostility = match self.king_square() {
             None => unreachable!(),
-            Some(square) => square != THRONE,
+            Some(square) if square == THRONE => rules.occupied_throne_hostility,
+            Some(_) => rules.throne_hostility,
         };
+        let throne_capture = throne_hostility.is_hostile_to(&side.opposite());
         // the conditions necessary for a capture
         let is_capture = |sq: &Square| {
-            sq.is_exit() || self.get(sq).is_ally(side) || (*sq == THRONE && throne_capture)
+            let space = self.get(sq);
+            (sq.is_exit() && rules.corners_hostile)
+                || (space.is_ally(side) && (rules.king_can_capture || space != Space::King))
+                || (*sq == THRONE && throne_capture)
         };
 
-        if let Some(up_1) = dest.up() {
-            let space = self.get(&up_1);
-            if space != Space::King && space != Space::Empty && !space.is_ally(side) {
-                if let Some(up_2) = up_1.up() {
-                    if is_capture(&up_2) {
-                        captures.push(up_1);
-                    }
-                }
-            }
-        }
-
-        if let Some(left_1) = dest.left() {
-            let space = self.get(&left_1);
-            if space != Space::King && space != Space::Empty && !space.is_ally(side) {
-                if let Some(left_2) = left_1.left() {
-                    if is_capture(&left_2) {
-                        captures.push(left_1);
-                    }
-                }
-            }
-        }
-
-        if let Some(down_1) = dest.down() {
-            let space = self.get(&down_1);
-            if space != Space::King && space != Space::Empty && !space.is_ally(side) {
-                if let Some(down_2) = down_1.down() {
-                    if is_capture(&down_2) {
-                        captures.push(down_1);
-                    }
-                }
+        for step in [Square::up, Square::left, Square::down, Square::right] {
+            let Some(captured) = step(dest) else {
+                continue;
+            };
+            let space = self.get(&captured);
+            if space == Space::King || space == Space::Empty || space.is_ally(side) {
+                continue;
             }
-        }
-
-        if let Some(right_1) = dest.right() {
-            let space = self.get(&right_1);
-            if space != Space::King && space != Space::Empty && !space.is_ally(side) {
-                if let Some(right_2) = right_1.right() {
-                    if is_capture(&right_2) {
-                        captures.push(right_1);
-                    }
-                }
+            if let Some(flanker) = step(&captured)
+                && is_capture(&flanker)
+            {
+                captures.push(Capture {
+                    square: captured,
+                    mechanism: CaptureMechanism::Custodial {
+                        flankers: [*dest, flanker],
+                    },
+                });
             }
         }
         captures
@@ -316,9 +720,10 @@ impl Board {
         &self,
         dest: &Square,
         side: &Role,
+        min_len: usize,
         get_next: F,
         get_shield_pos: G,
-    ) -> Vec<Square>
+    ) -> Vec<Capture>
     where
         F: Fn(&Square) -> Option<Square>,
         G: Fn(&Square) -> Square,
@@ -328,9 +733,18 @@ impl Board {
         while let Some(sq) = next {
             let space = self.get(&sq);
 
-            // found a shield wall capture
+            // found a shield wall capture, if it is long enough
             if space.is_ally(side) || sq.is_restricted() {
-                break;
+                if maybe_captured.len() < min_len {
+                    break;
+                }
+                return maybe_captured
+                    .into_iter()
+                    .map(|square| Capture {
+                        square,
+                        mechanism: CaptureMechanism::ShieldWall { anchor: sq },
+                    })
+                    .collect();
             }
 
             // not sandwiched between pieces of same side, no capture
@@ -351,23 +765,39 @@ impl Board {
             }
             next = get_next(&sq);
         }
-        maybe_captured
+        vec![]
     }
 
     /// Determine if a shield wall capture occurs when player `side` moves a piece
     /// to square `dest`.
-    pub fn captures_shield_wall(&self, side: &Role, dest: &Square) -> Vec<Square> {
+    pub fn captures_shield_wall(&self, side: &Role, dest: &Square, rules: &Rules) -> Vec<Square> {
+        self.shield_wall_captures(side, dest, rules)
+            .into_iter()
+            .map(|capture| capture.square)
+            .collect()
+    }
+
+    /// Like [`Board::captures_shield_wall`], but with the square
+    /// anchoring each shield wall
+    fn shield_wall_captures(&self, side: &Role, dest: &Square, rules: &Rules) -> Vec<Capture> {
+        // shield walls only form along the edges, so don't allocate for
+        // the vast majority of moves
+        if !dest.is_edge() {
+            return vec![];
+        }
         let mut captures = Vec::with_capacity(22);
         if dest.x == 0 {
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.up(),
                 |sq| sq.right().unwrap(),
             ));
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.down(),
                 |sq| sq.right().unwrap(),
             ));
@@ -376,12 +806,14 @@ impl Board {
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.up(),
                 |sq| sq.left().unwrap(),
             ));
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.down(),
                 |sq| sq.left().unwrap(),
             ));
@@ -391,12 +823,14 @@ impl Board {
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.left(),
                 |sq| sq.down().unwrap(),
             ));
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.right(),
                 |sq| sq.down().unwrap(),
             ));
@@ -406,12 +840,14 @@ impl Board {
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.left(),
                 |sq| sq.up().unwrap(),
             ));
             captures.extend(self.shield_wall_aux(
                 dest,
                 side,
+                rules.shield_wall_min,
                 |sq| sq.right(),
                 |sq| sq.up().unwrap(),
             ));
@@ -419,34 +855,193 @@ impl Board {
         captures
     }
 
+    /// Find all empty squares that player `side` could move a piece to
+    /// which would capture at least one opposing piece.
+    pub fn threatened_squares(&self, side: &Role, rules: &Rules) -> SquareSet {
+        let mut threatened = SquareSet::default();
+        for from in self.occupied_squares(*side) {
+            let space = self.get(&from);
+            for step in [Square::up, Square::down, Square::left, Square::right] {
+                let mut next = step(&from);
+                while let Some(to) = next {
+                    if self.is_occupied(&to) {
+                        break;
+                    }
+                    next = step(&to);
+                    if space != Space::King && to.is_restricted() {
+                        continue;
+                    }
+                    let mut board = self.clone();
+                    board.set(&from, Space::Empty);
+                    board.set(&to, space);
+                    if !board.captures(&to, side, rules).is_empty()
+                        || !board.captures_shield_wall(side, &to, rules).is_empty()
+                    {
+                        threatened.add(to);
+                    }
+                }
+            }
+        }
+        threatened
+    }
+
     /// If the king is not captured, find the square on which he is located.
     pub fn find_the_king(&self) -> Option<Square> {
         self.spaces
             .iter()
             .enumerate()
             .find(|(_, s)| matches!(s, Space::King))
-            .map(|(ix, _)| Square {
-                x: ix.rem_euclid(11),
-                y: ix / 11,
+            .map(|(ix, _)| Square::from_index(ix))
+    }
+
+    /// Check if the king can slide straight into a corner
+    pub fn king_can_escape(&self) -> bool {
+        let Some(king) = self.king_square() else {
+            return false;
+        };
+        for (dx, dy) in [(0, -1), (-1, 0), (1, 0), (0, 1)] {
+            let mut next = king.step(dx, dy);
+            while let Some(sq) = next {
+                if self.get(&sq) != Space::Empty {
+                    break;
+                }
+                if sq.is_exit() {
+                    return true;
+                }
+                next = sq.step(dx, dy);
+            }
+        }
+        false
+    }
+
+    /// The moves sliding the king straight into a corner, no further
+    /// than `rules` let him move
+    pub fn king_escape_moves(&self, rules: &Rules) -> Vec<Play> {
+        let Some(king) = self.king_square() else {
+            return vec![];
+        };
+        let range = rules.king_move_range.map_or(usize::MAX, usize::from);
+        let mut escapes = vec![];
+        for (dx, dy) in [(0, -1), (-1, 0), (1, 0), (0, 1)] {
+            let mut next = king.step(dx, dy);
+            while let Some(sq) = next {
+                if self.get(&sq) != Space::Empty || king.manhattan_distance(&sq) > range {
+                    break;
+                }
+                if sq.is_exit() {
+                    escapes.push(Play {
+                        role: Role::Defender,
+                        from: king,
+                        to: sq,
+                    });
+                }
+                next = sq.step(dx, dy);
+            }
+        }
+        escapes
+    }
+
+    /// The distance from `sq` to the first attacker above, left of, right
+    /// of and below it, in that order. A direction is `None` if the edge
+    /// or another piece is reached before any attacker.
+    pub fn nearest_attacker_along_lines(&self, sq: Square) -> [Option<u8>; 4] {
+        [(0, -1), (-1, 0), (1, 0), (0, 1)].map(|(dx, dy)| {
+            let mut next = sq.step(dx, dy);
+            let mut distance = 1;
+            while let Some(sq) = next {
+                match self.get(&sq) {
+                    Space::Empty => {}
+                    Space::Occupied(Role::Attacker) => return Some(distance),
+                    _ => return None,
+                }
+                distance += 1;
+                next = sq.step(dx, dy);
+            }
+            None
+        })
+    }
+
+    /// Check if the attackers can capture the king with their next move.
+    /// This is the case if the king is surrounded on all sides but one and
+    /// an attacker can slide into the last.
+    pub fn king_can_be_captured(&self, rules: &Rules) -> bool {
+        let Some(king) = self.king_square() else {
+            return false;
+        };
+        if !Self::king_capturable_at(&king, rules) {
+            return false;
+        }
+        let mut gap = None;
+        for sq in king.orthogonal_neighbors() {
+            match self.get(&sq) {
+                Space::Occupied(Role::Attacker) => {}
+                Space::Empty if gap.is_none() && !sq.is_restricted() => gap = Some(sq),
+                _ => return false,
+            }
+        }
+        let Some(gap) = gap else {
+            return false;
+        };
+        [(0, -1), (-1, 0), (1, 0), (0, 1)]
+            .into_iter()
+            .any(|(dx, dy)| {
+                let mut next = gap.step(dx, dy);
+                while let Some(sq) = next {
+                    match self.get(&sq) {
+                        Space::Empty => next = sq.step(dx, dy),
+                        space => return space == Space::Occupied(Role::Attacker),
+                    }
+                }
+                false
             })
     }
 
-    /// Determine if the king is surrounded on all four sides by attackers
-    fn capture_the_king(&self) -> bool {
-        match self.find_the_king() {
-            Some(king) => {
-                for sq in [king.up(), king.down(), king.left(), king.right()] {
-                    if let Some(sq) = sq.as_ref() {
-                        let space = self.get(sq);
-                        if space.is_ally(&Role::Defender) || space == Space::Empty {
-                            return false;
-                        }
-                    } else {
-                        return false;
+    /// The moves of player `role` after which the opponent cannot win
+    /// straight away under `rules`. Only the king escaping or being
+    /// captured are considered and repetitions are ignored. If there are
+    /// no such moves, the position is lost. The moves are checked as they
+    /// are taken, in no particular order, so callers needing only a few can
+    /// stop early.
+    pub fn forced_moves(&self, role: &Role, rules: &Rules) -> impl Iterator<Item = Play> {
+        self.moves(role)
+            .map(|(from, to)| Play {
+                role: *role,
+                from,
+                to,
+            })
+            .filter(move |play| {
+                let Ok((board, _)) = self.move_piece(play, rules) else {
+                    return false;
+                };
+                match role {
+                    Role::Attacker => {
+                        board.capture_the_king(rules) || board.king_escape_moves(rules).is_empty()
+                    }
+                    Role::Defender => {
+                        board.king_square().is_some_and(|king| king.is_exit())
+                            || !board.king_can_be_captured(rules)
                     }
                 }
-                true
-            }
+            })
+    }
+
+    /// Determine if the king is surrounded on all four sides by attackers,
+    /// or on his three other sides if he is on the edge and the rules allow it
+    pub fn capture_the_king(&self, rules: &Rules) -> bool {
+        match self.king_square() {
+            Some(king) if Self::king_capturable_at(&king, rules) => king
+                .orthogonal_neighbors()
+                .all(|sq| self.get(&sq) == Space::Occupied(Role::Attacker)),
+            _ => false,
+        }
+    }
+
+    /// Check if the king may be captured on a square at all. Unless the
+    /// rules say otherwise, he can't be captured against the edge.
+    fn king_capturable_at(king: &Square, rules: &Rules) -> bool {
+        match king.orthogonal_neighbors().count() {
+            4 => true,
+            3 => rules.edge_king_capture,
             _ => false,
         }
     }
@@ -489,52 +1084,122 @@ impl Board {
         true
     }
 
+    /// All squares reachable from `start` by repeatedly stepping to a
+    /// neighboring square whose space is `passable`. The start is always
+    /// included.
+    pub fn reachable_region(&self, start: Square, passable: impl Fn(Space) -> bool) -> SquareSet {
+        self.flood_fill(start, passable, |_| false)
+    }
+
+    /// Flood fill from `start` as in [`Board::reachable_region`], stopping
+    /// as soon as a square reached satisfies `found`. Returns the squares
+    /// reached so far.
+    fn flood_fill(
+        &self,
+        start: Square,
+        passable: impl Fn(Space) -> bool,
+        mut found: impl FnMut(Square) -> bool,
+    ) -> SquareSet {
+        let mut region = SquareSet::default();
+        region.add(start);
+        if found(start) {
+            return region;
+        }
+        let mut queue = VecDeque::from([start]);
+        while let Some(sq) = queue.pop_front() {
+            for neighbor in sq.orthogonal_neighbors() {
+                if !region.contains(&neighbor) && passable(self.get(&neighbor)) {
+                    region.add(neighbor);
+                    if found(neighbor) {
+                        return region;
+                    }
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        region
+    }
+
     /// See if we can reach a defender from any corner by traversing through empty squares.
     /// If not, the attackers win.
     ///
     /// N.B. There are rare cases where a corner is blocked with an attacker sandwiched
     /// inside. This algorithm will not detect this.
-    fn flood_fill_attackers_win(&self) -> bool {
+    pub fn flood_fill_attackers_win(&self) -> bool {
         for corner in RESTRICTED_SQUARES.into_iter().filter(|sq| *sq != THRONE) {
             if self.special_corner_block(&corner) {
                 continue;
             }
-            // Do a breadth-first search from the corner
-            let mut queue = VecDeque::from([corner]);
-            let mut visited = SquareSet::default();
-            visited.add(corner);
-            while let Some(sq) = queue.pop_front() {
-                for neighbor in [sq.up(), sq.down(), sq.left(), sq.right()]
-                    .into_iter()
-                    .flatten()
-                {
-                    match self.get(&neighbor) {
-                        // if we can reach a defender, the attackers have not won
-                        Space::Occupied(Role::Defender) | Space::King => return false,
-                        // we cannot pass through attackers unless we are at the corner
-                        Space::Occupied(Role::Attacker) => {
-                            if sq == corner && !visited.contains(&neighbor) {
-                                queue.push_back(neighbor);
-                                visited.add(neighbor);
-                            }
-                        }
-                        // traverse through this space
-                        Space::Empty => {
-                            if !visited.contains(&neighbor) {
-                                queue.push_back(neighbor);
-                                visited.add(neighbor);
-                            }
-                        }
-                    }
+            // we cannot pass through attackers unless we are at the corner
+            let starts = std::iter::once(corner).chain(
+                corner
+                    .orthogonal_neighbors()
+                    .filter(|sq| self.get(sq) == Space::Occupied(Role::Attacker)),
+            );
+            for start in starts {
+                // if we can reach a defender, the attackers have not won,
+                // so stop at the first one
+                let mut reached = false;
+                self.flood_fill(
+                    start,
+                    |space| space != Space::Occupied(Role::Attacker),
+                    |sq| {
+                        reached = self.get(&sq).is_ally(&Role::Defender);
+                        reached
+                    },
+                );
+                if reached {
+                    return false;
                 }
             }
         }
         true
     }
 
+    /// Determine if the king is on the edge of the board inside an enclosure
+    /// of defenders that the attackers can neither enter nor break, while still
+    /// having room to move.
+    ///
+    /// We flood fill through empty squares from the king. If this reaches an
+    /// attacker or a restricted square, the enclosure is not sealed. Otherwise,
+    /// every defender in the wall must be impossible to capture, i.e. along both
+    /// axes it must have a neighbor the attackers cannot occupy.
+    pub fn is_exit_fort(&self) -> bool {
+        let Some(king) = self.king_square() else {
+            return false;
+        };
+        if !king.is_edge() {
+            return false;
+        }
+        let inside = self.reachable_region(king, |space| space == Space::Empty);
+        let mut wall = SquareSet::default();
+        for sq in inside.keys() {
+            if sq != king && sq.is_restricted() {
+                return false;
+            }
+            for neighbor in sq.orthogonal_neighbors() {
+                match self.get(&neighbor) {
+                    Space::Occupied(Role::Defender) => wall.add(neighbor),
+                    Space::Occupied(Role::Attacker) => return false,
+                    Space::Empty | Space::King => {}
+                }
+            }
+        }
+        // the king must have a free move
+        if inside.len() == 1 {
+            return false;
+        }
+        let safe = |sq: Option<Square>| match sq {
+            None => true,
+            Some(sq) => inside.contains(&sq) || wall.contains(&sq),
+        };
+        wall.keys()
+            .all(|sq| (safe(sq.up()) || safe(sq.down())) && (safe(sq.left()) || safe(sq.right())))
+    }
+
     #[must_use]
     pub fn get(&self, square: &Square) -> Space {
-        self.spaces[square.y * 11 + square.x]
+        self.spaces[square.to_index()]
     }
 
     pub fn is_occupied(&self, square: &Square) -> bool {
@@ -546,6 +1211,18 @@ impl Board {
         }
     }
 
+    /// For each attacker next to a corner which is vulnerable
+    /// to capture, take off `penalty`.
+    pub fn attacker_corner_penalties(&self, penalty: f64) -> f64 {
+        CORNER_GUARDS
+            .iter()
+            .filter(|(attacker, beyond)| {
+                self.get(attacker) == Space::Occupied(Role::Attacker) && !self.is_occupied(beyond)
+            })
+            .map(|_| -penalty)
+            .sum()
+    }
+
     /// Play a move. Errors if the play is invalid or the game is already over.
     /// Stores the board in the history for checking repeated positions and enforcing
     /// the one hundred move limit.
@@ -562,116 +1239,368 @@ impl Board {
         Ok((captures, status))
     }
 
+    /// Play a sequence of moves from an ongoing game with `turn` to move,
+    /// sharing one tracker of previous boards. The players must alternate.
+    /// Returns the board before each play along with the final status, or
+    /// the index of the first illegal play along with why it is illegal.
+    /// In that case, the board is left as it was before that play.
+    pub fn apply_move_sequence(
+        &mut self,
+        plays: &[Play],
+        turn: Role,
+        tracker: &mut PositionsTracker,
+    ) -> Result<(Vec<Board>, Status), (usize, PlayError)> {
+        let mut boards = vec![];
+        let mut status = Status::Ongoing;
+        let mut turn = turn;
+        for (ix, play) in plays.iter().enumerate() {
+            if play.role != turn {
+                let wrong_turn = match self.get(&play.from) {
+                    Space::Empty => PlayError::EmptySquare(play.from),
+                    Space::King => PlayError::WrongTurn {
+                        square: play.from,
+                        found: Role::Defender,
+                    },
+                    Space::Occupied(found) => PlayError::WrongTurn {
+                        square: play.from,
+                        found,
+                    },
+                };
+                return Err((ix, wrong_turn));
+            }
+            let before = self.clone();
+            (_, status) = self.play(play, &status, tracker).map_err(|e| (ix, e))?;
+            boards.push(before);
+            turn = turn.opposite();
+        }
+        Ok((boards, status))
+    }
+
+    /// The actual game logic using the default rules. See
+    /// [`Board::play_internal_with_rules`].
+    pub fn play_internal(
+        &self,
+        play: &Play,
+        status: &Status,
+        previous_boards: &PositionsTracker,
+    ) -> Result<(Board, Vec<Square>, Status), PlayError> {
+        self.play_internal_with_rules(play, status, previous_boards, &Rules::default())
+    }
+
     /// The actual game logic. Checks if a move is valid, computes
     /// captures, and checks if the game is won.
     ///
     /// Errors on an illegal move
-    pub fn play_internal(
+    pub fn play_internal_with_rules(
         &self,
         play: &Play,
         status: &Status,
         previous_boards: &PositionsTracker,
+        rules: &Rules,
     ) -> Result<(Board, Vec<Square>, Status), PlayError> {
         if *status != Status::Ongoing {
             return Err(PlayError::GameFinished);
         }
-        play.valid()?;
+        let (board, captures) = self.move_piece(play, rules)?;
 
-        let space_from = self.get(&play.from);
-        if !space_from.is_ally(&play.role) {
-            return Err(PlayError::WrongTurn);
+        if EXIT_SQUARES.contains(&play.to) {
+            return Ok((board, captures, Status::DefendersWin));
         }
 
-        let x_diff = play.from.x as i32 - play.to.x as i32;
-        let y_diff = play.from.y as i32 - play.to.y as i32;
+        if rules.exit_forts && board.is_exit_fort() {
+            return Ok((board, captures, Status::DefendersWin));
+        }
 
-        if x_diff != 0 {
-            let x_diff_sign = x_diff.signum();
-            for x_diff in 1..=x_diff.abs() {
-                let sq = Square {
-                    x: (play.from.x as i32 - (x_diff * x_diff_sign)) as usize,
-                    y: play.from.y,
-                };
+        if board.capture_the_king(rules) {
+            return Ok((board, captures, Status::AttackersWin));
+        }
 
-                let space = self.get(&sq);
-                if space != Space::Empty {
-                    return Err(PlayError::MoveThroughPiece);
-                }
-            }
-        } else {
-            let y_diff_sign = y_diff.signum();
-            for y_diff in 1..=y_diff.abs() {
-                let sq = Square {
-                    x: play.from.x,
-                    y: (play.from.y as i32 - (y_diff * y_diff_sign)) as usize,
-                };
-                let space = self.get(&sq);
-                if space != Space::Empty {
-                    return Err(PlayError::MoveThroughPiece);
-                }
-            }
+        if let PositionsTracker::Previous(prev) = previous_boards
+            && prev.0.contains_key(&board)
+            && play.role == Role::Defender
+        {
+            return Err(PlayError::RepeatedPosition);
         }
 
-        if space_from != Space::King && RESTRICTED_SQUARES.contains(&play.to) {
-            return Err(PlayError::RestrictedSquare);
+        if board.flood_fill_attackers_win() {
+            return Ok((board, captures, Status::AttackersWin));
         }
 
-        let mut board = self.clone();
-        board.set(&play.from, Space::Empty);
-        board.set(&play.to, space_from);
+        if !board.a_legal_move_exists(&play.role.opposite()) {
+            return Ok((board, captures, play.role.victory()));
+        }
 
-        let mut captures = Vec::new();
-        captures.extend(board.captures(&play.to, &play.role));
-        captures.extend(board.captures_shield_wall(&play.role, &play.to));
-        for capture in &captures {
-            board.set(capture, Space::Empty);
+        if previous_boards.len() >= MOVE_LIMIT {
+            return Ok((board, captures, Status::Draw));
         }
 
-        if EXIT_SQUARES.contains(&play.to) {
-            return Ok((board, captures, Status::DefendersWin));
+        Ok((board, captures, Status::Ongoing))
+    }
+
+    /// Check if [`Board::play_internal`] would accept a move. This still
+    /// runs the full validation, including that defenders may not repeat
+    /// a position, but doesn't work out the status of the game afterwards.
+    #[cfg(test)]
+    pub fn is_legal_move(
+        &self,
+        play: &Play,
+        status: &Status,
+        previous_boards: &PositionsTracker,
+    ) -> bool {
+        if *status != Status::Ongoing {
+            return false;
         }
+        let rules = Rules::default();
+        let Ok((board, _)) = self.move_piece(play, &rules) else {
+            return false;
+        };
+        let PositionsTracker::Previous(prev) = previous_boards else {
+            return true;
+        };
+        // winning moves are checked before repetitions
+        play.role == Role::Attacker
+            || !prev.0.contains_key(&board)
+            || EXIT_SQUARES.contains(&play.to)
+            || (rules.exit_forts && board.is_exit_fort())
+            || board.capture_the_king(&rules)
+    }
 
-        if board.capture_the_king() {
-            return Ok((board, captures, Status::AttackersWin));
+    /// Work out the move that turned `before` into `after` and the
+    /// pieces it captured. The moving piece is the only one to arrive on
+    /// an empty square, and it is replayed under `rules` to check that it
+    /// turns `before` into exactly `after`. Returns `None` if the boards
+    /// are not one legal move apart.
+    pub fn diff(before: &Board, after: &Board, rules: &Rules) -> Option<(Play, Vec<Square>)> {
+        let mut arrived = Square::iter()
+            .filter(|sq| before.get(sq) == Space::Empty && after.get(sq) != Space::Empty);
+        let to = arrived.next()?;
+        if arrived.next().is_some() {
+            return None;
+        }
+        let space = after.get(&to);
+        let mut left =
+            Square::iter().filter(|sq| before.get(sq) == space && after.get(sq) == Space::Empty);
+        let from = left.next()?;
+        if left.next().is_some() {
+            return None;
         }
+        let role = match space {
+            Space::Occupied(role) => role,
+            _ => Role::Defender,
+        };
+        let play = Play { role, from, to };
+        let (board, captures) = before.move_piece(&play, rules).ok()?;
+        (board == *after).then_some((play, captures))
+    }
 
-        if let PositionsTracker::Previous(prev) = previous_boards {
-            if prev.0.contains(&board) && play.role == Role::Defender {
-                return Err(PlayError::RepeatedPosition);
+    /// Find the pieces a move would capture without playing it. Errors
+    /// if the move is illegal.
+    pub fn captures_for_move(&self, play: &Play) -> Result<Vec<Square>, PlayError> {
+        self.move_piece(play, &Default::default())
+            .map(|(_, captures)| captures)
+    }
+
+    /// The move capturing the most pieces for player `role` this turn,
+    /// along with how many it captures. Ties go to the first move in
+    /// [`Play::sort_key`] order. Returns `None` if no move captures.
+    #[cfg(test)]
+    pub fn best_immediate_capture(&self, role: &Role) -> Option<(Play, usize)> {
+        let mut best: Option<(Play, usize)> = None;
+        for play in self.legal_moves_sorted(role) {
+            let Ok(captures) = self.captures_for_move(&play) else {
+                continue;
+            };
+            if !captures.is_empty()
+                && best
+                    .as_ref()
+                    .is_none_or(|(_, count)| captures.len() > *count)
+            {
+                best = Some((play, captures.len()));
             }
         }
+        best
+    }
 
-        if board.flood_fill_attackers_win() {
-            return Ok((board, captures, Status::AttackersWin));
+    /// Check that a move is legal and return the resulting board along
+    /// with the captured pieces. Does not check if the game is over.
+    fn move_piece(&self, play: &Play, rules: &Rules) -> Result<(Board, Vec<Square>), PlayError> {
+        let mut board = self.slide_piece(play, rules)?;
+        let mut captures = board.captures(&play.to, &play.role, rules);
+        for capture in board.captures_shield_wall(&play.role, &play.to, rules) {
+            if !captures.contains(&capture) {
+                captures.push(capture);
+            }
+        }
+        for capture in &captures {
+            board.set(capture, Space::Empty);
         }
+        Ok((board, captures))
+    }
 
-        if !board.a_legal_move_exists(&play.role.opposite()) {
-            return Ok((board, captures, play.role.victory()));
+    /// Describe every capture a move makes under the default rules and
+    /// what caused it. See [`Board::capture_report_with_rules`].
+    #[cfg(test)]
+    pub fn capture_report(&self, play: &Play) -> Result<CaptureReport, PlayError> {
+        self.capture_report_with_rules(play, &Rules::default())
+    }
+
+    /// Describe every capture a move makes and what caused it, for
+    /// debugging. Errors on an illegal move.
+    pub fn capture_report_with_rules(
+        &self,
+        play: &Play,
+        rules: &Rules,
+    ) -> Result<CaptureReport, PlayError> {
+        let board = self.slide_piece(play, rules)?;
+        let mut captures = board.custodial_captures(&play.to, &play.role, rules);
+        captures.extend(board.shield_wall_captures(&play.role, &play.to, rules));
+        Ok(CaptureReport { captures })
+    }
+
+    /// Check that a move is legal and return the board with the piece moved,
+    /// before any captures are made
+    fn slide_piece(&self, play: &Play, rules: &Rules) -> Result<Board, PlayError> {
+        play.valid()?;
+
+        let space_from = self.get(&play.from);
+        match space_from {
+            Space::Empty => return Err(PlayError::EmptySquare(play.from)),
+            Space::Occupied(found) if found != play.role => {
+                return Err(PlayError::WrongTurn {
+                    square: play.from,
+                    found,
+                });
+            }
+            Space::King if play.role != Role::Defender => {
+                return Err(PlayError::WrongTurn {
+                    square: play.from,
+                    found: Role::Defender,
+                });
+            }
+            _ => {}
         }
 
-        if previous_boards.len() >= 100 {
-            return Ok((board, captures, Status::Draw));
+        let Some(line) = play.from.line_to(&play.to) else {
+            return Err(PlayError::StraightLine);
+        };
+        for sq in line.iter().chain([&play.to]) {
+            if self.get(sq) != Space::Empty {
+                return Err(PlayError::MoveThroughPiece(*sq));
+            }
         }
 
-        Ok((board, captures, Status::Ongoing))
+        if space_from != Space::King && RESTRICTED_SQUARES.contains(&play.to) {
+            return Err(PlayError::RestrictedSquare);
+        }
+
+        if space_from == Space::King
+            && let Some(range) = rules.king_move_range
+            && play.from.manhattan_distance(&play.to) > range as usize
+        {
+            return Err(PlayError::KingMoveTooFar(range));
+        }
+
+        let mut board = self.clone();
+        board.set(&play.from, Space::Empty);
+        board.set(&play.to, space_from);
+        Ok(board)
     }
 
     pub fn set(&mut self, square: &Square, space: Space) {
-        self.spaces[square.y * 11 + square.x] = space;
+        let old = std::mem::replace(&mut self.spaces[square.to_index()], space);
+        self.zobrist ^= zobrist::key(square, old) ^ zobrist::key(square, space);
+        match old {
+            Space::Occupied(Role::Attacker) => self.material.0 -= 1,
+            Space::Occupied(Role::Defender) => self.material.1 -= 1,
+            Space::King => {
+                self.material.1 -= 1;
+                self.king = None;
+            }
+            Space::Empty => {}
+        }
+        match space {
+            Space::Occupied(Role::Attacker) => self.material.0 += 1,
+            Space::Occupied(Role::Defender) => self.material.1 += 1,
+            Space::King => {
+                self.material.1 += 1;
+                self.king = Some(*square);
+            }
+            Space::Empty => {}
+        }
+    }
+
+    /// The square of the king, if he is on the board. Unlike
+    /// [`Board::find_the_king`], this does not scan the board.
+    pub fn king_square(&self) -> Option<Square> {
+        debug_assert_eq!(self.king, self.find_the_king());
+        self.king
     }
 
     pub fn attackers(&self) -> u8 {
-        self.spaces
-            .iter()
-            .filter(|sp| matches!(sp, Space::Occupied(Role::Attacker)))
-            .count() as u8
+        self.material().0
     }
 
     pub fn defenders(&self) -> u8 {
+        self.material().1
+    }
+
+    /// The number of attackers and defenders (including the king) on the board
+    pub fn material(&self) -> (u8, u8) {
+        debug_assert_eq!(self.material, self.count_material());
+        self.material
+    }
+
+    /// The Zobrist hash of the board. This is updated as pieces are
+    /// moved, so it is far cheaper than hashing the whole board.
+    pub fn zobrist(&self) -> u64 {
+        debug_assert_eq!(self.zobrist, self.compute_zobrist());
+        self.zobrist
+    }
+
+    fn compute_zobrist(&self) -> u64 {
+        Square::iter().fold(0, |hash, sq| hash ^ zobrist::key(&sq, self.get(&sq)))
+    }
+
+    /// The smallest Zobrist hash of any of the symmetries of the board.
+    /// Symmetric boards share this value.
+    pub fn canonical_zobrist(&self) -> u64 {
+        let pieces = Square::iter()
+            .map(|sq| (sq, self.get(&sq)))
+            .filter(|(_, sp)| *sp != Space::Empty)
+            .collect::<Vec<_>>();
+        D8.iter()
+            .map(|d8| {
+                pieces.iter().fold(0, |hash, (sq, sp)| {
+                    hash ^ zobrist::key(&d8.apply_square(sq), *sp)
+                })
+            })
+            .min()
+            .unwrap()
+    }
+
+    fn count_material(&self) -> (u8, u8) {
         self.spaces
             .iter()
-            .filter(|sp| matches!(sp, Space::Occupied(Role::Defender) | Space::King))
-            .count() as u8
+            .fold((0, 0), |(attackers, defenders), sp| match sp {
+                Space::Occupied(Role::Attacker) => (attackers + 1, defenders),
+                Space::Occupied(Role::Defender) | Space::King => (attackers, defenders + 1),
+                Space::Empty => (attackers, defenders),
+            })
+    }
+
+    /// Classify the phase of the game by the number of pieces remaining
+    /// relative to the starting position
+    pub fn phase(&self) -> GamePhase {
+        let (attackers, defenders) = self.material();
+        let remaining = attackers as u32 + defenders as u32;
+        let start = STARTING_MATERIAL.0 as u32 + STARTING_MATERIAL.1 as u32;
+        if 10 * remaining >= 9 * start {
+            GamePhase::Opening
+        } else if 10 * remaining <= 4 * start {
+            GamePhase::Endgame
+        } else {
+            GamePhase::Midgame
+        }
     }
 }
 
@@ -679,6 +1608,9 @@ impl Board {
 mod test_board {
     use super::*;
     use crate::game::PreviousBoards;
+    use crate::game::rules::ThroneHostility;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
 
     /// Test we can detect if a side still has a legal move
     #[test]
@@ -720,6 +1652,47 @@ mod test_board {
         assert!(!board.a_legal_move_exists(&Role::Attacker));
     }
 
+    /// Test that we count material and classify the phase of the game by it
+    #[test]
+    fn test_phase() {
+        let board = Board::default();
+        assert_eq!(board.material(), STARTING_MATERIAL);
+        assert_eq!(board.material(), (board.attackers(), board.defenders()));
+        assert_eq!(board.phase(), GamePhase::Opening);
+        let board = [
+            "...OO.OO...",
+            ".....O.....",
+            "...........",
+            "O....X....O",
+            "O...X.X...O",
+            "O..X.K.X..O",
+            "....X.X...O",
+            "O....X....O",
+            "...........",
+            ".....O.....",
+            "...O.O.O...",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(board.material(), (18, 9));
+        assert_eq!(board.phase(), GamePhase::Midgame);
+        let board = [
+            "...O.......",
+            "...........",
+            "...........",
+            "O.........O",
+            "......X....",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            ".....O.....",
+            "...O...O...",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(board.material(), (6, 2));
+        assert_eq!(board.phase(), GamePhase::Endgame);
+    }
+
     /// Test that captured pieces are correctly computed
     #[test]
     fn test_captures() {
@@ -739,7 +1712,11 @@ mod test_board {
         ];
         let board = Board::try_from(board).expect("Test failed");
         assert_eq!(
-            board.captures(&Square { x: 8, y: 10 }, &Role::Attacker),
+            board.captures(
+                &Square { x: 8, y: 10 },
+                &Role::Attacker,
+                &Default::default()
+            ),
             vec![Square { x: 7, y: 10 }, Square { x: 9, y: 10 }]
         );
         // check that we don't compute king captures with this method
@@ -759,7 +1736,7 @@ mod test_board {
         ];
         let board = Board::try_from(board).expect("Test failed");
         assert_eq!(
-            board.captures(&Square { x: 5, y: 8 }, &Role::Attacker),
+            board.captures(&Square { x: 5, y: 8 }, &Role::Attacker, &Default::default()),
             vec![Square { x: 5, y: 7 }, Square { x: 4, y: 8 }]
         );
         // check we don't capture allies
@@ -778,88 +1755,1541 @@ mod test_board {
         ];
         let board = Board::try_from(board).expect("Test failed");
         assert_eq!(
-            board.captures(&Square { x: 5, y: 8 }, &Role::Attacker),
+            board.captures(&Square { x: 5, y: 8 }, &Role::Attacker, &Default::default()),
             vec![Square { x: 4, y: 8 }]
         );
     }
 
-    /// Check that we correctly identify shield wall captures
+    /// Test that corners only take part in captures if the rules allow it
     #[test]
-    fn test_shield_walls() {
-        // valid shield wall with two capture sets,
-        // one of which includes the king, and using
-        // a corner as a flanking piece
+    fn test_corner_hostility() {
         let board = [
             "...........",
-            "O..........",
-            "XO.........",
-            "XO.........",
-            "XO.........",
-            "XO.........",
-            "O..........",
-            "XO.........",
-            "KO.........",
-            "XO.........",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "......OXOX.",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let rules = Rules::default();
+        assert!(rules.corners_hostile);
+        assert_eq!(
+            board.captures(&Square { x: 8, y: 10 }, &Role::Attacker, &rules),
+            vec![Square { x: 7, y: 10 }, Square { x: 9, y: 10 }]
+        );
+        let rules = Rules {
+            corners_hostile: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            board.captures(&Square { x: 8, y: 10 }, &Role::Attacker, &rules),
+            vec![Square { x: 7, y: 10 }]
+        );
+    }
+
+    /// Test that the king may be limited to moving a few squares at a time
+    #[test]
+    fn test_king_move_range() {
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "......O....",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let previous_boards = PositionsTracker::Counter(0);
+        let king = |to: Square| Play {
+            role: Role::Defender,
+            from: Square { x: 5, y: 4 },
+            to,
+        };
+        let play = |play: &Play, rules: &Rules| {
+            board.play_internal_with_rules(play, &Status::Ongoing, &previous_boards, rules)
+        };
+        let far = king(Square { x: 5, y: 1 });
+        let near = king(Square { x: 6, y: 4 });
+        let rules = Rules::default();
+        assert!(play(&far, &rules).is_ok());
+        assert!(play(&near, &rules).is_ok());
+
+        let rules = Rules {
+            king_move_range: Some(1),
+            ..Default::default()
+        };
+        let err = play(&far, &rules).unwrap_err();
+        assert!(matches!(err, PlayError::KingMoveTooFar(1)));
+        assert_eq!(
+            err.to_string(),
+            "The king may move at most 1 squares at a time"
+        );
+        assert!(play(&near, &rules).is_ok());
+        // other pieces are unaffected
+        let attacker = Play {
+            role: Role::Attacker,
+            from: Square { x: 6, y: 10 },
+            to: Square { x: 6, y: 5 },
+        };
+        assert!(play(&attacker, &rules).is_ok());
+    }
+
+    /// Test the distances to the attackers in line with a square
+    #[test]
+    fn test_nearest_attacker_along_lines() {
+        let board = [
+            "...........",
+            "...........",
+            ".....O.....",
+            "...........",
+            "...........",
+            ".O...K.X.O.",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
             "...........",
         ];
         let board = Board::try_from(board).expect("Test failed");
-        let captures = board.captures_shield_wall(&Role::Attacker, &Square { x: 0, y: 6 });
+        let king = Square { x: 5, y: 5 };
+        // flanked above and to the left, blocked to the right and open below
+        assert_eq!(
+            board.nearest_attacker_along_lines(king),
+            [Some(3), Some(4), None, None]
+        );
+        assert_eq!(
+            board.nearest_attacker_along_lines(Square { x: 8, y: 5 }),
+            [None, None, Some(1), None]
+        );
+    }
+
+    /// Test that an unarmed king takes no part in capturing attackers
+    #[test]
+    fn test_unarmed_king() {
+        // a defender moves to flank an attacker against a standing king
+        let anvil = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".KO.X......",
+            "...........",
+        ];
+        let anvil = Board::try_from(anvil).expect("Test failed");
+        let defender = Play {
+            role: Role::Defender,
+            from: Square { x: 4, y: 9 },
+            to: Square { x: 3, y: 9 },
+        };
+        // the king moves to flank an attacker against a defender
+        let hammer = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "..K........",
+            "...........",
+            "...........",
+            "...........",
+            "...OX......",
+            "...........",
+        ];
+        let hammer = Board::try_from(hammer).expect("Test failed");
+        let king = Play {
+            role: Role::Defender,
+            from: Square { x: 2, y: 5 },
+            to: Square { x: 2, y: 9 },
+        };
+        let captured = |board: &Board, play: &Play, rules: &Rules| {
+            board.move_piece(play, rules).expect("Test failed").1
+        };
+
+        let rules = Rules::default();
+        assert!(rules.king_can_capture);
+        assert_eq!(
+            captured(&anvil, &defender, &rules),
+            vec![Square { x: 2, y: 9 }]
+        );
+        assert_eq!(
+            captured(&hammer, &king, &rules),
+            vec![Square { x: 3, y: 9 }]
+        );
+
+        let rules = Rules {
+            king_can_capture: false,
+            ..Default::default()
+        };
+        assert!(captured(&anvil, &defender, &rules).is_empty());
+        assert!(captured(&hammer, &king, &rules).is_empty());
+    }
+
+    /// Test the different rules for captures against the empty throne
+    #[test]
+    fn test_throne_hostility() {
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "..K........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        // a piece is captured between the empty throne and square F4
+        let defender_captured = |rules: &Rules| {
+            let mut board = board.clone();
+            board.set(&Square { x: 5, y: 6 }, Space::Occupied(Role::Defender));
+            board.set(&Square { x: 5, y: 7 }, Space::Occupied(Role::Attacker));
+            board.captures(&Square { x: 5, y: 7 }, &Role::Attacker, rules)
+                == vec![Square { x: 5, y: 6 }]
+        };
+        let attacker_captured = |rules: &Rules| {
+            let mut board = board.clone();
+            board.set(&Square { x: 5, y: 6 }, Space::Occupied(Role::Attacker));
+            board.set(&Square { x: 5, y: 7 }, Space::Occupied(Role::Defender));
+            board.captures(&Square { x: 5, y: 7 }, &Role::Defender, rules)
+                == vec![Square { x: 5, y: 6 }]
+        };
+
+        let rules = Rules::default();
+        assert!(defender_captured(&rules));
+        assert!(attacker_captured(&rules));
+        let rules = Rules {
+            throne_hostility: ThroneHostility::Defenders,
+            ..Default::default()
+        };
+        assert!(defender_captured(&rules));
+        assert!(!attacker_captured(&rules));
+        let rules = Rules {
+            throne_hostility: ThroneHostility::Neither,
+            ..Default::default()
+        };
+        assert!(!defender_captured(&rules));
+        assert!(!attacker_captured(&rules));
+    }
+
+    /// Test that the empty throne and the throne with the king on it
+    /// take part in captures under separate rules
+    #[test]
+    fn test_occupied_throne_hostility() {
+        let empty = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "..K........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let mut occupied = empty.clone();
+        occupied.set(&Square { x: 2, y: 9 }, Space::Empty);
+        occupied.set(&THRONE, Space::King);
+        // a piece of `role` is captured between the throne and square F4
+        let captured = |board: &Board, role: Role, rules: &Rules| {
+            let mut board = board.clone();
+            board.set(&Square { x: 5, y: 6 }, Space::Occupied(role));
+            board.set(&Square { x: 5, y: 7 }, Space::Occupied(role.opposite()));
+            board.captures(&Square { x: 5, y: 7 }, &role.opposite(), rules)
+                == vec![Square { x: 5, y: 6 }]
+        };
+        let hostilities = [
+            ThroneHostility::All,
+            ThroneHostility::Attackers,
+            ThroneHostility::Defenders,
+            ThroneHostility::Neither,
+        ];
+        for throne_hostility in hostilities {
+            for occupied_throne_hostility in hostilities {
+                for king_can_capture in [true, false] {
+                    let rules = Rules {
+                        throne_hostility,
+                        occupied_throne_hostility,
+                        king_can_capture,
+                        ..Default::default()
+                    };
+                    for role in [Role::Attacker, Role::Defender] {
+                        assert_eq!(
+                            captured(&empty, role, &rules),
+                            throne_hostility.is_hostile_to(&role)
+                        );
+                        // an armed king captures attackers whatever the throne
+                        let by_king = king_can_capture && role == Role::Attacker;
+                        assert_eq!(
+                            captured(&occupied, role, &rules),
+                            by_king || occupied_throne_hostility.is_hostile_to(&role)
+                        );
+                    }
+                }
+            }
+        }
+        // by default, only the empty throne is hostile
+        let rules = Rules {
+            king_can_capture: false,
+            ..Default::default()
+        };
+        assert!(captured(&empty, Role::Attacker, &rules));
+        assert!(captured(&empty, Role::Defender, &rules));
+        assert!(!captured(&occupied, Role::Attacker, &rules));
+        assert!(!captured(&occupied, Role::Defender, &rules));
+    }
+
+    /// Check that we recognize exit forts and only end the game with
+    /// them if the rules allow it
+    #[test]
+    fn test_exit_fort() {
+        let fort = [
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "....OOO....",
+            "....XXX....",
+            "...X.K.X...",
+        ];
+        let fort = Board::try_from(fort).expect("Test failed");
+        assert!(fort.is_exit_fort());
+
+        // the attackers can get inside through a gap
+        let gap = [
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "....OOO....",
+            "....X.X....",
+            "...X.K.X...",
+        ];
+        let gap = Board::try_from(gap).expect("Test failed");
+        assert!(!gap.is_exit_fort());
+
+        // the enclosure is sealed, but the defender at F3
+        // can be captured
+        let breakable = [
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".....X.....",
+            "...........",
+            "....X.X....",
+            "...X.K.X...",
+        ];
+        let breakable = Board::try_from(breakable).expect("Test failed");
+        assert!(!breakable.is_exit_fort());
+
+        // the king cannot move
+        let stuck = [
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "....XXX....",
+            "....XKX....",
+        ];
+        let stuck = Board::try_from(stuck).expect("Test failed");
+        assert!(!stuck.is_exit_fort());
+
+        // close the fort with a move
+        let mut board = fort.clone();
+        board.set(&Square { x: 7, y: 10 }, Space::Empty);
+        board.set(&Square { x: 7, y: 7 }, Space::Occupied(Role::Defender));
+        let play = Play {
+            role: Role::Defender,
+            from: Square { x: 7, y: 7 },
+            to: Square { x: 7, y: 10 },
+        };
+        let previous_boards = PositionsTracker::Previous(Default::default());
+        let (_, _, status) = board
+            .play_internal(&play, &Status::Ongoing, &previous_boards)
+            .expect("Test failed");
+        assert_eq!(status, Status::Ongoing);
+        let rules = Rules {
+            exit_forts: true,
+            ..Default::default()
+        };
+        let (_, _, status) = board
+            .play_internal_with_rules(&play, &Status::Ongoing, &previous_boards, &rules)
+            .expect("Test failed");
+        assert_eq!(status, Status::DefendersWin);
+    }
+
+    /// Check that we correctly identify shield wall captures
+    #[test]
+    fn test_shield_walls() {
+        // valid shield wall with two capture sets,
+        // one of which includes the king, and using
+        // a corner as a flanking piece
+        let board = [
+            "...........",
+            "O..........",
+            "XO.........",
+            "XO.........",
+            "XO.........",
+            "XO.........",
+            "O..........",
+            "XO.........",
+            "KO.........",
+            "XO.........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let captures = board.captures_shield_wall(
+            &Role::Attacker,
+            &Square { x: 0, y: 6 },
+            &Default::default(),
+        );
+        assert_eq!(
+            captures,
+            vec![
+                Square { x: 0, y: 5 },
+                Square { x: 0, y: 4 },
+                Square { x: 0, y: 3 },
+                Square { x: 0, y: 2 },
+                Square { x: 0, y: 7 },
+                Square { x: 0, y: 9 },
+            ],
+        );
+        // non-flanking moves should not result in shield captures
+        let captures = board.captures_shield_wall(
+            &Role::Attacker,
+            &Square { x: 1, y: 5 },
+            &Default::default(),
+        );
+        assert!(captures.is_empty());
+        // moves off the edge skip the scans entirely
+        for sq in Square::iter().filter(|sq| !sq.is_edge()) {
+            let captures = board.captures_shield_wall(&Role::Attacker, &sq, &Default::default());
+            assert!(captures.is_empty());
+            assert_eq!(captures.capacity(), 0);
+        }
+        // the situation on the left should be ignored
+        let board = [
+            "...........",
+            "O.........O",
+            "XO.......OX",
+            "XO.......OX",
+            "XO.......OX",
+            "XO.......OX",
+            "O.........O",
+            "XO.......OX",
+            "XO.......OK",
+            "O.........O",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let captures = board.captures_shield_wall(
+            &Role::Attacker,
+            &Square { x: 10, y: 6 },
+            &Default::default(),
+        );
+        assert_eq!(
+            captures,
+            vec![
+                Square { x: 10, y: 5 },
+                Square { x: 10, y: 4 },
+                Square { x: 10, y: 3 },
+                Square { x: 10, y: 2 },
+                Square { x: 10, y: 7 },
+            ],
+        );
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".OOO..O....",
+            "..KXOXXO...",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let captures = board.captures_shield_wall(
+            &Role::Attacker,
+            &Square { x: 4, y: 10 },
+            &Default::default(),
+        );
+        assert!(captures.is_empty());
+    }
+
+    /// Test that shield walls shorter than the rules allow capture nothing
+    #[test]
+    fn test_shield_wall_min() {
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "..O....OO..",
+            ".OXO..OXXO.",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let rules = Rules {
+            shield_wall_min: 2,
+            ..Default::default()
+        };
+        let single = Square { x: 3, y: 10 };
+        assert_eq!(
+            board.captures_shield_wall(&Role::Attacker, &single, &Default::default()),
+            vec![Square { x: 2, y: 10 }],
+        );
+        assert!(
+            board
+                .captures_shield_wall(&Role::Attacker, &single, &rules)
+                .is_empty()
+        );
+        let double = Square { x: 6, y: 10 };
+        for rules in [Default::default(), rules] {
+            assert_eq!(
+                board.captures_shield_wall(&Role::Attacker, &double, &rules),
+                vec![Square { x: 7, y: 10 }, Square { x: 8, y: 10 }],
+            );
+        }
+    }
+
+    /// Test recovering a move and its captures from the boards before
+    /// and after it
+    #[test]
+    fn test_diff() {
+        let before = [
+            "...O.......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".OX........",
+            "...........",
+            "...........",
+            "........K..",
+            "...........",
+            "...........",
+        ];
+        let before = Board::try_from(before).expect("Test failed");
+        let plain = Play {
+            role: Role::Defender,
+            from: Square { x: 8, y: 8 },
+            to: Square { x: 8, y: 6 },
+        };
+        let (after, captures) = before
+            .move_piece(&plain, &Default::default())
+            .expect("Test failed");
+        assert!(captures.is_empty());
+        assert_eq!(
+            Board::diff(&before, &after, &Default::default()),
+            Some((plain.clone(), vec![]))
+        );
+
+        let capture = Play {
+            role: Role::Attacker,
+            from: Square { x: 3, y: 0 },
+            to: Square { x: 3, y: 5 },
+        };
+        let (after, captures) = before
+            .move_piece(&capture, &Default::default())
+            .expect("Test failed");
+        assert_eq!(captures, vec![Square { x: 2, y: 5 }]);
+        assert_eq!(
+            Board::diff(&before, &after, &Default::default()),
+            Some((capture, captures))
+        );
+
+        // no move, two moves and a piece jumping over another
+        assert_eq!(Board::diff(&before, &before, &Default::default()), None);
+        let (two_moves, _) = after
+            .move_piece(&plain, &Default::default())
+            .expect("Test failed");
+        assert_eq!(Board::diff(&before, &two_moves, &Default::default()), None);
+        let mut jumped = before.clone();
+        jumped.set(&Square { x: 1, y: 5 }, Space::Empty);
+        jumped.set(&Square { x: 3, y: 5 }, Space::Occupied(Role::Attacker));
+        assert_eq!(Board::diff(&before, &jumped, &Default::default()), None);
+        // a legal move, but with an enemy piece it couldn't capture gone
+        let (mut vanished, _) = before
+            .move_piece(&plain, &Default::default())
+            .expect("Test failed");
+        vanished.set(&Square { x: 3, y: 0 }, Space::Empty);
+        assert_eq!(Board::diff(&before, &vanished, &Default::default()), None);
+    }
+
+    /// Test that the cached king square and material stay correct
+    /// as the board changes
+    #[test]
+    fn test_cached_king_and_material() {
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...O.......",
+            "...X.......",
+            ".......O...",
+            "..K........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(board.king_square(), Some(Square { x: 2, y: 7 }));
+        assert_eq!(board.material(), (2, 2));
+        let previous_boards = PositionsTracker::Previous(Default::default());
+
+        // the king moves
+        let (board, _, _) = board
+            .play_internal(
+                &Play {
+                    role: Role::Defender,
+                    from: Square { x: 2, y: 7 },
+                    to: Square { x: 2, y: 6 },
+                },
+                &Status::Ongoing,
+                &previous_boards,
+            )
+            .expect("Test failed");
+        assert_eq!(board.king_square(), Some(Square { x: 2, y: 6 }));
+        assert_eq!(board.material(), (2, 2));
+
+        // a defender is captured
+        let (mut board, captures, _) = board
+            .play_internal(
+                &Play {
+                    role: Role::Attacker,
+                    from: Square { x: 7, y: 6 },
+                    to: Square { x: 3, y: 6 },
+                },
+                &Status::Ongoing,
+                &previous_boards,
+            )
+            .expect("Test failed");
+        assert_eq!(captures, vec![Square { x: 3, y: 5 }]);
+        assert_eq!(board.king_square(), Some(Square { x: 2, y: 6 }));
+        assert_eq!(board.material(), (2, 1));
+
+        // the king is removed from the board
+        board.set(&Square { x: 2, y: 6 }, Space::Empty);
+        assert_eq!(board.king_square(), None);
+        assert_eq!(board.material(), (2, 0));
+        assert_eq!(board.king_square(), board.find_the_king());
+    }
+
+    /// Test comparing boards while ignoring their orientation
+    #[test]
+    fn test_same_up_to_symmetry() {
+        let board = [
+            "...OOOOO...",
+            "...X....O..",
+            ".........O.",
+            "...O.X....O",
+            "O....XX...O",
+            "...O..XX..O",
+            "O.O.....O.O",
+            "OX.O.......",
+            "..........K",
+            ".....O.....",
+            "....OO.O...",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        // the king is on the throne, a line of symmetry
+        let mut throne = Board::default();
+        throne.set(&Square { x: 3, y: 0 }, Space::Empty);
+        throne.set(&Square { x: 1, y: 0 }, Space::Occupied(Role::Attacker));
+        for board in [board, throne] {
+            for symmetric in board.symmetries() {
+                assert!(board.same_up_to_symmetry(&symmetric));
+                assert!(symmetric.same_up_to_symmetry(&board));
+            }
+            let mut different = board.clone();
+            different.set(&Square { x: 5, y: 0 }, Space::Empty);
+            different.set(&Square { x: 5, y: 2 }, Space::Occupied(Role::Attacker));
+            assert!(!board.same_up_to_symmetry(&different));
+            assert!(!different.same_up_to_symmetry(&board));
+            assert!(!board.same_up_to_symmetry(&Board::default()));
+        }
+    }
+
+    /// Test that the Zobrist hash kept up to date while playing agrees
+    /// with hashing the board from scratch, and that symmetric boards
+    /// share a canonical hash
+    #[test]
+    fn test_zobrist() {
+        let start = Board::default();
+        assert_eq!(start.zobrist(), start.compute_zobrist());
+        let previous_boards = PositionsTracker::Previous(Default::default());
+        // the attacker is captured
+        let (board, captures, _) = Board::try_from([
+            "...........",
+            "...K.......",
+            "...........",
+            "...........",
+            "...O.......",
+            "...X.......",
+            ".......O...",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed")
+        .play_internal(
+            &Play {
+                role: Role::Defender,
+                from: Square { x: 3, y: 1 },
+                to: Square { x: 3, y: 3 },
+            },
+            &Status::Ongoing,
+            &previous_boards,
+        )
+        .expect("Test failed");
+        assert_eq!(captures, vec![Square { x: 3, y: 4 }]);
+        let expected = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...K.......",
+            "...........",
+            "...X.......",
+            ".......O...",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(board.zobrist(), board.compute_zobrist());
+        assert_eq!(board.zobrist(), expected.zobrist());
+        assert_ne!(board.zobrist(), start.zobrist());
+
+        for board in [start, board] {
+            for symmetric in board.symmetries() {
+                assert_eq!(symmetric.canonical_zobrist(), board.canonical_zobrist());
+            }
+            let mut different = board.clone();
+            different.set(&Square { x: 5, y: 2 }, Space::Occupied(Role::Attacker));
+            assert_ne!(different.canonical_zobrist(), board.canonical_zobrist());
+        }
+    }
+
+    /// Test replaying a list of moves to the end of the game and
+    /// stopping at the first illegal one
+    #[test]
+    fn test_apply_move_sequence() {
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...O.......",
+            "...........",
+            ".....K.....",
+            "...........",
+            ".......O...",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let play = |role, from: (usize, usize), to: (usize, usize)| Play {
+            role,
+            from: Square {
+                x: from.0,
+                y: from.1,
+            },
+            to: Square { x: to.0, y: to.1 },
+        };
+        let game = [
+            play(Role::Attacker, (3, 3), (3, 4)),
+            play(Role::Defender, (5, 5), (5, 10)),
+            play(Role::Attacker, (7, 7), (8, 7)),
+            play(Role::Defender, (5, 10), (10, 10)),
+        ];
+        let mut replayed = board.clone();
+        let mut tracker = PositionsTracker::Previous(Default::default());
+        let (boards, status) = replayed
+            .apply_move_sequence(&game, Role::Attacker, &mut tracker)
+            .expect("Test failed");
+        assert_eq!(status, Status::DefendersWin);
+        assert_eq!(boards.len(), 4);
+        assert_eq!(boards[0], board);
+        assert_eq!(replayed.king_square(), Some(Square { x: 10, y: 10 }));
+        assert_eq!(tracker.len(), 4);
+
+        // the game is over
+        let mut replayed = board.clone();
+        let mut tracker = PositionsTracker::Previous(Default::default());
+        let mut too_long = game.to_vec();
+        too_long.push(play(Role::Attacker, (3, 4), (3, 5)));
+        let (ix, err) = replayed
+            .apply_move_sequence(&too_long, Role::Attacker, &mut tracker)
+            .unwrap_err();
+        assert_eq!(ix, 4);
+        assert!(matches!(err, PlayError::GameFinished));
+
+        // the attackers move twice
+        let mut replayed = board.clone();
+        let mut tracker = PositionsTracker::Previous(Default::default());
+        let twice = [game[0].clone(), game[2].clone()];
+        let (ix, err) = replayed
+            .apply_move_sequence(&twice, Role::Attacker, &mut tracker)
+            .unwrap_err();
+        assert_eq!(ix, 1);
+        assert!(matches!(
+            err,
+            PlayError::WrongTurn {
+                square: Square { x: 7, y: 7 },
+                found: Role::Attacker
+            }
+        ));
+
+        // the second attacker move goes through the king
+        let mut replayed = board.clone();
+        let mut tracker = PositionsTracker::Previous(Default::default());
+        let illegal = [
+            game[0].clone(),
+            play(Role::Defender, (5, 5), (5, 4)),
+            play(Role::Attacker, (3, 4), (7, 4)),
+            game[3].clone(),
+        ];
+        let (ix, err) = replayed
+            .apply_move_sequence(&illegal, Role::Attacker, &mut tracker)
+            .unwrap_err();
+        assert_eq!(ix, 2);
+        assert!(matches!(
+            err,
+            PlayError::MoveThroughPiece(Square { x: 5, y: 4 })
+        ));
+        assert_eq!(replayed.king_square(), Some(Square { x: 5, y: 4 }));
+        assert_eq!(tracker.len(), 2);
+
+        // the defenders are to move first, and the error names the piece
+        // actually on the square rather than the side the play claims
+        let mut replayed = board.clone();
+        let mut tracker = PositionsTracker::Previous(Default::default());
+        let mislabeled = [play(Role::Attacker, (5, 5), (5, 4))];
+        let (ix, err) = replayed
+            .apply_move_sequence(&mislabeled, Role::Defender, &mut tracker)
+            .unwrap_err();
+        assert_eq!(ix, 0);
+        assert!(matches!(
+            err,
+            PlayError::WrongTurn {
+                square: Square { x: 5, y: 5 },
+                found: Role::Defender
+            }
+        ));
+        assert_eq!(replayed, board);
+    }
+
+    /// Test setting up a board from the squares of each side
+    #[test]
+    fn test_from_role_setup() {
+        let board = Board::from_role_setup(
+            Role::Attacker.starting_squares(),
+            Role::Defender.starting_squares(),
+        )
+        .expect("Test failed");
+        assert_eq!(board, Board::default());
+
+        let err = Board::from_role_setup(
+            Role::Attacker.starting_squares(),
+            Role::Attacker.starting_squares().take(1),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "More than one piece was placed on A8");
+        let err = Board::from_role_setup([Square { x: 0, y: 0 }], []).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Only the king is allowed on restricted squares!"
+        );
+    }
+
+    /// Test checking if a game can be played from a board
+    #[test]
+    fn test_validate_playable() {
+        assert!(Board::default().validate_playable().is_ok());
+        let mut board = Board::default();
+        board.set(&Square { x: 0, y: 5 }, Space::King);
+        assert_eq!(
+            board.validate_playable().unwrap_err().to_string(),
+            "There must be exactly one king, found 2"
+        );
+        let mut board = Board::default();
+        board.set(&THRONE, Space::Empty);
+        assert!(board.validate_playable().is_err());
+        let mut board = Board::default();
+        board.set(&Square { x: 0, y: 0 }, Space::Occupied(Role::Attacker));
+        assert_eq!(
+            board.validate_playable().unwrap_err().to_string(),
+            "Only the king is allowed on restricted squares!"
+        );
+        let mut board = Board::default();
+        board.set(&THRONE, Space::Empty);
+        board.set(&Square { x: 10, y: 10 }, Space::King);
+        assert_eq!(
+            board.validate_playable().unwrap_err().to_string(),
+            "The king has already escaped"
+        );
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "....O......",
+            "...OKO.....",
+            "....O......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(
+            board.validate_playable().unwrap_err().to_string(),
+            "The attackers have already won"
+        );
+    }
+
+    /// Test working out the status of loaded positions without playing
+    #[test]
+    fn test_compute_status() {
+        let tracker = PositionsTracker::Counter(0);
+        let rules = Rules::default();
+        assert_eq!(
+            Board::default().compute_status(Role::Attacker, &tracker, &rules),
+            Status::Ongoing
+        );
+        assert_eq!(
+            Board::default().compute_status(
+                Role::Attacker,
+                &PositionsTracker::Counter(100),
+                &rules
+            ),
+            Status::Draw
+        );
+        let escaped = Board::try_from([
+            "...OOOOO..K",
+            ".....O.....",
+            "...........",
+            "O....X....O",
+            "O...X.X...O",
+            "OO..X.XX.OO",
+            "O..XXXX...O",
+            "O....X....O",
+            "...........",
+            ".....O.....",
+            "...OOOOO...",
+        ])
+        .expect("Test failed");
+        assert_eq!(
+            escaped.compute_status(Role::Attacker, &tracker, &rules),
+            Status::DefendersWin
+        );
+        let encircled = Board::try_from([
+            "...........",
+            "...........",
+            "....O......",
+            "...OKO.....",
+            "....O......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(
+            encircled.compute_status(Role::Defender, &tracker, &rules),
+            Status::AttackersWin
+        );
+        // the attackers have no moves left
+        let stuck = Board::try_from([
+            ".OX........",
+            ".X.........",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(
+            stuck.compute_status(Role::Attacker, &tracker, &rules),
+            Status::DefendersWin
+        );
+        // the king is only captured against the edge under some rules
+        let edge = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "O..........",
+            "KO.........",
+            "O..........",
+            "...........",
+            "...........",
+            "...........",
+            "......X....",
+        ])
+        .expect("Test failed");
+        assert_eq!(
+            edge.compute_status(Role::Defender, &tracker, &rules),
+            Status::Ongoing
+        );
+        let edge_capture = Rules {
+            edge_king_capture: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            edge.compute_status(Role::Defender, &tracker, &edge_capture),
+            Status::AttackersWin
+        );
+    }
+
+    /// Test that boards survive being encoded as bytes, whether or not
+    /// the king is on the throne
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mut board = Board::random_start(&mut rng);
+            let mut role = Role::Attacker;
+            for _ in 0..rng.random_range(0..40) {
+                assert_eq!(Board::from_bytes(&board.to_bytes()), board);
+                let plays = board.legal_moves_sorted(&role);
+                if plays.is_empty() {
+                    break;
+                }
+                let play = &plays[rng.random_range(0..plays.len())];
+                board = board
+                    .move_piece(play, &Default::default())
+                    .expect("Test failed")
+                    .0;
+                role = role.opposite();
+            }
+            assert_eq!(Board::from_bytes(&board.to_bytes()), board);
+        }
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "..........K",
+        ])
+        .expect("Test failed");
+        assert_eq!(Board::from_bytes(&board.to_bytes()), board);
+    }
+
+    /// Test that a board whose king has been captured is not decoded with
+    /// the king back on the throne
+    #[test]
+    fn test_bytes_without_king() {
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".....O.....",
+            "....O.O....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(board.king_square(), None);
+        let bytes = board.to_bytes();
+        let mut on_throne = board.clone();
+        on_throne.set(&THRONE, Space::King);
+        assert_ne!(bytes, on_throne.to_bytes());
+        let decoded = Board::from_bytes(&bytes);
+        assert_eq!(decoded.king_square(), None);
+        assert_eq!(decoded, board);
+    }
+
+    /// Test that random starting positions are playable, keep the material
+    /// and the king on the throne, and usually differ from each other
+    #[test]
+    fn test_random_start() {
+        let mut rng = rand::rng();
+        let starts = (0..100)
+            .map(|_| Board::random_start(&mut rng))
+            .collect::<Vec<_>>();
+        for board in &starts {
+            board.validate_playable().expect("Test failed");
+            assert_eq!(board.king_square(), Some(THRONE));
+            assert_eq!(board.material(), STARTING_MATERIAL);
+            // the attackers on each edge are rotations of each other
+            assert!(board.symmetries().contains(board));
+            let mut rotated = board.clone();
+            D8[5].apply(&mut rotated);
+            assert_eq!(rotated, *board);
+        }
+        let distinct = starts.iter().collect::<HashSet<_>>();
+        assert!(distinct.len() > 50);
+    }
+
+    /// Test that legal moves are listed in a fixed order
+    #[test]
+    fn test_legal_moves_sorted() {
+        let board = Board::default();
+        let plays = board.legal_moves_sorted(&Role::Attacker);
+        assert_eq!(plays.len(), board.mobility(&Role::Attacker));
+        assert_eq!(plays, board.legal_moves_sorted(&Role::Attacker));
+        assert!(plays.windows(2).all(|w| w[0].sort_key() < w[1].sort_key()));
+        let text = plays
+            .iter()
+            .take(3)
+            .map(Play::to_algebraic)
+            .collect::<Vec<_>>();
+        assert_eq!(text, vec!["A4->A2", "A4->A3", "A4->B4"]);
+    }
+
+    /// Test that the move capturing the most pieces is found
+    #[test]
+    fn test_best_immediate_capture() {
+        let board = [
+            "...........",
+            "OX.........",
+            "......OX.XO",
+            "..O........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "........O..",
+            "..K........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let (play, count) = board
+            .best_immediate_capture(&Role::Attacker)
+            .expect("Test failed");
+        assert_eq!(count, 2);
+        assert_eq!(
+            play,
+            Play {
+                role: Role::Attacker,
+                from: Square { x: 8, y: 8 },
+                to: Square { x: 8, y: 2 },
+            }
+        );
+
+        let board = Board::default();
+        assert!(board.best_immediate_capture(&Role::Attacker).is_none());
+        assert!(board.best_immediate_capture(&Role::Defender).is_none());
+    }
+
+    /// Test that the pieces of each side are found
+    #[test]
+    fn test_pieces() {
+        let sparse = [
+            "...........",
+            "...........",
+            "...O.......",
+            "...........",
+            "...........",
+            "........X..",
+            "...........",
+            "...........",
+            "...........",
+            ".K........O",
+            "...........",
+        ];
+        let boards = [
+            Board::default(),
+            Board::empty(),
+            Board::try_from(sparse).expect("Test failed"),
+        ];
+        for board in boards {
+            for role in [Role::Attacker, Role::Defender] {
+                let pieces = board.pieces(&role);
+                let mut expected = Square::iter()
+                    .filter(|sq| board.get(sq).is_ally(&role))
+                    .collect::<Vec<_>>();
+                expected.sort_by_key(|sq| (sq.y, sq.x));
+                assert_eq!(pieces.keys().collect::<Vec<_>>(), expected);
+            }
+            let (attackers, defenders) = board.material();
+            assert_eq!(board.pieces(&Role::Attacker).len(), attackers as usize);
+            assert_eq!(board.pieces(&Role::Defender).len(), defenders as usize);
+        }
+    }
+
+    /// Test that the occupied squares of a side follow the board
+    /// as pieces move and are captured
+    #[test]
+    fn test_occupied_squares() {
+        let mut board = Board::try_from([
+            "...........",
+            "...........",
+            "...O.......",
+            "...X.......",
+            "...........",
+            "...O.......",
+            "...........",
+            "...........",
+            "...........",
+            ".K.........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(
+            board.occupied_squares(Role::Defender).collect::<Vec<_>>(),
+            vec![Square { x: 3, y: 3 }, Square { x: 1, y: 9 }]
+        );
+        let (captures, _) = board
+            .play(
+                &Play {
+                    role: Role::Attacker,
+                    from: Square { x: 3, y: 5 },
+                    to: Square { x: 3, y: 4 },
+                },
+                &Status::Ongoing,
+                &mut PositionsTracker::Counter(0),
+            )
+            .expect("Test failed");
+        assert_eq!(captures, vec![Square { x: 3, y: 3 }]);
+        assert_eq!(
+            board.occupied_squares(Role::Defender).collect::<Vec<_>>(),
+            vec![Square { x: 1, y: 9 }]
+        );
+        assert_eq!(
+            board.occupied_squares(Role::Attacker).collect::<Vec<_>>(),
+            vec![Square { x: 3, y: 2 }, Square { x: 3, y: 4 }]
+        );
+    }
+
+    /// Test the squares reachable through passable spaces
+    #[test]
+    fn test_reachable_region() {
+        let empty = |space| space == Space::Empty;
+        let board = Board::empty();
+        assert_eq!(board.reachable_region(THRONE, empty).len(), 121);
+
+        // a wall across the board
+        let mut board = Board::empty();
+        for y in 0..11 {
+            board.set(&Square { x: 3, y }, Space::Occupied(Role::Attacker));
+        }
+        let region = board.reachable_region(THRONE, empty);
+        assert_eq!(region.len(), 7 * 11);
+        assert!(region.keys().all(|sq| sq.x > 3));
+        let region = board.reachable_region(Square { x: 0, y: 0 }, empty);
+        assert_eq!(region.len(), 3 * 11);
+        // the wall is no obstacle if attackers are passable
+        let region = board.reachable_region(THRONE, |space| space != Space::King);
+        assert_eq!(region.len(), 121);
+
+        // a box around the throne
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "....XXX....",
+            "...X...X...",
+            "...X...X...",
+            "...X...X...",
+            "....XXX....",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(board.reachable_region(THRONE, empty).len(), 9);
+        // the start is included even if it isn't passable, and leads
+        // both into and out of the box
+        let region = board.reachable_region(Square { x: 4, y: 3 }, empty);
+        assert!(region.contains(&Square { x: 4, y: 3 }));
+        assert_eq!(region.len(), 121 - 12 + 1);
+    }
+
+    /// Test that only the moves parrying an immediate threat are safe
+    #[test]
+    fn test_forced_moves() {
+        let board = [
+            "...........",
+            "..O........",
+            ".OKO.......",
+            "...........",
+            "..O........",
+            "...........",
+            "...........",
+            "...........",
+            "........X..",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert!(board.king_can_be_captured(&Default::default()));
+        // only stepping the king into the gap stops the capture
+        assert_eq!(
+            board
+                .forced_moves(&Role::Defender, &Default::default())
+                .collect::<Vec<_>>(),
+            vec![Play {
+                role: Role::Defender,
+                from: Square { x: 2, y: 2 },
+                to: Square { x: 2, y: 3 },
+            }]
+        );
+        assert!(board.mobility(&Role::Defender) > 1);
+
+        // the attackers can block only one of the king's escapes
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "K..........",
+            "...........",
+            "...........",
+            "...........",
+            "..O........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert!(board.king_can_escape());
+        let rules = Rules::default();
+        assert_eq!(board.forced_moves(&Role::Attacker, &rules).count(), 0);
+        assert_eq!(board.forced_moves(&Role::Defender, &rules).count(), 20);
+
+        // the king can only be captured on the edge under some rules
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "O..........",
+            "KO.........",
+            ".....O.....",
+            "...........",
+            "...........",
+            "........X..",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let edge_capture = Rules {
+            edge_king_capture: true,
+            ..Default::default()
+        };
+        // only the king stepping away is safe
+        assert!(
+            board
+                .forced_moves(&Role::Defender, &edge_capture)
+                .all(|play| play.from == Square { x: 0, y: 5 })
+        );
+        assert!(
+            board.forced_moves(&Role::Defender, &edge_capture).count()
+                < board.forced_moves(&Role::Defender, &rules).count()
+        );
+    }
+
+    /// Test listing the moves taking the king straight to a corner
+    #[test]
+    fn test_king_escape_moves() {
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "K..........",
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(
+            board.king_escape_moves(&Default::default()),
+            vec![Play {
+                role: Role::Defender,
+                from: Square { x: 0, y: 5 },
+                to: Square { x: 0, y: 0 },
+            }]
+        );
+        // the corner is out of reach of a king moving a few squares
+        let short = |range| Rules {
+            king_move_range: Some(range),
+            ..Default::default()
+        };
+        assert!(board.king_escape_moves(&short(4)).is_empty());
+        assert_eq!(board.king_escape_moves(&short(5)).len(), 1);
+
+        let board = [
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+            "K..........",
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert!(board.king_escape_moves(&Default::default()).is_empty());
+        assert!(!board.king_can_escape());
+    }
+
+    /// Test that previewing a move finds both custodial and
+    /// shield wall captures and leaves the board untouched
+    #[test]
+    fn test_captures_for_move() {
+        let board = [
+            "...........",
+            "...........",
+            "O..........",
+            "XO.........",
+            "XO.........",
+            ".XO........",
+            "...........",
+            "...........",
+            "O.......K..",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let before = board.clone();
+        let captures = board
+            .captures_for_move(&Play {
+                role: Role::Attacker,
+                from: Square { x: 0, y: 8 },
+                to: Square { x: 0, y: 5 },
+            })
+            .expect("Test failed");
         assert_eq!(
             captures,
             vec![
-                Square { x: 0, y: 5 },
+                Square { x: 1, y: 5 },
                 Square { x: 0, y: 4 },
                 Square { x: 0, y: 3 },
-                Square { x: 0, y: 2 },
-                Square { x: 0, y: 7 },
-                Square { x: 0, y: 9 },
-            ],
+            ]
         );
-        // non-flanking moves should not result in shield captures
-        let captures = board.captures_shield_wall(&Role::Attacker, &Square { x: 1, y: 5 });
-        assert!(captures.is_empty());
-        // the situation on the left should be ignored
+        assert_eq!(board, before);
+
+        let err = board
+            .captures_for_move(&Play {
+                role: Role::Attacker,
+                from: Square { x: 0, y: 8 },
+                to: Square { x: 0, y: 1 },
+            })
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PlayError::MoveThroughPiece(Square { x: 0, y: 4 })
+        ));
+    }
+
+    /// Test that we find the squares that would result in captures
+    #[test]
+    fn test_threatened_squares() {
+        // a pending custodial capture
         let board = [
             "...........",
-            "O.........O",
-            "XO.......OX",
-            "XO.......OX",
-            "XO.......OX",
-            "XO.......OX",
-            "O.........O",
-            "XO.......OX",
-            "XO.......OK",
-            "O.........O",
             "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...OX......",
+            "...........",
+            "...........",
+            ".....O.....",
         ];
         let board = Board::try_from(board).expect("Test failed");
-        let captures = board.captures_shield_wall(&Role::Attacker, &Square { x: 10, y: 6 });
-        assert_eq!(
-            captures,
-            vec![
-                Square { x: 10, y: 5 },
-                Square { x: 10, y: 4 },
-                Square { x: 10, y: 3 },
-                Square { x: 10, y: 2 },
-                Square { x: 10, y: 7 },
-            ],
-        );
+        let threatened = board.threatened_squares(&Role::Attacker, &Default::default());
+        let threatened = Square::iter()
+            .filter(|sq| threatened.contains(sq))
+            .collect::<Vec<_>>();
+        assert_eq!(threatened, vec![Square { x: 5, y: 7 }]);
+        let threatened = board.threatened_squares(&Role::Defender, &Default::default());
+        assert!(Square::iter().all(|sq| !threatened.contains(&sq)));
+
+        // a pending shield wall capture
         let board = [
             "...........",
             "...........",
             "...........",
             "...........",
             "...........",
+            ".....K.....",
             "...........",
             "...........",
             "...........",
-            "...........",
-            ".OOO..O....",
-            "..KXOXXO...",
+            "...OO......",
+            "..OXX..O...",
         ];
         let board = Board::try_from(board).expect("Test failed");
-        let captures = board.captures_shield_wall(&Role::Attacker, &Square { x: 4, y: 10 });
-        assert!(captures.is_empty());
+        let threatened = board.threatened_squares(&Role::Attacker, &Default::default());
+        let threatened = Square::iter()
+            .filter(|sq| threatened.contains(sq))
+            .collect::<Vec<_>>();
+        assert_eq!(threatened, vec![Square { x: 5, y: 10 }]);
     }
 
     /// Test we detect when capturing the king
@@ -880,7 +3310,7 @@ mod test_board {
             "...........",
         ];
         let board = Board::try_from(board).expect("Test failed");
-        assert!(!board.capture_the_king());
+        assert!(!board.capture_the_king(&Default::default()));
         // not a king capture
         let board = [
             "...........",
@@ -896,7 +3326,7 @@ mod test_board {
             "...........",
         ];
         let board = Board::try_from(board).expect("Test failed");
-        assert!(!board.capture_the_king());
+        assert!(!board.capture_the_king(&Default::default()));
         // throne does not partake in capture
         let board = [
             "...........",
@@ -912,7 +3342,7 @@ mod test_board {
             "...........",
         ];
         let board = Board::try_from(board).expect("Test failed");
-        assert!(!board.capture_the_king());
+        assert!(!board.capture_the_king(&Default::default()));
         // a real king capture
         let board = [
             "...........",
@@ -928,7 +3358,72 @@ mod test_board {
             "...........",
         ];
         let board = Board::try_from(board).expect("Test failed");
-        assert!(board.capture_the_king());
+        assert!(board.capture_the_king(&Default::default()));
+    }
+
+    /// Test that a king on the edge is only captured by three attackers
+    /// if the rules allow it
+    #[test]
+    fn test_edge_king_capture() {
+        let edge_rules = Rules {
+            edge_king_capture: true,
+            ..Default::default()
+        };
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "X..........",
+            "OO.........",
+            "K..........",
+            "O..........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let play = Play {
+            role: Role::Attacker,
+            from: Square { x: 1, y: 7 },
+            to: Square { x: 1, y: 8 },
+        };
+        let previous_boards = PositionsTracker::Counter(0);
+        let (after, _, status) = board
+            .play_internal_with_rules(
+                &play,
+                &Status::Ongoing,
+                &previous_boards,
+                &Default::default(),
+            )
+            .expect("Test failed");
+        assert_eq!(status, Status::Ongoing);
+        assert!(!after.capture_the_king(&Default::default()));
+        assert!(after.capture_the_king(&edge_rules));
+        let (_, _, status) = board
+            .play_internal_with_rules(&play, &Status::Ongoing, &previous_boards, &edge_rules)
+            .expect("Test failed");
+        assert_eq!(status, Status::AttackersWin);
+        // before the move, the attackers threaten the capture
+        assert!(!board.king_can_be_captured(&Default::default()));
+        assert!(board.king_can_be_captured(&edge_rules));
+
+        // the empty corner does not help capture the king
+        let board = [
+            ".KO........",
+            ".O.........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert!(!board.capture_the_king(&edge_rules));
     }
 
     #[test]
@@ -1043,7 +3538,7 @@ mod test_board {
             .to_string();
         assert_eq!(
             err,
-            "Attempted to move a piece belonging to the opposite player"
+            "Attempted to move the attacker on D1, which belongs to the opposite player"
         );
 
         let err = board
@@ -1060,8 +3555,39 @@ mod test_board {
             .to_string();
         assert_eq!(
             err,
-            "Attempted to move a piece belonging to the opposite player"
+            "Attempted to move the defender on F8, which belongs to the opposite player"
+        );
+
+        let err = board
+            .play_internal(
+                &Play {
+                    role: Role::Attacker,
+                    from: THRONE,
+                    to: Square { x: 5, y: 4 },
+                },
+                &Status::Ongoing,
+                &previous_boards,
+            )
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Attempted to move the defender on F6, which belongs to the opposite player"
         );
+
+        let err = board
+            .play_internal(
+                &Play {
+                    role: Role::Attacker,
+                    from: Square { x: 2, y: 2 },
+                    to: Square { x: 2, y: 3 },
+                },
+                &Status::Ongoing,
+                &previous_boards,
+            )
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "There is no piece on C9 to move");
     }
 
     /// Test that moving pieces through other pieces is forbidden
@@ -1081,7 +3607,7 @@ mod test_board {
             )
             .unwrap_err()
             .to_string();
-        assert_eq!(err, "Attempted to move a piece through another piece");
+        assert_eq!(err, "Attempted to move a piece through the piece on E6");
         let err = board
             .play_internal(
                 &Play {
@@ -1094,7 +3620,7 @@ mod test_board {
             )
             .unwrap_err()
             .to_string();
-        assert_eq!(err, "Attempted to move a piece through another piece");
+        assert_eq!(err, "Attempted to move a piece through the piece on G6");
         let err = board
             .play_internal(
                 &Play {
@@ -1107,7 +3633,7 @@ mod test_board {
             )
             .unwrap_err()
             .to_string();
-        assert_eq!(err, "Attempted to move a piece through another piece");
+        assert_eq!(err, "Attempted to move a piece through the piece on F5");
         let err = board
             .play_internal(
                 &Play {
@@ -1120,7 +3646,23 @@ mod test_board {
             )
             .unwrap_err()
             .to_string();
-        assert_eq!(err, "Attempted to move a piece through another piece");
+        assert_eq!(err, "Attempted to move a piece through the piece on F7");
+        // the piece on the destination square is reported as well
+        let err = board
+            .play_internal(
+                &Play {
+                    role: Role::Defender,
+                    from: THRONE,
+                    to: Square { x: 5, y: 4 },
+                },
+                &Status::Ongoing,
+                &previous_boards,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PlayError::MoveThroughPiece(Square { x: 5, y: 4 })
+        ));
     }
 
     /// Test moving to / through restricted squares
@@ -1315,6 +3857,64 @@ mod test_board {
         );
     }
 
+    /// Test the yes or no answer to whether a move is legal agrees
+    /// with playing it
+    #[test]
+    fn test_is_legal_move() {
+        let board = Board::default();
+        let mut previous_boards = PositionsTracker::Previous(Default::default());
+        let play = |role, from, to| Play { role, from, to };
+        let legal = play(Role::Attacker, Square { x: 3, y: 0 }, Square { x: 3, y: 3 });
+        assert!(board.is_legal_move(&legal, &Status::Ongoing, &previous_boards));
+        assert!(!board.is_legal_move(&legal, &Status::Draw, &previous_boards));
+        // moving the opponent's piece
+        let opponents = play(
+            Role::Defender,
+            Square { x: 3, y: 10 },
+            Square { x: 3, y: 9 },
+        );
+        assert!(!board.is_legal_move(&opponents, &Status::Ongoing, &previous_boards));
+        // moving through another piece
+        let through = play(Role::Defender, THRONE, Square { x: 2, y: 5 });
+        assert!(!board.is_legal_move(&through, &Status::Ongoing, &previous_boards));
+        // moving onto a restricted square
+        let restricted = play(Role::Attacker, Square { x: 3, y: 0 }, Square { x: 0, y: 0 });
+        assert!(!board.is_legal_move(&restricted, &Status::Ongoing, &previous_boards));
+
+        // defenders can't repeat a position
+        let board = Board::try_from([
+            "...OOOOO...",
+            ".....O.....",
+            "...........",
+            "O....X....O",
+            "O...XXX...O",
+            "OO..XKXX.OO",
+            "O..XXXX...O",
+            "O....X....O",
+            "...........",
+            ".....O.....",
+            "...OOOOO...",
+        ])
+        .expect("Test failed");
+        let repeat = play(Role::Defender, Square { x: 3, y: 6 }, Square { x: 3, y: 5 });
+        assert!(board.is_legal_move(&repeat, &Status::Ongoing, &previous_boards));
+        previous_boards.insert(&Board::default());
+        assert!(!board.is_legal_move(&repeat, &Status::Ongoing, &previous_boards));
+        assert!(board.is_legal_move(&repeat, &Status::Ongoing, &PositionsTracker::Counter(1)));
+        for (play, legal) in [(legal, true), (repeat, false)] {
+            assert_eq!(
+                board
+                    .play_internal(&play, &Status::Ongoing, &previous_boards)
+                    .is_ok(),
+                legal
+            );
+            assert_eq!(
+                board.is_legal_move(&play, &Status::Ongoing, &previous_boards),
+                legal
+            );
+        }
+    }
+
     /// Test that defenders win if the king reaches a corner
     #[test]
     fn test_king_escape() {
@@ -1477,6 +4077,36 @@ mod test_board {
         assert_eq!(board, expected);
     }
 
+    /// Test that once the king is captured, a board and its mirror
+    /// image still normalize to the same board
+    #[test]
+    fn test_normalize_without_king() {
+        let board = Board::try_from([
+            "...........",
+            "...O.......",
+            ".OX........",
+            "...O.......",
+            "...........",
+            "...........",
+            "........X..",
+            "...........",
+            "...........",
+            "......O....",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(board.king_square(), None);
+        let mut normalized = board.clone();
+        normalized.normalize();
+        assert!(board.symmetries().contains(&normalized));
+        for d8 in D8 {
+            let mut mirror = board.clone();
+            d8.apply(&mut mirror);
+            mirror.normalize();
+            assert_eq!(mirror, normalized);
+        }
+    }
+
     /// Test listing all symmetric equivalents of a board
     #[test]
     fn test_symmetries() {
@@ -1613,6 +4243,190 @@ mod test_board {
         assert_eq!(expected, board.symmetries());
     }
 
+    /// Test that writing a board out as rows and reading it back
+    /// gives the same board
+    #[test]
+    fn test_ascii_round_trip() {
+        assert_eq!(
+            Board::default().to_ascii(),
+            STARTING_POSITION.map(String::from)
+        );
+        let board = [
+            "...........",
+            "...........",
+            "O..........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "....OOO....",
+            "....XXX....",
+            "...X.K.X...",
+        ];
+        assert_eq!(
+            Board::try_from(board).expect("Test failed").to_ascii(),
+            board.map(String::from)
+        );
+        let mut boards = vec![Board::default(), Board::empty()];
+        let mut rng = StdRng::seed_from_u64(0);
+        for plies in [1, 10, 40] {
+            boards.push(Board::random_legal_position(&mut rng, plies).0);
+        }
+        for board in boards {
+            let rows = board.to_ascii();
+            assert_eq!(Board::from_ascii(&rows).expect("Test failed"), board);
+            assert_eq!(
+                Board::try_from(rows.each_ref().map(String::as_str)).expect("Test failed"),
+                board
+            );
+        }
+    }
+
+    /// Test that the causes of each capture made by a move are reported
+    #[test]
+    fn test_capture_report() {
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...O.......",
+            ".OOX.......",
+            ".XX...O....",
+        ])
+        .expect("Test failed");
+        let play = Play {
+            role: Role::Attacker,
+            from: Square { x: 6, y: 10 },
+            to: Square { x: 3, y: 10 },
+        };
+        let report = board.capture_report(&play).expect("Test failed");
+        let shield_wall = CaptureMechanism::ShieldWall {
+            anchor: Square { x: 0, y: 10 },
+        };
+        assert_eq!(
+            report.captures,
+            vec![
+                Capture {
+                    square: Square { x: 3, y: 9 },
+                    mechanism: CaptureMechanism::Custodial {
+                        flankers: [Square { x: 3, y: 10 }, Square { x: 3, y: 8 }],
+                    },
+                },
+                Capture {
+                    square: Square { x: 2, y: 10 },
+                    mechanism: shield_wall,
+                },
+                Capture {
+                    square: Square { x: 1, y: 10 },
+                    mechanism: shield_wall,
+                },
+            ]
+        );
+        let (_, captures) = board
+            .move_piece(&play, &Rules::default())
+            .expect("Test failed");
+        assert_eq!(
+            captures,
+            report
+                .captures
+                .iter()
+                .map(|capture| capture.square)
+                .collect::<Vec<_>>()
+        );
+
+        // the corner flanks a piece next to it
+        let mut board = board;
+        board.set(&Square { x: 1, y: 10 }, Space::Empty);
+        board.set(&Square { x: 1, y: 9 }, Space::Empty);
+        board.set(&Square { x: 0, y: 9 }, Space::Occupied(Role::Defender));
+        let play = Play {
+            role: Role::Attacker,
+            from: Square { x: 0, y: 4 },
+            to: Square { x: 0, y: 8 },
+        };
+        assert!(board.capture_report(&play).is_err());
+        board.set(&Square { x: 0, y: 4 }, Space::Occupied(Role::Attacker));
+        assert_eq!(
+            board.capture_report(&play).expect("Test failed").captures,
+            vec![Capture {
+                square: Square { x: 0, y: 9 },
+                mechanism: CaptureMechanism::Custodial {
+                    flankers: [Square { x: 0, y: 8 }, Square { x: 0, y: 10 }],
+                },
+            }]
+        );
+    }
+
+    /// Test the penalty for attackers next to a corner with nothing
+    /// guarding them from capture along the edge
+    #[test]
+    fn test_attacker_corner_penalties() {
+        assert_eq!(Board::default().attacker_corner_penalties(0.5), 0.0);
+        let mut clean = Board::empty();
+        clean.set(&THRONE, Space::King);
+        assert_eq!(clean.attacker_corner_penalties(0.5), 0.0);
+        let mut all = clean.clone();
+        for (attacker, beyond) in CORNER_GUARDS {
+            let mut board = clean.clone();
+            board.set(&attacker, Space::Occupied(Role::Attacker));
+            assert_eq!(board.attacker_corner_penalties(0.5), -0.5);
+            assert_eq!(board.attacker_corner_penalties(2.0), -2.0);
+            all.set(&attacker, Space::Occupied(Role::Attacker));
+            // a piece beyond the attacker protects it
+            board.set(&beyond, Space::Occupied(Role::Attacker));
+            assert_eq!(board.attacker_corner_penalties(0.5), 0.0);
+            board.set(&beyond, Space::Occupied(Role::Defender));
+            assert_eq!(board.attacker_corner_penalties(0.5), 0.0);
+            // defenders next to the corner are not penalized
+            board.set(&attacker, Space::Occupied(Role::Defender));
+            board.set(&beyond, Space::Empty);
+            assert_eq!(board.attacker_corner_penalties(0.5), 0.0);
+        }
+        assert_eq!(all.attacker_corner_penalties(0.5), -4.0);
+    }
+
+    /// Test counting the boards equivalent to a board under symmetry
+    #[test]
+    fn test_symmetry_class_size() {
+        assert_eq!(Board::default().symmetry_class_size(), 1);
+        let board = Board::try_from([
+            ".K.........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(board.symmetry_class_size(), 8);
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        assert_eq!(board.symmetry_class_size(), 1);
+    }
+
     /// Test the bitboard representation of `Board`
     #[test]
     fn test_bitboard() {
@@ -1647,4 +4461,23 @@ mod test_board {
         ];
         assert_eq!(bitboard, expected);
     }
+
+    /// Test that flipping the perspective swaps the attackers and
+    /// defenders and leaves the king
+    #[test]
+    fn test_flip_perspective() {
+        let board = Board::default();
+        let flipped = board.flip_perspective();
+        assert_eq!(flipped.material(), (12, 25));
+        assert_eq!(
+            flipped.get(&Square { x: 0, y: 3 }),
+            Space::Occupied(Role::Defender)
+        );
+        assert_eq!(
+            flipped.get(&Square { x: 5, y: 3 }),
+            Space::Occupied(Role::Attacker)
+        );
+        assert_eq!(flipped.get(&THRONE), Space::King);
+        assert_eq!(flipped.flip_perspective(), board);
+    }
 }
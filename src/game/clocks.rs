@@ -0,0 +1,93 @@
+//! Clocks for timed games. A side's clock runs down while they think
+//! about their move and gains an increment once it has been made.
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::space::Role;
+
+/// How many more moves the engine assumes it must make with the time
+/// it has left
+const MOVES_TO_PLAN_FOR: u32 = 30;
+
+/// The time each side has left to make the rest of their moves
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Clocks {
+    pub attacker: Duration,
+    pub defender: Duration,
+    /// The time added to a side's clock after each of their moves
+    pub increment: Duration,
+}
+
+impl Clocks {
+    /// Give both sides the same amount of time
+    pub fn new(time: Duration, increment: Duration) -> Self {
+        Self {
+            attacker: time,
+            defender: time,
+            increment,
+        }
+    }
+
+    /// The time player `role` has left
+    pub fn remaining(&self, role: Role) -> Duration {
+        match role {
+            Role::Attacker => self.attacker,
+            Role::Defender => self.defender,
+        }
+    }
+
+    fn remaining_mut(&mut self, role: Role) -> &mut Duration {
+        match role {
+            Role::Attacker => &mut self.attacker,
+            Role::Defender => &mut self.defender,
+        }
+    }
+
+    /// Take the time player `role` spent thinking off their clock.
+    /// Returns false if they ran out of time.
+    pub fn charge(&mut self, role: Role, elapsed: Duration) -> bool {
+        let remaining = self.remaining_mut(role);
+        *remaining = remaining.saturating_sub(elapsed);
+        !remaining.is_zero()
+    }
+
+    /// Add the increment to the clock of player `role` after their move
+    pub fn add_increment(&mut self, role: Role) {
+        let increment = self.increment;
+        *self.remaining_mut(role) += increment;
+    }
+
+    /// How long player `role` can afford to spend on their next move
+    pub fn budget(&self, role: Role) -> Duration {
+        let remaining = self.remaining(role);
+        (remaining / MOVES_TO_PLAN_FOR + self.increment).min(remaining)
+    }
+}
+
+#[cfg(test)]
+mod test_clocks {
+    use super::*;
+
+    /// Test that thinking runs down the mover's clock only, and that
+    /// the increment is added afterwards
+    #[test]
+    fn test_charge() {
+        let mut clocks = Clocks::new(Duration::from_secs(10), Duration::from_secs(2));
+        assert!(clocks.charge(Role::Attacker, Duration::from_secs(4)));
+        clocks.add_increment(Role::Attacker);
+        assert_eq!(clocks.remaining(Role::Attacker), Duration::from_secs(8));
+        assert_eq!(clocks.remaining(Role::Defender), Duration::from_secs(10));
+        assert!(!clocks.charge(Role::Defender, Duration::from_secs(11)));
+        assert_eq!(clocks.remaining(Role::Defender), Duration::ZERO);
+    }
+
+    /// Test that a move is never budgeted more time than is left
+    #[test]
+    fn test_budget() {
+        let clocks = Clocks::new(Duration::from_secs(60), Duration::from_secs(1));
+        assert_eq!(clocks.budget(Role::Attacker), Duration::from_secs(3));
+        let clocks = Clocks::new(Duration::from_millis(500), Duration::from_secs(1));
+        assert_eq!(clocks.budget(Role::Defender), Duration::from_millis(500));
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::game::board::Board;
-use crate::game::space::{EXIT_SQUARES, Role, Space, Square, SquareMap};
+use crate::game::space::{EXIT_SQUARES, Role, Space, Square, SquareMap, SquareSet};
 use rayon::iter::Either;
 
 /// Given a board state, we find the maximum flow
@@ -9,7 +9,7 @@ use rayon::iter::Either;
 /// ignoring the defenders. This is a value between 0 and 8
 /// inclusive.
 pub fn escape_routes(board: &Board) -> u8 {
-    let Some(king) = board.find_the_king() else {
+    let Some(king) = board.king_square() else {
         return 0;
     };
 
@@ -19,60 +19,80 @@ pub fn escape_routes(board: &Board) -> u8 {
         .sum()
 }
 
-fn get_neighbors<F>(board: &Board, square: Square, predicate: F) -> [Option<Square>; 4]
+/// Given a board state, we count the corners the king can slide into along
+/// at least two independent paths, so that the attackers can't cut him off
+/// from them by occupying any one square. Unlike [`escape_routes`], this
+/// respects that the king moves like a rook through empty squares only:
+/// every path through empty squares can be travelled in slides, so this is
+/// the same minimum vertex cut as [`Board::min_block_squares`], one corner
+/// at a time and capped at two.
+///
+/// This is a value between 0 and 4 inclusive.
+pub fn sliding_escape_routes(board: &Board) -> u8 {
+    let Some(king) = board.king_square() else {
+        return 0;
+    };
+    let capacity = |sq: &Square| {
+        if sq.is_restricted() { UNBLOCKABLE } else { 1 }
+    };
+    EXIT_SQUARES
+        .into_iter()
+        .filter(|corner| {
+            // the king's slides end in the first corner he enters
+            let passable =
+                |sq: &Square| board.get(sq) == Space::Empty && (!sq.is_exit() || sq == corner);
+            min_vertex_cut(board, king, &[*corner], passable, capacity, 2) == 2
+        })
+        .count() as u8
+}
+
+/// The neighbors of `square` satisfying `predicate`, in the same order as
+/// [`Square::orthogonal_neighbors`]
+fn get_neighbors<F>(board: &Board, square: Square, predicate: F) -> impl Iterator<Item = Square>
 where
     F: Fn(&Board, Square) -> bool,
 {
-    let mut neighbors = [None; 4];
-    if let Some(sq) = square.up() {
-        if predicate(board, sq) {
-            neighbors[0] = Some(sq);
-        }
-    }
-    if let Some(sq) = square.left() {
-        if predicate(board, sq) {
-            neighbors[1] = Some(sq);
-        }
-    }
-    if let Some(sq) = square.right() {
-        if predicate(board, sq) {
-            neighbors[2] = Some(sq);
-        }
-    }
-    if let Some(sq) = square.down() {
-        if predicate(board, sq) {
-            neighbors[3] = Some(sq);
-        }
-    }
-    neighbors
+    square
+        .orthogonal_neighbors()
+        .filter(move |sq| predicate(board, *sq))
 }
 
 type Predecessor = SquareMap<Square>;
 
+/// The flow along each edge of the grid. Flows only ever exist between
+/// neighboring squares, so we store one value per direction for each square.
 #[derive(Debug)]
 struct EdgeFlows {
-    flows: [[i64; 121]; 121],
+    flows: [[i64; 4]; 121],
 }
 
 impl Default for EdgeFlows {
     fn default() -> Self {
         Self {
-            flows: [[0; 121]; 121],
+            flows: [[0; 4]; 121],
         }
     }
 }
 
 impl EdgeFlows {
-    fn get(&self, [f, s]: &[Square; 2]) -> i64 {
-        let f_ix = f.y * 11 + f.x;
-        let s_ix = s.y * 11 + s.x;
-        self.flows[f_ix][s_ix]
+    /// The index of the direction from the first square to the second,
+    /// in the same order as [`Square::orthogonal_neighbors`].
+    fn direction([f, s]: &[Square; 2]) -> usize {
+        match (s.x as i64 - f.x as i64, s.y as i64 - f.y as i64) {
+            (0, -1) => 0,
+            (-1, 0) => 1,
+            (1, 0) => 2,
+            (0, 1) => 3,
+            _ => unreachable!("Flows only exist between neighboring squares"),
+        }
+    }
+
+    fn get(&self, edge: &[Square; 2]) -> i64 {
+        self.flows[edge[0].to_index()][Self::direction(edge)]
     }
 
-    fn insert(&mut self, [f, s]: [Square; 2], flow: i64) {
-        let f_ix = f.y * 11 + f.x;
-        let s_ix = s.y * 11 + s.x;
-        self.flows[f_ix][s_ix] = flow;
+    fn insert(&mut self, edge: [Square; 2], flow: i64) {
+        self.flows[edge[0].to_index()][Self::direction(&edge)] = flow;
     }
 }
 
@@ -93,10 +113,7 @@ fn edmonds_karp(board: &Board, king: Square, corner: Square) -> u8 {
                     board.get(&sq),
                     Space::Empty | Space::Occupied(Role::Defender),
                 )
-            })
-            .into_iter()
-            .flatten()
-            {
+            }) {
                 if !pred.contains_key(&n) && 1i64 > flow.get(&[square, n]) {
                     pred.insert(n, square);
                     queue.push_back(n);
@@ -135,11 +152,125 @@ fn edmonds_karp(board: &Board, king: Square, corner: Square) -> u8 {
     flow_total
 }
 
+/// A capacity larger than any cut of the board
+const UNBLOCKABLE: i64 = 121;
+
+impl Board {
+    /// The fewest empty squares the attackers would need to occupy to cut
+    /// every path from the king to an escape square, found as a minimum
+    /// vertex cut. Defenders are passed through as in [`escape_routes`],
+    /// but can't be blocked, nor can restricted squares. The search stops
+    /// once the cut reaches `cap`, so larger cuts and paths which can't be
+    /// blocked at all are reported as `cap`.
+    pub fn min_block_squares(&self, cap: u8) -> u8 {
+        let Some(king) = self.king_square() else {
+            return 0;
+        };
+        let passable =
+            |sq: &Square| matches!(self.get(sq), Space::Empty | Space::Occupied(Role::Defender));
+        let capacity = |sq: &Square| {
+            if self.get(sq) == Space::Empty && !sq.is_restricted() {
+                1
+            } else {
+                UNBLOCKABLE
+            }
+        };
+        min_vertex_cut(self, king, &EXIT_SQUARES, passable, capacity, cap)
+    }
+}
+
+/// The fewest squares which must be blocked to cut every path from `start`
+/// to the `targets` through `passable` squares, where blocking a square
+/// takes its `capacity`. The search stops once the cut reaches `cap`, so
+/// larger cuts and paths which can't be blocked at all are reported as `cap`.
+fn min_vertex_cut(
+    board: &Board,
+    start: Square,
+    targets: &[Square],
+    passable: impl Fn(&Square) -> bool,
+    capacity: impl Fn(&Square) -> i64,
+    cap: u8,
+) -> u8 {
+    // first see if there is a path without any square which could be blocked
+    let mut open = SquareSet::default();
+    open.add(start);
+    let mut queue = VecDeque::from([start]);
+    while let Some(square) = queue.pop_front() {
+        for sq in get_neighbors(board, square, |_, sq| {
+            passable(&sq) && capacity(&sq) == UNBLOCKABLE
+        }) {
+            if targets.contains(&sq) {
+                return cap;
+            }
+            if !open.contains(&sq) {
+                open.add(sq);
+                queue.push_back(sq);
+            }
+        }
+    }
+    // each square is split into an entry and an exit joined by the
+    // square's capacity, so that cutting edges means blocking squares.
+    // Every augmenting path then carries a single unit of flow.
+    let mut through = [0i64; 121];
+    let mut flow = EdgeFlows::default();
+    // the previous node on the path to each square's entry and exit
+    let mut pred = [[None; 2]; 121];
+    let mut total = 0u8;
+    while total < cap {
+        pred.fill([None; 2]);
+        let mut queue = VecDeque::from([(start, true)]);
+        let mut reached = None;
+        while let Some((square, out)) = queue.pop_front() {
+            if !out && targets.contains(&square) {
+                reached = Some(square);
+                break;
+            }
+            let mut visit = |node: (Square, bool)| {
+                let prev = &mut pred[node.0.to_index()][node.1 as usize];
+                if node != (start, true) && prev.is_none() {
+                    *prev = Some((square, out));
+                    queue.push_back(node);
+                }
+            };
+            if out {
+                // on to the neighbors, or back into the square
+                get_neighbors(board, square, |_, sq| passable(&sq))
+                    .for_each(|sq| visit((sq, false)));
+                if through[square.to_index()] > 0 {
+                    visit((square, false));
+                }
+            } else {
+                // through the square, or back along flow into it
+                get_neighbors(board, square, |_, sq| flow.get(&[sq, square]) > 0)
+                    .for_each(|sq| visit((sq, true)));
+                if through[square.to_index()] < capacity(&square) {
+                    visit((square, true));
+                }
+            }
+        }
+        let Some(target) = reached else {
+            return total;
+        };
+        let mut node = (target, false);
+        while let Some(prev) = pred[node.0.to_index()][node.1 as usize] {
+            if prev.0 == node.0 {
+                through[node.0.to_index()] += if node.1 { 1 } else { -1 };
+            } else {
+                flow.insert([prev.0, node.0], flow.get(&[prev.0, node.0]) + 1);
+                flow.insert([node.0, prev.0], flow.get(&[node.0, prev.0]) - 1);
+            }
+            node = prev;
+        }
+        total += 1;
+    }
+    total
+}
+
 /// Given a board state, we find out the shortest path from the king to an
 /// escape square if any exists.
 #[allow(dead_code)]
 pub fn shortest_escape(board: &Board) -> Option<u8> {
-    let king = board.find_the_king()?;
+    let king = board.king_square()?;
     let mut queue = VecDeque::from([king]);
     let mut pred = HashMap::<Square, Square>::new();
     let mut escape = None;
@@ -147,10 +278,7 @@ pub fn shortest_escape(board: &Board) -> Option<u8> {
     while let Some(square) = queue.pop_front() {
         for n in get_neighbors(board, square, |board, sq| {
             matches!(board.get(&sq), Space::Empty)
-        })
-        .into_iter()
-        .flatten()
-        {
+        }) {
             if let std::collections::hash_map::Entry::Vacant(e) = pred.entry(n) {
                 e.insert(square);
                 if EXIT_SQUARES.contains(&n) {
@@ -179,7 +307,7 @@ pub fn shortest_escape(board: &Board) -> Option<u8> {
 /// the path with fewest "turns" or "corners" to an exit square.
 pub fn fewest_turns_to_escape(board: &Board) -> Option<u8> {
     let mut current_turns = 1u8;
-    let king = board.find_the_king()?;
+    let king = board.king_square()?;
     let mut visited = HashSet::from([king]);
     let mut starts = HashSet::from([king]);
     loop {
@@ -349,6 +477,186 @@ mod test_heuristics {
         assert_eq!(flow, 5);
     }
 
+    /// Test the number of squares the attackers need to seal the king in,
+    /// and that occupying one of them brings it down
+    #[test]
+    fn test_min_block_squares() {
+        // the only way to a corner is through A2
+        let board = [
+            ".O.......O.",
+            "..........O",
+            "OO.........",
+            "K..........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "O.........O",
+            ".O.......O.",
+        ];
+        let mut board = Board::try_from(board).expect("Test failed");
+        assert_eq!(board.min_block_squares(u8::MAX), 1);
+        board.set(&Square { x: 0, y: 1 }, Space::Occupied(Role::Attacker));
+        assert_eq!(board.min_block_squares(u8::MAX), 0);
+        assert_eq!(escape_routes(&board), 0);
+
+        // the king is walled in below, so each of his three open
+        // neighbors must be blocked
+        let board = [
+            "...O.......",
+            "...O.......",
+            "..K........",
+            "OOOO.......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let mut board = Board::try_from(board).expect("Test failed");
+        assert_eq!(board.min_block_squares(u8::MAX), 3);
+        for (blocks, square) in [(2, (2, 1)), (1, (1, 2)), (0, (3, 2))] {
+            let (x, y) = square;
+            board.set(&Square { x, y }, Space::Occupied(Role::Attacker));
+            assert_eq!(board.min_block_squares(u8::MAX), blocks);
+        }
+        assert_eq!(Board::default().min_block_squares(u8::MAX), 8);
+        // the search stops at the cap
+        assert_eq!(Board::default().min_block_squares(3), 3);
+
+        // the defenders lead the king to a corner
+        let board = [
+            "...........",
+            "X..........",
+            "K..........",
+            "OO.........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(board.min_block_squares(u8::MAX), u8::MAX);
+        assert_eq!(board.min_block_squares(8), 8);
+    }
+
+    /// Check that the flows only need a small allocation per call
+    #[test]
+    fn test_edge_flows_size() {
+        assert_eq!(
+            std::mem::size_of::<EdgeFlows>(),
+            121 * 4 * std::mem::size_of::<i64>()
+        );
+        assert!(std::mem::size_of::<EdgeFlows>() * 30 < 121 * 121 * std::mem::size_of::<i64>());
+    }
+
+    /// A micro-benchmark of computing the escape routes. Run with
+    /// `cargo test --release bench_escape_routes -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_escape_routes() {
+        const RUNS: u32 = 1000;
+        let board = Board::default();
+        let start = std::time::Instant::now();
+        for _ in 0..RUNS {
+            assert_eq!(escape_routes(std::hint::black_box(&board)), 8);
+        }
+        println!(
+            "escape_routes: {:?} per call, {} bytes of flows per corner",
+            start.elapsed() / RUNS,
+            std::mem::size_of::<EdgeFlows>()
+        );
+    }
+
+    /// Test that sliding escapes need two independent paths to a corner,
+    /// which the flow overestimates by passing through defenders
+    #[test]
+    fn test_sliding_flows() {
+        // the defenders block every slide, but the flow passes through them
+        let board = Board::default();
+        assert_eq!(escape_routes(&board), 8);
+        assert_eq!(sliding_escape_routes(&board), 0);
+
+        // on an open board, no one square cuts the king off from a corner
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...K.......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(escape_routes(&board), 8);
+        assert_eq!(sliding_escape_routes(&board), 4);
+
+        // the king has a single open neighbor, so blocking it cuts
+        // off every corner he could otherwise slide into
+        let board = [
+            "...........",
+            "...........",
+            "...O.......",
+            "..OK.......",
+            "...O.......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(escape_routes(&board), 4);
+        assert_eq!(sliding_escape_routes(&board), 0);
+
+        // only one side of the corner is reachable
+        let board = [
+            ".O.......O.",
+            "..........O",
+            "OO.........",
+            "K..........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "O.........O",
+            ".O.......O.",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(escape_routes(&board), 1);
+        assert_eq!(sliding_escape_routes(&board), 0);
+
+        // the king is boxed in with one corner, which he can slide into
+        // along either of its edges
+        let board = [
+            "....O......",
+            "....O......",
+            "..K.O......",
+            "....O......",
+            "OOOO.......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        assert_eq!(sliding_escape_routes(&board), 1);
+    }
+
     #[test]
     fn test_escape_distance() {
         let board = Board::default();
@@ -1,21 +1,35 @@
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use board::Board;
-use rustc_hash::FxHashSet;
-use serde::{Deserialize, Serialize};
+use board::{Board, MOVE_LIMIT};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
-use crate::alpha_beta::alphabeta;
-use crate::alpha_beta::heuristic::HeuristicPolicy;
-use crate::game::space::{Role, Square};
-pub use crate::game::symmetries::{NormalizedBoardMap, NormalizedBoards};
+use crate::alpha_beta::heuristic::{HeuristicPolicy, heuristic};
+use crate::alpha_beta::{ScoredFromSideToMove, alphabeta, aspiration_search};
+use crate::game::clocks::Clocks;
+use crate::game::opening_book::OpeningBook;
+use crate::game::rules::Rules;
+use crate::game::space::{EXIT_SQUARES, Role, Square};
+#[cfg(test)]
+pub use crate::game::symmetries::NormalizedBoardMap;
+pub use crate::game::symmetries::NormalizedBoards;
+use crate::game::tablebase::Tablebase;
 use crate::game_tree::{GameSummary, GameTreeNode};
 use crate::mcts::scaled_i64_to_float;
 
 pub mod board;
+pub mod clocks;
 pub mod heuristics;
+pub mod opening_book;
+pub mod rules;
+pub mod saved;
 pub mod space;
 mod symmetries;
+pub mod tablebase;
+mod zobrist;
 
 #[derive(Error, Debug)]
 pub enum PlayError {
@@ -27,18 +41,62 @@ pub enum PlayError {
     StraightLine,
     #[error("The start and end squares for a move piece cannot be the same")]
     DidntMove,
-    #[error("Attempted to move a piece belonging to the opposite player")]
-    WrongTurn,
-    #[error("Attempted to move a piece through another piece")]
-    MoveThroughPiece,
+    #[error("Attempted to move the {found} on {square}, which belongs to the opposite player")]
+    WrongTurn { square: Square, found: Role },
+    #[error("There is no piece on {0} to move")]
+    EmptySquare(Square),
+    #[error("Attempted to move a piece through the piece on {0}")]
+    MoveThroughPiece(Square),
     #[error("Only the king may move to a restricted square")]
     RestrictedSquare,
     #[error("A defender can't repeat a board position")]
     RepeatedPosition,
+    #[error("The king may move at most {0} squares at a time")]
+    KingMoveTooFar(u8),
+}
+
+/// The positions seen so far in a game, along with how many
+/// times each of them has occurred
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PreviousBoards(pub FxHashMap<Board, usize>);
+
+impl FromIterator<Board> for PreviousBoards {
+    fn from_iter<T: IntoIterator<Item = Board>>(iter: T) -> Self {
+        let mut boards = Self::default();
+        for board in iter {
+            *boards.0.entry(board).or_default() += 1;
+        }
+        boards
+    }
+}
+
+/// The iteration order of the map is not deterministic, so the boards
+/// are sorted by their bitboards to give reproducible output. A board
+/// is written once for each time it occurred.
+impl Serialize for PreviousBoards {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut boards = self.0.iter().collect::<Vec<_>>();
+        boards.sort_unstable_by_key(|(board, _)| board.as_bitboard());
+        serializer.collect_seq(
+            boards
+                .into_iter()
+                .flat_map(|(board, count)| std::iter::repeat_n(board, *count)),
+        )
+    }
 }
 
-#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
-pub struct PreviousBoards(pub FxHashSet<Board>);
+impl<'de> Deserialize<'de> for PreviousBoards {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let boards = Vec::<Board>::deserialize(deserializer)?;
+        Ok(boards.into_iter().collect())
+    }
+}
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum PositionsTracker {
@@ -57,11 +115,20 @@ impl PositionsTracker {
     pub fn insert(&mut self, board: &Board) {
         match self {
             PositionsTracker::Previous(prev) => {
-                _ = prev.0.insert(board.clone());
+                *prev.0.entry(board.clone()).or_default() += 1;
             }
             PositionsTracker::Counter(moves) => *moves += 1,
         }
     }
+
+    /// The number of times the board has occurred. Without the
+    /// previous boards, this is unknown and taken to be zero.
+    pub fn occurrences(&self, board: &Board) -> usize {
+        match self {
+            PositionsTracker::Previous(prev) => prev.0.get(board).copied().unwrap_or_default(),
+            PositionsTracker::Counter(_) => 0,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize, Hash)]
@@ -71,6 +138,23 @@ pub enum Status {
     Ongoing,
     DefendersWin,
     Draw,
+    /// The given side conceded the game
+    Resigned(Role),
+}
+
+impl Status {
+    /// The reward for `role` if the game has finished: `1.0` for a win,
+    /// `-1.0` for a loss and `0.0` for a draw
+    pub fn result_for(&self, role: &Role) -> Option<f64> {
+        let winner = match self {
+            Status::Ongoing => return None,
+            Status::Draw => return Some(0.0),
+            Status::AttackersWin => Role::Attacker,
+            Status::DefendersWin => Role::Defender,
+            Status::Resigned(loser) => loser.opposite(),
+        };
+        Some(if winner == *role { 1.0 } else { -1.0 })
+    }
 }
 
 impl Display for Status {
@@ -80,7 +164,95 @@ impl Display for Status {
             Status::Ongoing => f.write_str("Game ongoing"),
             Status::DefendersWin => f.write_str("Defenders win"),
             Status::Draw => f.write_str("Draw"),
+            Status::Resigned(Role::Attacker) => f.write_str("Attackers resigned"),
+            Status::Resigned(Role::Defender) => f.write_str("Defenders resigned"),
+        }
+    }
+}
+
+/// How the winner of a game won
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum WinReason {
+    /// The king reached a corner
+    KingEscaped,
+    /// The king built an unbreakable fort on the edge
+    ExitFort,
+    /// The king was surrounded on all four sides
+    KingCaptured,
+    /// The attackers enclosed all the defenders
+    Surrounded,
+    /// The losing side had no legal moves
+    NoMoves,
+    /// The losing side conceded
+    Resigned,
+    /// The losing side ran out of time
+    TimeOut,
+}
+
+impl Display for WinReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WinReason::KingEscaped => f.write_str("the king escaped"),
+            WinReason::ExitFort => f.write_str("the king built an exit fort"),
+            WinReason::KingCaptured => f.write_str("the king was captured"),
+            WinReason::Surrounded => f.write_str("the defenders were surrounded"),
+            WinReason::NoMoves => f.write_str("the losing side had no moves"),
+            WinReason::Resigned => f.write_str("the losing side resigned"),
+            WinReason::TimeOut => f.write_str("the losing side ran out of time"),
+        }
+    }
+}
+
+/// The rule that ended a game in a draw
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum DrawReason {
+    /// The attackers repeated a position too often
+    Repetition,
+    /// The game went on for too many moves
+    MoveLimit,
+    /// Both sides agreed to a draw
+    Agreement,
+}
+
+impl Display for DrawReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DrawReason::Repetition => f.write_str("the attackers repeated a position"),
+            DrawReason::MoveLimit => f.write_str("the move limit was reached"),
+            DrawReason::Agreement => f.write_str("both sides agreed to a draw"),
+        }
+    }
+}
+
+/// A summary of a finished game
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GameResult {
+    /// The winning side, if the game wasn't drawn
+    pub winner: Option<Role>,
+    pub win_reason: Option<WinReason>,
+    /// Why the game was drawn, if it was and the reason is known, e.g. not
+    /// for a game loaded as already drawn
+    pub draw_reason: Option<DrawReason>,
+    /// The number of moves played by both sides
+    pub plies: usize,
+    /// The number of attackers and defenders (including the king) left
+    pub material: (u8, u8),
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ended after {} moves", self.plies)?;
+        if let Some(reason) = self.win_reason {
+            write!(f, " as {reason}")?;
+        }
+        if let Some(reason) = self.draw_reason {
+            write!(f, " as {reason}")?;
         }
+        let (attackers, defenders) = self.material;
+        write!(
+            f,
+            ", with {attackers} attackers and {defenders} defenders left"
+        )
     }
 }
 
@@ -92,6 +264,35 @@ pub struct Play {
 }
 
 impl Play {
+    /// A key for listing plays in a stable, human friendly order: by side,
+    /// then by the starting square and then the destination. Squares are
+    /// ordered by column and then by row from the bottom, as they are
+    /// written, so `A1->A2` comes before `A1->B1` which comes before `B1->B2`.
+    pub fn sort_key(&self) -> (Role, [usize; 4]) {
+        (
+            self.role,
+            [self.from.x, 10 - self.from.y, self.to.x, 10 - self.to.y],
+        )
+    }
+
+    /// Write the play as its starting and ending squares, e.g. `F6->F2`
+    pub fn to_algebraic(&self) -> String {
+        format!("{}->{}", self.from, self.to)
+    }
+
+    /// Read a play by `role` written as its starting and ending squares,
+    /// e.g. `F6->F2`. Squares may be written in lower case.
+    pub fn from_algebraic(s: &str, role: Role) -> anyhow::Result<Self> {
+        let Some((from, to)) = s.split_once("->") else {
+            return Err(anyhow::Error::msg(format!("Could not parse input '{s}'")));
+        };
+        Ok(Self {
+            role,
+            from: Square::from_str(from.trim())?,
+            to: Square::from_str(to.trim())?,
+        })
+    }
+
     pub fn valid(&self) -> Result<(), PlayError> {
         if std::cmp::max(self.from.x, self.from.y) > 10 {
             return Err(PlayError::InvalidSquare);
@@ -114,10 +315,20 @@ impl Play {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct EngineRole {
     engine: HeuristicPolicy,
     role: Role,
+    /// Discard moves leading to positions symmetric to ones already searched
+    normalize: bool,
+    /// How many plies deep the engine searches each move
+    depth: usize,
+}
+
+impl Default for EngineRole {
+    fn default() -> Self {
+        Role::default().into()
+    }
 }
 
 impl From<Role> for EngineRole {
@@ -125,10 +336,44 @@ impl From<Role> for EngineRole {
         Self {
             engine: Default::default(),
             role,
+            normalize: true,
+            depth: ENGINE_DEPTH,
         }
     }
 }
 
+impl EngineRole {
+    /// Consider every move the engine can make rather than one from
+    /// each set of symmetric moves
+    pub fn without_symmetry(self) -> Self {
+        Self {
+            normalize: false,
+            ..self
+        }
+    }
+}
+
+/// The default number of times the attackers may bring about the same
+/// position without making progress before the game is drawn.
+pub const ATTACKER_REPETITION_LIMIT: usize = 3;
+
+/// The engine only accepts a draw if its evaluation of the position is
+/// at most this far from even, as a reward
+pub const DRAW_ACCEPTANCE_MARGIN: f64 = 0.5;
+
+/// How many plies deep the engine searches each move by default
+const ENGINE_DEPTH: usize = 3;
+
+/// How many times longer the engine assumes searching one ply deeper takes
+const DEPTH_TIME_FACTOR: u32 = 10;
+
+/// How far either side of the score from the last depth the engine
+/// first looks for the score at the next, as a scaled reward
+const ASPIRATION_MARGIN: i64 = 1_000_000;
+
+/// How many of the latest moves are listed beside the board on the CLI
+pub const RECENT_MOVES: usize = 10;
+
 /// A UI friendly version of a game for playing on the CLI
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct LiveGame {
@@ -139,6 +384,21 @@ pub struct LiveGame {
     pub turn: Role,
     pub current_board: Board,
     pub engine: Option<EngineRole>,
+    /// The number of occurrences of a position after an attacker's move,
+    /// with no captures or king moves in between, which results in a draw.
+    pub attacker_repetition_limit: usize,
+    /// Moves the engine plays without searching
+    pub opening_book: OpeningBook,
+    /// The results of endgames with few pieces, played without searching
+    pub tablebase: Tablebase,
+    pub rules: Rules,
+    /// The side which offered a draw, if any. It remains set if the offer
+    /// was accepted.
+    pub draw_offer: Option<Role>,
+    /// The time each side has left, if the game is timed
+    pub clocks: Option<Clocks>,
+    /// When the side to move started thinking, once the clocks are running
+    pub turn_started: Option<Instant>,
 }
 
 impl Default for LiveGame {
@@ -151,6 +411,13 @@ impl Default for LiveGame {
             turn: Default::default(),
             current_board: Default::default(),
             engine: None,
+            attacker_repetition_limit: ATTACKER_REPETITION_LIMIT,
+            opening_book: Default::default(),
+            tablebase: Default::default(),
+            rules: Default::default(),
+            draw_offer: None,
+            clocks: None,
+            turn_started: None,
         }
     }
 }
@@ -159,40 +426,262 @@ impl Display for LiveGame {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(&format!("Status: {}\n", self.status))?;
         f.write_str(&format!("Turn: {}\n", self.turn))?;
-        self.current_board.fmt(f)
+        if let Some(clocks) = &self.clocks {
+            f.write_str(&format!(
+                "Time: attackers {}s, defenders {}s\n",
+                clocks.attacker.as_secs(),
+                clocks.defender.as_secs()
+            ))?;
+        }
+        f.write_str(self.render_with_moves(RECENT_MOVES).trim_end())
     }
 }
 
+/// The node carries the game's full position history, so that the
+/// search respects the repetition rule exactly as live play does.
 impl From<&mut LiveGame> for GameTreeNode {
     fn from(game: &mut LiveGame) -> Self {
         GameTreeNode {
             status: game.status,
-            previous_boards: PositionsTracker::Counter(game.previous_boards.len()),
+            previous_boards: game.previous_boards.clone(),
             turn: game.turn,
             current_board: game.current_board.clone(),
+            rules: game.rules,
+            recent_boards: Default::default(),
         }
     }
 }
 
 impl LiveGame {
-    /// Play a move and update the game state
+    /// Play a move and update the game state. If the game is timed, the
+    /// mover is charged for the time since their turn started.
     pub fn play(&mut self, play: &Play) -> anyhow::Result<()> {
-        let current = self.current_board.clone();
-        let (_, status) = self
-            .current_board
-            .play(play, &self.status, &mut self.previous_boards)?;
+        let elapsed = self
+            .turn_started
+            .map(|started| started.elapsed())
+            .unwrap_or_default();
+        self.play_timed(play, elapsed)
+    }
+
+    /// Start the clocks running for the side to move
+    pub fn start_clocks(&mut self) {
+        if self.clocks.is_some() {
+            self.turn_started = Some(Instant::now());
+        }
+    }
+
+    /// Play a move which took `elapsed` to think of. If the mover runs
+    /// out of time, they lose and the move is not made.
+    pub fn play_timed(&mut self, play: &Play, elapsed: Duration) -> anyhow::Result<()> {
+        let (board, _, status) = self.current_board.play_internal_with_rules(
+            play,
+            &self.status,
+            &self.previous_boards,
+            &self.rules,
+        )?;
+        if let Some(clocks) = &mut self.clocks {
+            if !clocks.charge(play.role, elapsed) {
+                self.status = match play.role {
+                    Role::Attacker => Status::DefendersWin,
+                    Role::Defender => Status::AttackersWin,
+                };
+                return Ok(());
+            }
+            clocks.add_increment(play.role);
+            self.turn_started = Some(Instant::now());
+        }
+        if tracing::enabled!(tracing::Level::DEBUG)
+            && let Ok(report) = self
+                .current_board
+                .capture_report_with_rules(play, &self.rules)
+        {
+            for capture in report.captures {
+                tracing::debug!(?capture, "captured a piece");
+            }
+        }
+        self.previous_boards.insert(&board);
+        let current = std::mem::replace(&mut self.current_board, board);
         self.history.push(current);
         self.ahead.clear();
         self.turn = self.turn.opposite();
         self.status = status;
+        self.check_attacker_repetition(play.role);
+        // playing on declines the opponent's offer
+        if self.draw_offer == Some(play.role.opposite()) {
+            self.draw_offer = None;
+        }
         Ok(())
     }
 
+    /// The player whose turn it is concedes the game
+    pub fn resign(&mut self) {
+        if self.status == Status::Ongoing {
+            self.status = Status::Resigned(self.turn);
+        }
+    }
+
+    /// The player whose turn it is offers a draw. If the opponent already
+    /// offered one, this accepts it instead. If the engine is the opponent,
+    /// it responds straight away.
+    pub fn offer_draw(&mut self) {
+        if self.status != Status::Ongoing {
+            return;
+        }
+        match self.draw_offer {
+            Some(role) if role != self.turn => self.respond_to_draw(true),
+            _ => {
+                self.draw_offer = Some(self.turn);
+                if let Some(engine) = self.engine
+                    && engine.role != self.turn
+                {
+                    let accept = self.engine_accepts_draw();
+                    self.respond_to_draw(accept);
+                }
+            }
+        }
+    }
+
+    /// The opponent of the side offering a draw accepts or declines it
+    pub fn respond_to_draw(&mut self, accept: bool) {
+        if self.draw_offer.is_none() || self.status != Status::Ongoing {
+            return;
+        }
+        if accept {
+            self.status = Status::Draw;
+        } else {
+            self.draw_offer = None;
+        }
+    }
+
+    /// The engine accepts a draw if it judges the position to be
+    /// roughly even
+    fn engine_accepts_draw(&mut self) -> bool {
+        if self.engine.is_none() {
+            return false;
+        }
+        // how close to even doesn't depend on whose point of view it is from
+        let eval = heuristic(&GameTreeNode::from(&mut *self));
+        scaled_i64_to_float(eval.0).abs() <= DRAW_ACCEPTANCE_MARGIN
+    }
+
+    /// A key for the current position and the player to move, however
+    /// the game got there. Symmetric positions share a key.
+    #[cfg(test)]
+    pub fn position_key(&self) -> (u64, Role) {
+        (self.current_board.canonical_zobrist(), self.turn)
+    }
+
+    /// Count the number of times the current position has occurred with the
+    /// same player to move since the last capture or king move.
+    pub fn repetitions(&self) -> usize {
+        let mut count = 1;
+        let mut later = &self.current_board;
+        for (ix, board) in self.history.iter().rev().enumerate() {
+            if board.attackers() != later.attackers()
+                || board.defenders() != later.defenders()
+                || board.king_square() != later.king_square()
+            {
+                break;
+            }
+            // every other board in the history has the same player to move
+            if ix & 1 == 1 && *board == self.current_board {
+                count += 1;
+            }
+            later = board;
+        }
+        count
+    }
+
+    /// Summarize the game once it has finished. Returns `None` while it is
+    /// still ongoing.
+    pub fn result(&self) -> Option<GameResult> {
+        let board = &self.current_board;
+        let (winner, win_reason, draw_reason) = match self.status {
+            Status::Ongoing => return None,
+            Status::Draw => {
+                let reason = if self.draw_offer.is_some() {
+                    Some(DrawReason::Agreement)
+                } else if self.repetitions() >= self.attacker_repetition_limit {
+                    Some(DrawReason::Repetition)
+                } else if self.previous_boards.len() >= MOVE_LIMIT {
+                    Some(DrawReason::MoveLimit)
+                } else {
+                    None
+                };
+                (None, None, reason)
+            }
+            Status::Resigned(role) => (Some(role.opposite()), Some(WinReason::Resigned), None),
+            Status::AttackersWin | Status::DefendersWin
+                if self
+                    .clocks
+                    .is_some_and(|clocks| clocks.remaining(self.turn).is_zero()) =>
+            {
+                (Some(self.turn.opposite()), Some(WinReason::TimeOut), None)
+            }
+            Status::AttackersWin => {
+                let reason = if board.capture_the_king(&self.rules) {
+                    WinReason::KingCaptured
+                } else if board.flood_fill_attackers_win() {
+                    WinReason::Surrounded
+                } else {
+                    WinReason::NoMoves
+                };
+                (Some(Role::Attacker), Some(reason), None)
+            }
+            Status::DefendersWin => {
+                let reason = match board.king_square() {
+                    Some(king) if EXIT_SQUARES.contains(&king) => WinReason::KingEscaped,
+                    _ if self.rules.exit_forts && board.is_exit_fort() => WinReason::ExitFort,
+                    _ => WinReason::NoMoves,
+                };
+                (Some(Role::Defender), Some(reason), None)
+            }
+        };
+        Some(GameResult {
+            winner,
+            win_reason,
+            draw_reason,
+            plies: self.history.len(),
+            material: board.material(),
+        })
+    }
+
+    /// Only defenders are barred from repeating positions. If the attackers
+    /// keep shuffling back into the same position, the game is a draw.
+    fn check_attacker_repetition(&mut self, role: Role) {
+        if role == Role::Attacker
+            && self.status == Status::Ongoing
+            && self.repetitions() >= self.attacker_repetition_limit
+        {
+            self.status = Status::Draw;
+        }
+    }
+
+    /// Suggest a move for the side to move with its evaluation, searching
+    /// with the attached engine or a heuristic one if there is none. The
+    /// game is left unchanged.
+    pub fn hint(&mut self) -> Option<(Play, ScoredFromSideToMove)> {
+        if self.status != Status::Ongoing {
+            return None;
+        }
+        let EngineRole { engine, depth, .. } = self.engine.unwrap_or_default();
+        GameTreeNode::from(&mut *self)
+            .evaluate_children(&engine, depth)
+            .into_iter()
+            .next()
+    }
+
     /// If the game has an engine attached, use it to
     /// make a move if it is the engine's turn. Returns
     /// a boolean indicating if the engine played or not.
     pub fn engine_play(&mut self) -> bool {
-        let Some(EngineRole { engine, role }) = self.engine else {
+        let Some(EngineRole {
+            engine,
+            role,
+            normalize,
+            depth,
+        }) = self.engine
+        else {
             return false;
         };
         if self.turn != role {
@@ -202,36 +691,173 @@ impl LiveGame {
             return false;
         }
 
+        if let Some(play) = self
+            .opening_book
+            .choose(&self.current_board, &mut rand::rng())
+            && self.play(&play).is_ok()
+        {
+            println!("Book move: {}", play.to_algebraic());
+            return true;
+        }
+
+        if let Some((play, outcome)) = self.tablebase.best_play(
+            &self.current_board,
+            self.turn,
+            &self.previous_boards,
+            &self.rules,
+        ) && self.play(&play).is_ok()
+        {
+            println!("Tablebase move: {} ({outcome:?})", play.to_algebraic());
+            return true;
+        }
+
+        // search the children in the orientation of the actual board so that
+        // the chosen play can be applied to it
         let root = GameTreeNode::from(&mut *self);
-        let (score, next) = match root.turn {
-            Role::Attacker => root
-                .get_children()
-                .into_iter()
-                .map(|c| (alphabeta::<GameSummary, _, _>(&c, &engine, 3), c))
+        let children = root.get_plays_with(normalize);
+        // each child's score from the last search, used to narrow the
+        // window of the next, deeper one
+        let mut guesses = vec![None; children.len()];
+        let mut search = |depth: usize| {
+            children
+                .iter()
+                .zip(guesses.iter_mut())
+                .map(|((play, c), guess)| {
+                    let score = match *guess {
+                        Some(guess) => {
+                            aspiration_search::<GameSummary, _, _>(
+                                c,
+                                &engine,
+                                depth,
+                                guess,
+                                ASPIRATION_MARGIN,
+                            )
+                            .0
+                        }
+                        None => alphabeta::<GameSummary, _, _>(c, &engine, depth),
+                    };
+                    *guess = Some(score);
+                    // the search scores the child for the opponent, who moves next
+                    (-score, play.clone())
+                })
                 .max_by_key(|c| c.0)
-                .unwrap(),
-            Role::Defender => root
-                .get_children()
-                .into_iter()
-                .map(|c| (alphabeta::<GameSummary, _, _>(&c, &engine, 3), c))
-                .max_by_key(|c| c.0)
-                .unwrap(),
+                .unwrap()
+        };
+        // in a timed game, search less deeply rather than overrun the
+        // time that can be spent on this move
+        let (score, play) = match self.clocks.map(|clocks| clocks.budget(role)) {
+            None => search(depth),
+            Some(budget) => {
+                let start = Instant::now();
+                let mut best = search(1);
+                for depth in 2..=depth {
+                    if start.elapsed() * DEPTH_TIME_FACTOR > budget {
+                        break;
+                    }
+                    best = search(depth);
+                }
+                best
+            }
         };
         println!(
             "Evaluation of best position: {}",
-            scaled_i64_to_float(score)
+            scaled_i64_to_float(score.0)
         );
         println!("Done");
-        let current = self.current_board.clone();
-        self.history.push(current.clone());
-        self.previous_boards.insert(&current);
-        self.ahead.clear();
-        self.turn = next.turn;
-        self.status = next.status;
-        self.current_board = next.current_board;
+        self.play(&play).expect("The engine chose an illegal move");
         true
     }
 
+    /// Play the given moves from the starting position, then rewind to
+    /// the start so that the game can be stepped through with redo. The
+    /// side making the first move starts.
+    pub fn replay(plays: &[Play]) -> anyhow::Result<Self> {
+        let turn = plays.first().map_or(Role::default(), |play| play.role);
+        let mut game = Self::default().with_turn(turn);
+        let mut board = game.current_board.clone();
+        let (boards, status) = board
+            .apply_move_sequence(plays, turn, &mut game.previous_boards)
+            .map_err(|(ix, e)| {
+                anyhow::Error::msg(format!("Move {} can't be played: {e}", ix + 1))
+            })?;
+        // the moves are waiting to be redone, the first on top
+        game.ahead = boards.into_iter().skip(1).chain([board]).rev().collect();
+        game.status = status;
+        Ok(game)
+    }
+
+    /// Hand the move to `turn`, e.g. for variants where the defenders
+    /// move first. If the game is ongoing, its status is checked again
+    /// since the new side to move may have no moves.
+    pub fn with_turn(self, turn: Role) -> Self {
+        let status = match self.status {
+            Status::Ongoing => {
+                self.current_board
+                    .compute_status(turn, &self.previous_boards, &self.rules)
+            }
+            status => status,
+        };
+        Self {
+            status,
+            turn,
+            ..self
+        }
+    }
+
+    /// Start a game from a loaded position with `turn` to move under the
+    /// default rules. The position may already be decided, in which case
+    /// the game is over.
+    pub fn from_position(board: Board, turn: Role) -> Self {
+        let game = Self::default();
+        Self {
+            status: board.compute_status(turn, &game.previous_boards, &game.rules),
+            turn,
+            current_board: board,
+            ..game
+        }
+    }
+
+    /// Draw the board with the last `n` moves listed beside it, the
+    /// moves being recovered from the boards in the history
+    pub fn render_with_moves(&self, n: usize) -> String {
+        let boards = self
+            .history
+            .iter()
+            .chain(std::iter::once(&self.current_board))
+            .collect::<Vec<_>>();
+        let moves = boards
+            .windows(2)
+            .enumerate()
+            .skip(boards.len().saturating_sub(n + 1))
+            .map(
+                |(ix, pair)| match Board::diff(pair[0], pair[1], &self.rules) {
+                    Some((play, _)) => format!("{:3}. {}", ix + 1, play.to_algebraic()),
+                    None => format!("{:3}. ?", ix + 1),
+                },
+            )
+            .collect::<Vec<_>>();
+        let board = self.current_board.to_string();
+        let rows = board.lines().collect::<Vec<_>>();
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        // the board starts with an empty line, so list the moves from the next
+        let mut rendered = String::new();
+        for ix in 0..rows.len().max(moves.len() + 1) {
+            let row = rows.get(ix).copied().unwrap_or_default();
+            match ix.checked_sub(1).and_then(|m| moves.get(m)) {
+                Some(play) => {
+                    let padding = width - row.chars().count();
+                    rendered.push_str(&format!("{row}{}   {play}\n", " ".repeat(padding)));
+                }
+                None => rendered.push_str(&format!("{row}\n")),
+            }
+        }
+        rendered
+    }
+
     /// Undo a move
     pub fn undo(&mut self) {
         if let Some(mut board) = self.history.pop() {
@@ -254,6 +880,47 @@ impl LiveGame {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::game::space::Space;
+
+    /// Test writing plays out and reading them back
+    #[test]
+    fn test_algebraic_round_trip() {
+        let plays = [
+            Play {
+                role: Role::Defender,
+                from: Square { x: 5, y: 5 },
+                to: Square { x: 5, y: 9 },
+            },
+            Play {
+                role: Role::Attacker,
+                from: Square { x: 0, y: 3 },
+                to: Square { x: 1, y: 3 },
+            },
+            Play {
+                role: Role::Defender,
+                from: Square { x: 10, y: 4 },
+                to: Square { x: 10, y: 10 },
+            },
+            Play {
+                role: Role::Defender,
+                from: Square { x: 0, y: 7 },
+                to: Square { x: 0, y: 0 },
+            },
+        ];
+        let text = plays.iter().map(Play::to_algebraic).collect::<Vec<_>>();
+        assert_eq!(text, vec!["F6->F2", "A8->B8", "K7->K1", "A4->A11"]);
+        for (play, text) in plays.iter().zip(text) {
+            assert_eq!(&Play::from_algebraic(&text, play.role).unwrap(), play);
+        }
+        assert_eq!(
+            Play::from_algebraic("f6->f2", Role::Defender).unwrap(),
+            plays[0]
+        );
+        assert!(Play::from_algebraic("F6", Role::Defender).is_err());
+        assert!(Play::from_algebraic("F6->", Role::Defender).is_err());
+        assert!(Play::from_algebraic("F6->L6", Role::Defender).is_err());
+        assert!(Play::from_algebraic("F6->F2->F3", Role::Defender).is_err());
+    }
 
     /// Test that a play from or to a square not in the board
     /// bounds results in an error
@@ -347,4 +1014,597 @@ mod tests {
             .is_ok()
         )
     }
+
+    /// Test that the attackers cannot dodge a loss by repeating positions
+    #[test]
+    fn test_attacker_repetition() {
+        let mut game = LiveGame {
+            attacker_repetition_limit: 2,
+            ..Default::default()
+        };
+        let moves = [
+            (Role::Attacker, "a8", "b8"),
+            (Role::Defender, "f8", "f9"),
+            (Role::Attacker, "b8", "a8"),
+            (Role::Defender, "f9", "f8"),
+        ];
+        for (role, from, to) in moves {
+            game.play(&Play {
+                role,
+                from: Square::from_str(from).unwrap(),
+                to: Square::from_str(to).unwrap(),
+            })
+            .expect("Test failed");
+            assert_eq!(game.status, Status::Ongoing);
+        }
+        assert_eq!(game.repetitions(), 2);
+        game.play(&Play {
+            role: Role::Attacker,
+            from: Square::from_str("a8").unwrap(),
+            to: Square::from_str("b8").unwrap(),
+        })
+        .expect("Test failed");
+        assert_eq!(game.repetitions(), 2);
+        assert_eq!(game.status, Status::Draw);
+    }
+
+    /// Test that a finished game is summarized correctly
+    #[test]
+    fn test_game_result() {
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".O.........",
+            "...........",
+            "...........",
+            "......X....",
+            "..K........",
+        ];
+        let mut game = LiveGame {
+            current_board: board::Board::try_from(board).expect("Test failed"),
+            ..Default::default()
+        };
+        assert!(game.result().is_none());
+        // the attackers can only guard one of the corners
+        let moves = [(Role::Attacker, "b5", "b1"), (Role::Defender, "c1", "k1")];
+        for (role, from, to) in moves {
+            assert!(game.result().is_none());
+            game.play(&Play {
+                role,
+                from: Square::from_str(from).unwrap(),
+                to: Square::from_str(to).unwrap(),
+            })
+            .expect("Test failed");
+        }
+        assert_eq!(
+            game.result(),
+            Some(GameResult {
+                winner: Some(Role::Defender),
+                win_reason: Some(WinReason::KingEscaped),
+                draw_reason: None,
+                plies: 2,
+                material: (1, 2),
+            })
+        );
+
+        assert_eq!(
+            game.result().expect("Test failed").to_string(),
+            "Ended after 2 moves as the king escaped, with 1 attackers and 2 defenders left"
+        );
+
+        let mut game = LiveGame::default();
+        game.resign();
+        let result = game.result().expect("Test failed");
+        assert_eq!(result.winner, Some(Role::Defender));
+        assert_eq!(result.win_reason, Some(WinReason::Resigned));
+        assert_eq!(result.plies, 0);
+
+        // only a game which reached the move limit is drawn by it
+        let game = LiveGame {
+            status: Status::Draw,
+            previous_boards: PositionsTracker::Counter(MOVE_LIMIT),
+            ..Default::default()
+        };
+        assert_eq!(
+            game.result().expect("Test failed").draw_reason,
+            Some(DrawReason::MoveLimit)
+        );
+        let game = LiveGame {
+            status: Status::Draw,
+            ..Default::default()
+        };
+        let result = game.result().expect("Test failed");
+        assert_eq!(result.winner, None);
+        assert_eq!(result.draw_reason, None);
+    }
+
+    /// Test that the engine accepts a draw only in an even position
+    #[test]
+    fn test_draw_offers() {
+        let mut game = LiveGame {
+            engine: Some(EngineRole::from(Role::Attacker)),
+            turn: Role::Defender,
+            ..Default::default()
+        };
+        game.offer_draw();
+        assert_eq!(game.status, Status::Draw);
+        assert_eq!(game.draw_offer, Some(Role::Defender));
+        assert_eq!(
+            game.result().expect("Test failed").draw_reason,
+            Some(DrawReason::Agreement)
+        );
+
+        // a defender down, but still close to even
+        let mut board = Board::default();
+        let defender = Square::from_str("f4").unwrap();
+        assert_eq!(board.get(&defender), Space::Occupied(Role::Defender));
+        board.set(&defender, Space::Empty);
+        let mut game = LiveGame {
+            engine: Some(EngineRole::from(Role::Attacker)),
+            turn: Role::Defender,
+            current_board: board,
+            ..Default::default()
+        };
+        game.offer_draw();
+        assert_eq!(game.status, Status::Draw);
+
+        // the attackers are stuck next to the corners, far from even
+        let board = Board::try_from([
+            ".O.......O.",
+            "O...OOO...O",
+            ".....O.....",
+            "...........",
+            "O....X....O",
+            "O...XKX...O",
+            "O....X....O",
+            "...........",
+            ".....O.....",
+            "O...OOO...O",
+            ".O.......O.",
+        ])
+        .expect("Test failed");
+        let mut game = LiveGame {
+            engine: Some(EngineRole::from(Role::Attacker)),
+            turn: Role::Defender,
+            current_board: board,
+            ..Default::default()
+        };
+        game.offer_draw();
+        assert_eq!(game.status, Status::Ongoing);
+        assert_eq!(game.draw_offer, None);
+
+        // without an engine, the opponent accepts by offering in turn
+        let mut game = LiveGame::default();
+        game.offer_draw();
+        assert_eq!(game.draw_offer, Some(Role::Attacker));
+        game.play(&Play {
+            role: Role::Attacker,
+            from: Square::from_str("a8").unwrap(),
+            to: Square::from_str("b8").unwrap(),
+        })
+        .expect("Test failed");
+        game.offer_draw();
+        assert_eq!(game.status, Status::Draw);
+        // playing on instead declines the offer
+        let mut game = LiveGame::default();
+        game.offer_draw();
+        game.play(&Play {
+            role: Role::Attacker,
+            from: Square::from_str("a8").unwrap(),
+            to: Square::from_str("b8").unwrap(),
+        })
+        .expect("Test failed");
+        game.play(&Play {
+            role: Role::Defender,
+            from: Square::from_str("f8").unwrap(),
+            to: Square::from_str("f9").unwrap(),
+        })
+        .expect("Test failed");
+        assert_eq!(game.draw_offer, None);
+        assert_eq!(game.status, Status::Ongoing);
+    }
+
+    /// Test that the engine's move is applied to the board as the user
+    /// sees it, leaving every other piece in place
+    #[test]
+    fn test_engine_play_orientation() {
+        // the attackers have two moves, neither of which captures, and the
+        // position has no symmetries
+        let board = Board::try_from([
+            ".OX........",
+            "...........",
+            "...........",
+            ".X.........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let mut game = LiveGame {
+            engine: Some(EngineRole::from(Role::Attacker)),
+            current_board: board,
+            ..Default::default()
+        };
+        let before = game.current_board.clone();
+        assert!(game.engine_play());
+        let after = game.current_board.clone();
+        let changed = Square::iter()
+            .filter(|sq| before.get(sq) != after.get(sq))
+            .collect::<Vec<_>>();
+        assert_eq!(changed.len(), 2);
+        let [from, to] = [changed[0], changed[1]];
+        let (from, to) = if before.get(&from) == Space::Empty {
+            (to, from)
+        } else {
+            (from, to)
+        };
+        assert_eq!(before.get(&from), Space::Occupied(Role::Attacker));
+        assert_eq!(after.get(&to), Space::Occupied(Role::Attacker));
+        assert_eq!(game.history, vec![before]);
+        assert_eq!(game.previous_boards.occurrences(&after), 1);
+        assert_eq!(game.turn, Role::Defender);
+    }
+
+    /// Test that the engine plays from the opening book if possible
+    /// and searches otherwise
+    #[test]
+    fn test_engine_opening_book() {
+        let play = Play {
+            role: Role::Attacker,
+            from: Square { x: 5, y: 1 },
+            to: Square { x: 8, y: 1 },
+        };
+        let mut opening_book = OpeningBook::default();
+        opening_book.insert(&Board::default(), &play, 1);
+        let mut game = LiveGame {
+            engine: Some(EngineRole::from(Role::Attacker)),
+            opening_book,
+            ..Default::default()
+        };
+        let mut expected = game.clone();
+        expected.play(&play).expect("Test failed");
+        assert!(game.engine_play());
+        assert_eq!(game.current_board, expected.current_board);
+        assert_eq!(game.turn, Role::Defender);
+
+        // the attackers only have a single legal move
+        let board = Board::try_from([
+            ".OX........",
+            "...........",
+            ".X.........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        game.current_board = board.clone();
+        game.turn = Role::Attacker;
+        assert!(game.engine_play());
+        let mut expected = board;
+        expected.set(&Square { x: 1, y: 0 }, Space::Empty);
+        expected.set(&Square { x: 1, y: 1 }, Space::Occupied(Role::Attacker));
+        assert_eq!(game.current_board, expected);
+    }
+
+    /// Test that the serialization of previous boards does not depend
+    /// on the order they were inserted in
+    #[test]
+    fn test_previous_boards_serialization() {
+        let mut game = LiveGame::default();
+        let mut boards = vec![game.current_board.clone()];
+        for (from, to) in [("a8", "b8"), ("f8", "f9"), ("b8", "b7"), ("f9", "f8")] {
+            game.play(&Play {
+                role: game.turn,
+                from: Square::from_str(from).expect("Test failed"),
+                to: Square::from_str(to).expect("Test failed"),
+            })
+            .expect("Test failed");
+            boards.push(game.current_board.clone());
+        }
+        let forward = boards.iter().cloned().collect::<PreviousBoards>();
+        let backward = boards.iter().rev().cloned().collect::<PreviousBoards>();
+        let forward_json = serde_json::to_string(&forward).expect("Test failed");
+        let backward_json = serde_json::to_string(&backward).expect("Test failed");
+        assert_eq!(forward_json, backward_json);
+        let deserialized: PreviousBoards =
+            serde_json::from_str(&forward_json).expect("Test failed");
+        assert_eq!(deserialized, forward);
+    }
+
+    /// Test that stepping forward through a replayed game gives the
+    /// board after each of its moves, stopping at either end
+    #[test]
+    fn test_replay() {
+        let mut game = LiveGame::default();
+        let mut plays = vec![];
+        let mut boards = vec![game.current_board.clone()];
+        for _ in 0..6 {
+            let play = game.current_board.legal_moves_sorted(&game.turn)[0].clone();
+            game.play(&play).expect("Test failed");
+            plays.push(play);
+            boards.push(game.current_board.clone());
+        }
+
+        let mut replay = LiveGame::replay(&plays).expect("Test failed");
+        assert_eq!(replay.turn, Role::Attacker);
+        replay.undo();
+        assert_eq!(replay.current_board, boards[0]);
+        for board in &boards[1..] {
+            replay.redo();
+            assert_eq!(&replay.current_board, board);
+        }
+        replay.redo();
+        assert_eq!(&replay.current_board, boards.last().unwrap());
+        assert_eq!(replay.history.len(), plays.len());
+
+        // the attackers can't move twice in a row
+        let twice = [plays[0].clone(), plays[0].clone()];
+        assert!(LiveGame::replay(&twice).is_err());
+    }
+
+    /// Test that the position key depends on the position reached and
+    /// the side to move, but not on the order of the moves
+    #[test]
+    fn test_position_key() {
+        let play = |game: &mut LiveGame, role, (fx, fy), (tx, ty)| {
+            game.play(&Play {
+                role,
+                from: Square { x: fx, y: fy },
+                to: Square { x: tx, y: ty },
+            })
+            .expect("Test failed")
+        };
+        let mut game = LiveGame::default();
+        let start = game.position_key();
+        play(&mut game, Role::Attacker, (0, 3), (1, 3));
+        play(&mut game, Role::Defender, (5, 3), (5, 2));
+        play(&mut game, Role::Attacker, (10, 3), (9, 3));
+
+        let mut transposed = LiveGame::default();
+        play(&mut transposed, Role::Attacker, (10, 3), (9, 3));
+        play(&mut transposed, Role::Defender, (5, 3), (5, 2));
+        play(&mut transposed, Role::Attacker, (0, 3), (1, 3));
+        assert_ne!(transposed.history, game.history);
+        assert_eq!(transposed.position_key(), game.position_key());
+
+        // the same moves on the other side of the board
+        let mut mirrored = LiveGame::default();
+        play(&mut mirrored, Role::Attacker, (0, 7), (1, 7));
+        play(&mut mirrored, Role::Defender, (5, 7), (5, 8));
+        play(&mut mirrored, Role::Attacker, (10, 7), (9, 7));
+        assert_eq!(mirrored.position_key(), game.position_key());
+
+        assert_ne!(game.position_key(), start);
+        let key = game.position_key();
+        game.turn = Role::Attacker;
+        assert_eq!(game.position_key(), (key.0, Role::Attacker));
+        assert_ne!(game.position_key(), key);
+    }
+
+    /// Test that a game started from a decided position is already over
+    #[test]
+    fn test_from_position() {
+        let game = LiveGame::from_position(Board::default(), Role::Attacker);
+        assert_eq!(game.status, Status::Ongoing);
+        let mut board = Board::default();
+        board.set(&Square { x: 5, y: 5 }, Space::Empty);
+        board.set(&Square { x: 0, y: 10 }, Space::King);
+        let mut game = LiveGame::from_position(board, Role::Attacker);
+        assert_eq!(game.status, Status::DefendersWin);
+        let play = game.current_board.legal_moves_sorted(&Role::Attacker)[0].clone();
+        assert!(game.play(&play).is_err());
+    }
+
+    /// Test that a game can be started with the defenders to move
+    #[test]
+    fn test_with_turn() {
+        let mut game = LiveGame::default().with_turn(Role::Defender);
+        assert_eq!(game.turn, Role::Defender);
+        assert_eq!(game.status, Status::Ongoing);
+        let (play, _) = GameTreeNode::from(&mut game)
+            .plays()
+            .next()
+            .expect("Test failed");
+        assert_eq!(play.role, Role::Defender);
+        game.play(&play).expect("Test failed");
+        assert_eq!(game.turn, Role::Attacker);
+
+        // a game recorded with the defenders moving first replays
+        let attacker_play = game.current_board.legal_moves_sorted(&Role::Attacker)[0].clone();
+        let plays = [play, attacker_play];
+        let mut replay = LiveGame::replay(&plays).expect("Test failed");
+        assert_eq!(replay.turn, Role::Defender);
+        replay.redo();
+        replay.redo();
+        assert_eq!(replay.turn, Role::Defender);
+        assert_eq!(replay.history.len(), 2);
+
+        // a side without moves loses as soon as it is handed the move
+        let board = Board::try_from([
+            ".OX........",
+            ".X.........",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let game = LiveGame::from_position(board, Role::Defender);
+        assert_eq!(game.status, Status::Ongoing);
+        let game = game.with_turn(Role::Attacker);
+        assert_eq!(game.status, Status::DefendersWin);
+    }
+
+    /// Test that a hint is a legal move for the side to move and that
+    /// asking for one leaves the game unchanged
+    #[test]
+    fn test_hint() {
+        // the attackers fill the board, so there are few moves to search
+        let board = Board::try_from([
+            ".OOOOOOOOO.",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOO.OOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            ".O..K..OOO.",
+        ])
+        .expect("Test failed");
+        for turn in [Role::Attacker, Role::Defender] {
+            let mut game = LiveGame::from_position(board.clone(), turn);
+            let (play, _) = game.hint().expect("Test failed");
+            assert_eq!(play.role, turn);
+            assert!(
+                game.current_board
+                    .is_legal_move(&play, &game.status, &game.previous_boards)
+            );
+            assert_eq!(game.current_board, board);
+            assert_eq!(game.turn, turn);
+            assert!(game.history.is_empty());
+        }
+        // the king captures at B1, whether or not an engine is attached
+        let capture = Play {
+            role: Role::Defender,
+            from: Square { x: 4, y: 10 },
+            to: Square { x: 2, y: 10 },
+        };
+        let mut game = LiveGame::from_position(board.clone(), Role::Defender);
+        assert_eq!(game.hint().expect("Test failed").0, capture);
+        game.engine = Some(EngineRole::from(Role::Attacker));
+        assert_eq!(game.hint().expect("Test failed").0, capture);
+        // there is nothing to suggest once the game is over
+        let mut board = board;
+        board.set(&Square { x: 4, y: 10 }, Space::Empty);
+        board.set(&Square { x: 0, y: 10 }, Space::King);
+        assert!(
+            LiveGame::from_position(board, Role::Attacker)
+                .hint()
+                .is_none()
+        );
+    }
+
+    /// Test that a side whose clock runs out loses without their move
+    /// being made, and that otherwise the increment is added
+    #[test]
+    fn test_clocks() {
+        let mut game = LiveGame {
+            clocks: Some(Clocks::new(Duration::from_secs(10), Duration::from_secs(1))),
+            ..Default::default()
+        };
+        let play = game.current_board.legal_moves_sorted(&Role::Attacker)[0].clone();
+        game.play_timed(&play, Duration::from_secs(4))
+            .expect("Test failed");
+        assert_eq!(game.status, Status::Ongoing);
+        let clocks = game.clocks.expect("Test failed");
+        assert_eq!(clocks.attacker, Duration::from_secs(7));
+        assert_eq!(clocks.defender, Duration::from_secs(10));
+
+        let before = game.current_board.clone();
+        let play = game.current_board.legal_moves_sorted(&Role::Defender)[0].clone();
+        game.play_timed(&play, Duration::from_secs(10))
+            .expect("Test failed");
+        assert_eq!(game.status, Status::AttackersWin);
+        assert_eq!(game.current_board, before);
+        let result = game.result().expect("Test failed");
+        assert_eq!(result.winner, Some(Role::Attacker));
+        assert_eq!(result.win_reason, Some(WinReason::TimeOut));
+        assert!(game.play_timed(&play, Duration::ZERO).is_err());
+    }
+
+    /// Test the reward of every status for both sides
+    #[test]
+    fn test_result_for() {
+        let cases = [
+            (Status::AttackersWin, Some(1.0), Some(-1.0)),
+            (Status::DefendersWin, Some(-1.0), Some(1.0)),
+            (Status::Draw, Some(0.0), Some(0.0)),
+            (Status::Ongoing, None, None),
+            (Status::Resigned(Role::Attacker), Some(-1.0), Some(1.0)),
+            (Status::Resigned(Role::Defender), Some(1.0), Some(-1.0)),
+        ];
+        for (status, attacker, defender) in cases {
+            assert_eq!(status.result_for(&Role::Attacker), attacker, "{status}");
+            assert_eq!(status.result_for(&Role::Defender), defender, "{status}");
+        }
+    }
+
+    /// Test recovering the moves played from the boards in the history
+    /// and listing them beside the board
+    #[test]
+    fn test_render_with_moves() {
+        let board = [
+            "...O.......",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".OX........",
+            "...........",
+            "...........",
+            "........K..",
+            "...........",
+            "...........",
+        ];
+        let board = Board::try_from(board).expect("Test failed");
+        let mut game = LiveGame::from_position(board, Role::Attacker);
+        let capture = Play {
+            role: Role::Attacker,
+            from: Square { x: 3, y: 0 },
+            to: Square { x: 3, y: 5 },
+        };
+        game.play(&capture).expect("Test failed");
+        assert_eq!(game.current_board.get(&Square { x: 2, y: 5 }), Space::Empty);
+        let king_move = Play {
+            role: Role::Defender,
+            from: Square { x: 8, y: 8 },
+            to: Square { x: 8, y: 6 },
+        };
+        game.play(&king_move).expect("Test failed");
+
+        assert_eq!(
+            Board::diff(&game.history[0], &game.history[1], &game.rules),
+            Some((capture.clone(), vec![Square { x: 2, y: 5 }]))
+        );
+        assert_eq!(
+            Board::diff(&game.history[1], &game.current_board, &game.rules),
+            Some((king_move.clone(), vec![]))
+        );
+        assert_eq!(
+            Board::diff(&game.history[0], &game.current_board, &game.rules),
+            None
+        );
+
+        let rendered = game.render_with_moves(1);
+        assert!(!rendered.contains(&capture.to_algebraic()));
+        assert!(rendered.contains(&format!("2. {}", king_move.to_algebraic())));
+        let rendered = game.render_with_moves(5);
+        assert!(rendered.contains(&format!("1. {}", capture.to_algebraic())));
+        assert!(rendered.contains(&format!("2. {}", king_move.to_algebraic())));
+        assert_eq!(
+            rendered.lines().count(),
+            game.current_board.to_string().lines().count()
+        );
+    }
 }
@@ -0,0 +1,151 @@
+//! An opening book maps positions to recommended moves so that
+//! the engine can play quickly and with more variety early on.
+//!
+//! Positions are looked up independently of their orientation. Moves
+//! are stored relative to the canonical orientation of the board and are
+//! translated back to the orientation of the board being played.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::game::Play;
+use crate::game::board::Board;
+use crate::game::symmetries::{NormalizedBoardMap, canonical_symmetry};
+
+/// Recommended moves for positions along with their weights
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OpeningBook(NormalizedBoardMap<Vec<(Play, u32)>>);
+
+impl OpeningBook {
+    /// Read an opening book from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Write the opening book to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Add a move with the given weight to the book for this position
+    #[allow(dead_code)]
+    pub fn insert(&mut self, board: &Board, play: &Play, weight: u32) {
+        let play = canonical_symmetry(board).apply_play(play);
+        match self.0.get_mut(board) {
+            Some(moves) => moves.push((play, weight)),
+            None => _ = self.0.insert(board, vec![(play, weight)]),
+        }
+    }
+
+    /// If the position is in the book, pick one of its moves at random
+    /// according to their weights.
+    pub fn choose(&self, board: &Board, rng: &mut impl Rng) -> Option<Play> {
+        let moves = self.0.get(board)?;
+        let total = moves.iter().map(|(_, weight)| *weight as u64).sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+        let mut choice = rng.random_range(0..total);
+        let (play, _) = moves.iter().find(|(_, weight)| {
+            if choice < *weight as u64 {
+                true
+            } else {
+                choice -= *weight as u64;
+                false
+            }
+        })?;
+        Some(canonical_symmetry(board).inverse().apply_play(play))
+    }
+}
+
+#[cfg(test)]
+mod test_opening_book {
+    use super::*;
+    use crate::game::space::{Role, Square};
+    use crate::game::symmetries::D8;
+
+    /// Test that book moves are translated to the orientation of the
+    /// board being played
+    #[test]
+    fn test_choose_orientation() {
+        let board = Board::try_from([
+            "...OOOOO...",
+            ".....O.....",
+            "...........",
+            "O....X....O",
+            "O...XXX...O",
+            "OO.XXKXX.OO",
+            "O...XXX...O",
+            "O....X....O",
+            "...........",
+            ".....O.....",
+            "...OO.OO.O.",
+        ])
+        .expect("Test failed");
+        let play = Play {
+            role: Role::Defender,
+            from: Square { x: 5, y: 3 },
+            to: Square { x: 8, y: 3 },
+        };
+        let mut book = OpeningBook::default();
+        book.insert(&board, &play, 1);
+        let mut rng = rand::rng();
+        assert_eq!(book.choose(&board, &mut rng), Some(play.clone()));
+
+        for d8 in D8 {
+            let mut rotated = board.clone();
+            d8.apply(&mut rotated);
+            let expected = d8.apply_play(&play);
+            assert_eq!(book.choose(&rotated, &mut rng), Some(expected));
+        }
+        assert!(book.choose(&Board::default(), &mut rng).is_none());
+    }
+
+    /// Test that moves with no weight are never chosen
+    #[test]
+    fn test_choose_weights() {
+        let board = Board::default();
+        let good = Play {
+            role: Role::Attacker,
+            from: Square { x: 5, y: 1 },
+            to: Square { x: 8, y: 1 },
+        };
+        let bad = Play {
+            role: Role::Attacker,
+            from: Square { x: 3, y: 0 },
+            to: Square { x: 3, y: 2 },
+        };
+        let mut book = OpeningBook::default();
+        book.insert(&board, &bad, 0);
+        book.insert(&board, &good, 5);
+        let mut rng = rand::rng();
+        for _ in 0..20 {
+            assert_eq!(book.choose(&board, &mut rng), Some(good.clone()));
+        }
+    }
+
+    /// Test that the book survives a round trip through a file
+    #[test]
+    fn test_save_load() {
+        let mut book = OpeningBook::default();
+        book.insert(
+            &Board::default(),
+            &Play {
+                role: Role::Attacker,
+                from: Square { x: 5, y: 1 },
+                to: Square { x: 8, y: 1 },
+            },
+            3,
+        );
+        let dir = tempfile::tempdir().expect("Test failed");
+        let path = dir.path().join("book.json");
+        book.save(&path).expect("Test failed");
+        assert_eq!(OpeningBook::load(&path).expect("Test failed"), book);
+    }
+}
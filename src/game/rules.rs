@@ -0,0 +1,72 @@
+//! Hnefatafl is played with many variations of the rules. These
+//! are the variations we support. The defaults are the rules the
+//! engine was originally written for.
+use serde::{Deserialize, Serialize};
+
+use crate::game::space::Role;
+
+/// Which pieces the throne helps to capture
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ThroneHostility {
+    /// The throne is hostile to both sides
+    #[default]
+    All,
+    /// The throne only helps capture attackers
+    Attackers,
+    /// The throne only helps capture defenders
+    Defenders,
+    /// The throne never takes part in captures
+    Neither,
+}
+
+impl ThroneHostility {
+    /// Check if the throne takes part in capturing pieces of `role`
+    pub fn is_hostile_to(&self, role: &Role) -> bool {
+        match self {
+            ThroneHostility::All => true,
+            ThroneHostility::Attackers => *role == Role::Attacker,
+            ThroneHostility::Defenders => *role == Role::Defender,
+            ThroneHostility::Neither => false,
+        }
+    }
+}
+
+/// The variations of the rules a game is played with
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Rules {
+    /// Which pieces the empty throne helps to capture
+    pub throne_hostility: ThroneHostility,
+    /// Which pieces the throne helps to capture while the king is on it,
+    /// in addition to any captures the king makes himself
+    pub occupied_throne_hostility: ThroneHostility,
+    /// The defenders win if the king forms an exit fort
+    pub exit_forts: bool,
+    /// The corners take part in capturing pieces next to them
+    pub corners_hostile: bool,
+    /// The furthest the king may move in one turn. If unset, he moves
+    /// like any other piece.
+    pub king_move_range: Option<u8>,
+    /// A king on the edge is captured by attackers on his three other sides
+    pub edge_king_capture: bool,
+    /// The king helps to capture attackers. If unset, the king is unarmed
+    /// and never flanks a piece, whether moving or standing still.
+    pub king_can_capture: bool,
+    /// The fewest pieces a shield wall must capture. Shorter walls
+    /// capture nothing.
+    pub shield_wall_min: usize,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            throne_hostility: Default::default(),
+            occupied_throne_hostility: ThroneHostility::Neither,
+            exit_forts: false,
+            corners_hostile: true,
+            king_move_range: None,
+            edge_king_capture: false,
+            king_can_capture: true,
+            shield_wall_min: 1,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Games written to disk so that they can be resumed later. The engine
+//! is stored as its configuration and rebuilt when the game is loaded,
+//! as search handles such as networks can't be written out themselves.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::board::Board;
+use crate::game::clocks::Clocks;
+use crate::game::rules::Rules;
+use crate::game::space::Role;
+use crate::game::{EngineRole, LiveGame, PositionsTracker, Status};
+
+/// The kinds of policy an engine can search with
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum EnginePolicy {
+    /// The handwritten evaluation of positions
+    #[default]
+    Heuristic,
+}
+
+/// Everything needed to set up an engine, as opposed to the engine itself
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EngineConfig {
+    pub role: Role,
+    /// How many plies deep the engine searches each move
+    pub depth: usize,
+    pub policy: EnginePolicy,
+    /// Discard moves leading to positions symmetric to ones already searched
+    pub normalize: bool,
+    /// The network evaluating positions, for policies which use one
+    pub model: Option<PathBuf>,
+}
+
+impl From<&EngineRole> for EngineConfig {
+    fn from(engine: &EngineRole) -> Self {
+        Self {
+            role: engine.role,
+            depth: engine.depth,
+            policy: EnginePolicy::Heuristic,
+            normalize: engine.normalize,
+            model: None,
+        }
+    }
+}
+
+impl TryFrom<&EngineConfig> for EngineRole {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &EngineConfig) -> anyhow::Result<Self> {
+        match config.policy {
+            EnginePolicy::Heuristic if config.model.is_some() => Err(anyhow::Error::msg(
+                "The heuristic engine does not use a model",
+            )),
+            EnginePolicy::Heuristic => Ok(Self {
+                engine: Default::default(),
+                role: config.role,
+                normalize: config.normalize,
+                depth: config.depth,
+            }),
+        }
+    }
+}
+
+/// The state of a [`LiveGame`] as it is written to disk. The opening
+/// book and tablebase are loaded separately and the clocks are stopped.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SavedGame {
+    pub status: Status,
+    pub previous_boards: PositionsTracker,
+    pub history: Vec<Board>,
+    pub ahead: Vec<Board>,
+    pub turn: Role,
+    pub current_board: Board,
+    pub engine: Option<EngineConfig>,
+    pub attacker_repetition_limit: usize,
+    pub rules: Rules,
+    pub draw_offer: Option<Role>,
+    pub clocks: Option<Clocks>,
+}
+
+impl From<&LiveGame> for SavedGame {
+    fn from(game: &LiveGame) -> Self {
+        Self {
+            status: game.status,
+            previous_boards: game.previous_boards.clone(),
+            history: game.history.clone(),
+            ahead: game.ahead.clone(),
+            turn: game.turn,
+            current_board: game.current_board.clone(),
+            engine: game.engine.as_ref().map(EngineConfig::from),
+            attacker_repetition_limit: game.attacker_repetition_limit,
+            rules: game.rules,
+            draw_offer: game.draw_offer,
+            clocks: game.clocks,
+        }
+    }
+}
+
+impl TryFrom<SavedGame> for LiveGame {
+    type Error = anyhow::Error;
+
+    fn try_from(saved: SavedGame) -> anyhow::Result<Self> {
+        Ok(Self {
+            status: saved.status,
+            previous_boards: saved.previous_boards,
+            history: saved.history,
+            ahead: saved.ahead,
+            turn: saved.turn,
+            current_board: saved.current_board,
+            engine: saved
+                .engine
+                .as_ref()
+                .map(EngineRole::try_from)
+                .transpose()?,
+            attacker_repetition_limit: saved.attacker_repetition_limit,
+            rules: saved.rules,
+            draw_offer: saved.draw_offer,
+            clocks: saved.clocks,
+            ..Default::default()
+        })
+    }
+}
+
+impl LiveGame {
+    /// Read a game from a JSON file, setting up its engine again
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        serde_json::from_reader::<_, SavedGame>(BufReader::new(file))?.try_into()
+    }
+
+    /// Write the game to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), &SavedGame::from(self))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Play;
+    use crate::game::space::Square;
+    use std::time::Duration;
+
+    /// Test that a game is saved along with its engine's configuration
+    /// and that the engine is set up the same way when it is loaded
+    #[test]
+    fn test_save_load() {
+        let mut game = LiveGame {
+            engine: Some(EngineRole {
+                depth: 2,
+                ..EngineRole::from(Role::Defender).without_symmetry()
+            }),
+            clocks: Some(Clocks::new(Duration::from_secs(60), Duration::ZERO)),
+            ..Default::default()
+        };
+        game.play(&Play {
+            role: Role::Attacker,
+            from: Square { x: 0, y: 3 },
+            to: Square { x: 1, y: 3 },
+        })
+        .expect("Test failed");
+        let dir = tempfile::tempdir().expect("Test failed");
+        let path = dir.path().join("game.json");
+        game.save(&path).expect("Test failed");
+
+        let saved: SavedGame =
+            serde_json::from_reader(File::open(&path).expect("Test failed")).expect("Test failed");
+        assert_eq!(
+            saved.engine,
+            Some(EngineConfig {
+                role: Role::Defender,
+                depth: 2,
+                policy: EnginePolicy::Heuristic,
+                normalize: false,
+                model: None,
+            })
+        );
+        let loaded = LiveGame::load(&path).expect("Test failed");
+        assert_eq!(loaded.engine, game.engine);
+        assert_eq!(loaded.current_board, game.current_board);
+        assert_eq!(loaded.history, game.history);
+        assert_eq!(loaded.previous_boards, game.previous_boards);
+        assert_eq!(loaded.turn, Role::Defender);
+        assert_eq!(loaded.clocks, game.clocks);
+        assert_eq!(loaded.turn_started, None);
+
+        // an engine which can't be set up fails to load
+        let saved = SavedGame {
+            engine: Some(EngineConfig {
+                model: Some(PathBuf::from("attacker.model")),
+                ..saved.engine.expect("Test failed")
+            }),
+            ..saved
+        };
+        assert!(LiveGame::try_from(saved).is_err());
+    }
+}
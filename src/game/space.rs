@@ -5,6 +5,7 @@ use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
 use crate::game::Status;
+use crate::game::board::STARTING_POSITION;
 
 pub const BOARD_LETTERS: &str = "ABCDEFGHIJK";
 
@@ -33,6 +34,17 @@ impl Role {
             Role::Defender => Status::DefendersWin,
         }
     }
+
+    /// The squares this side's pieces start on in the standard setup,
+    /// not including the king. Note that [`AttackerIter`] and
+    /// [`DefenderIter`] instead order every square for move generation.
+    pub fn starting_squares(&self) -> impl Iterator<Item = Square> {
+        let piece = Space::Occupied(*self);
+        Square::iter().filter(move |sq| {
+            let ch = STARTING_POSITION[sq.y].as_bytes()[sq.x] as char;
+            Space::try_from(ch).is_ok_and(|space| space == piece)
+        })
+    }
 }
 
 impl fmt::Display for Role {
@@ -93,6 +105,17 @@ impl TryFrom<char> for Space {
     }
 }
 
+impl From<Space> for char {
+    fn from(space: Space) -> Self {
+        match space {
+            Space::Occupied(Role::Defender) => 'X',
+            Space::Occupied(Role::Attacker) => 'O',
+            Space::Empty => '.',
+            Space::King => 'K',
+        }
+    }
+}
+
 impl fmt::Display for Space {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -162,6 +185,20 @@ impl FromStr for Square {
 }
 
 impl Square {
+    /// The index of the square in arrays holding a value for each square,
+    /// which are laid out row by row from the top left corner
+    pub const fn to_index(self) -> usize {
+        self.y * 11 + self.x
+    }
+
+    /// The square at the given index. See [`Square::to_index`].
+    pub const fn from_index(ix: usize) -> Self {
+        Self {
+            x: ix % 11,
+            y: ix / 11,
+        }
+    }
+
     /// Checks if the square is one of the corners or the throne
     pub fn is_restricted(&self) -> bool {
         RESTRICTED_SQUARES.contains(self)
@@ -172,6 +209,19 @@ impl Square {
         EXIT_SQUARES.contains(self)
     }
 
+    /// Checks if the square is on the edge of the board
+    pub fn is_edge(&self) -> bool {
+        self.x == 0 || self.x == 10 || self.y == 0 || self.y == 10
+    }
+
+    /// The squares above, left of, right of and below this one, in that
+    /// order, skipping those off the board
+    pub fn orthogonal_neighbors(&self) -> impl Iterator<Item = Square> + use<> {
+        [self.up(), self.left(), self.right(), self.down()]
+            .into_iter()
+            .flatten()
+    }
+
     #[must_use]
     pub fn up(&self) -> Option<Square> {
         if self.y > 0 {
@@ -220,6 +270,48 @@ impl Square {
         }
     }
 
+    /// Get the square offset from this one by the given amounts,
+    /// if it is on the board.
+    #[must_use]
+    pub fn step(&self, dx: i32, dy: i32) -> Option<Square> {
+        let x = self.x as i32 + dx;
+        let y = self.y as i32 + dy;
+        if (0..11).contains(&x) && (0..11).contains(&y) {
+            Some(Square {
+                x: x as usize,
+                y: y as usize,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Get the squares strictly between this square and `other` if
+    /// they lie on the same row or column.
+    pub fn line_to(&self, other: &Square) -> Option<Vec<Square>> {
+        if self.x != other.x && self.y != other.y {
+            return None;
+        }
+        let dx = (other.x as i32 - self.x as i32).signum();
+        let dy = (other.y as i32 - self.y as i32).signum();
+        let mut line = Vec::with_capacity(self.manhattan_distance(other).saturating_sub(1));
+        let mut cursor = *self;
+        while let Some(next) = cursor.step(dx, dy) {
+            if next == *other {
+                break;
+            }
+            line.push(next);
+            cursor = next;
+        }
+        Some(line)
+    }
+
+    /// The number of squares between two squares, only moving
+    /// horizontally or vertically
+    pub fn manhattan_distance(&self, other: &Square) -> usize {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
+    }
+
     /// Get an iterator over all squares in the board
     pub fn iter() -> SquareIter {
         SquareIter::default()
@@ -249,6 +341,9 @@ impl Iterator for SquareIter {
     }
 }
 
+/// Every square on the board, whatever occupies it, in the order the
+/// defenders' moves are searched: the inner 5 x 5 square around the
+/// throne first, then the outer three layers
 #[derive(Default)]
 pub struct DefenderIter(Option<Square>);
 
@@ -298,6 +393,12 @@ impl Iterator for DefenderIter {
     }
 }
 
+/// Every square on the board except the restricted ones, whatever
+/// occupies it, in the order the attackers' moves are searched: the
+/// outer three layers first, then the inner 5 x 5 square. To find the
+/// pieces actually on a board, use [`Board::occupied_squares`].
+///
+/// [`Board::occupied_squares`]: crate::game::board::Board::occupied_squares
 #[derive(Default)]
 pub struct AttackerIter(Option<Square>);
 
@@ -367,17 +468,37 @@ impl<T> Default for SquareMap<T> {
 
 impl<T> SquareMap<T> {
     pub fn contains_key(&self, key: &Square) -> bool {
-        self.inner[key.y * 11 + key.x].is_some()
+        self.inner[key.to_index()].is_some()
     }
 
     pub fn get(&self, key: &Square) -> Option<&T> {
-        let ix = key.y * 11 + key.x;
-        self.inner[ix].as_ref()
+        self.inner[key.to_index()].as_ref()
     }
 
     pub fn insert(&mut self, key: Square, value: T) {
-        let ix = key.y * 11 + key.x;
-        self.inner[ix] = Some(value);
+        self.inner[key.to_index()] = Some(value);
+    }
+
+    /// Iterate over the entries of the map in board order, i.e.
+    /// row by row starting from the top left corner.
+    pub fn iter(&self) -> impl Iterator<Item = (Square, &T)> {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, value)| value.as_ref().map(|v| (Square::from_index(ix), v)))
+    }
+
+    /// Iterate over the keys of the map in board order
+    pub fn keys(&self) -> impl Iterator<Item = Square> {
+        self.iter().map(|(sq, _)| sq)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.iter().filter(|value| value.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.iter().all(|value| value.is_none())
     }
 }
 
@@ -391,6 +512,38 @@ impl SquareSet {
     pub fn add(&mut self, key: Square) {
         self.insert(key, ());
     }
+
+    /// The number of squares in the set
+    pub fn count(&self) -> usize {
+        self.len()
+    }
+
+    /// The squares in either set
+    #[must_use]
+    pub fn union(&self, other: &SquareSet) -> SquareSet {
+        self.combine(other, |a, b| a || b)
+    }
+
+    /// The squares in both sets
+    #[must_use]
+    pub fn intersection(&self, other: &SquareSet) -> SquareSet {
+        self.combine(other, |a, b| a && b)
+    }
+
+    /// The squares in this set but not in `other`
+    #[must_use]
+    pub fn difference(&self, other: &SquareSet) -> SquareSet {
+        self.combine(other, |a, b| a && !b)
+    }
+
+    /// Keep a square if `keep` holds for whether it is in each set
+    fn combine(&self, other: &SquareSet, keep: impl Fn(bool, bool) -> bool) -> SquareSet {
+        SquareMap {
+            inner: std::array::from_fn(|ix| {
+                keep(self.inner[ix].is_some(), other.inner[ix].is_some()).then_some(())
+            }),
+        }
+    }
 }
 
 impl<A> FromIterator<(Square, A)> for SquareMap<A> {
@@ -415,6 +568,7 @@ where
 #[cfg(test)]
 mod test_spaces {
     use super::*;
+    use crate::game::board::{Board, STARTING_MATERIAL};
     use std::collections::HashSet;
 
     /// Test that the iteration over the squares visits
@@ -430,6 +584,28 @@ mod test_spaces {
         assert_eq!(squares.len(), 11 * 11);
     }
 
+    /// Test that each side's starting squares hold exactly its pieces in
+    /// the default board
+    #[test]
+    fn test_starting_squares() {
+        let board = Board::default();
+        for role in [Role::Attacker, Role::Defender] {
+            assert!(
+                role.starting_squares()
+                    .all(|sq| board.get(&sq) == Space::Occupied(role))
+            );
+        }
+        assert_eq!(
+            Role::Attacker.starting_squares().count(),
+            STARTING_MATERIAL.0 as usize
+        );
+        // the king is not included
+        assert_eq!(
+            Role::Defender.starting_squares().count(),
+            STARTING_MATERIAL.1 as usize - 1
+        );
+    }
+
     /// Check that we can correctly tell which side a piece is on
     #[test]
     fn test_is_ally() {
@@ -452,6 +628,112 @@ mod test_spaces {
         assert_eq!(Square { x: 5, y: 5 }.to_string(), "F6");
     }
 
+    /// Test stepping between squares and finding the squares between them
+    #[test]
+    fn test_square_arithmetic() {
+        let sq = Square { x: 3, y: 7 };
+        assert_eq!(sq.step(2, -3), Some(Square { x: 5, y: 4 }));
+        assert_eq!(sq.step(0, 0), Some(sq));
+        assert_eq!(sq.step(-4, 0), None);
+        assert_eq!(sq.step(0, 4), None);
+
+        // diagonal
+        assert_eq!(sq.line_to(&Square { x: 5, y: 5 }), None);
+        // same square
+        assert_eq!(sq.line_to(&sq), Some(vec![]));
+        // neighbors
+        assert_eq!(sq.line_to(&Square { x: 3, y: 8 }), Some(vec![]));
+        // straight lines
+        assert_eq!(
+            sq.line_to(&Square { x: 0, y: 7 }),
+            Some(vec![Square { x: 2, y: 7 }, Square { x: 1, y: 7 }])
+        );
+        assert_eq!(
+            sq.line_to(&Square { x: 3, y: 10 }),
+            Some(vec![Square { x: 3, y: 8 }, Square { x: 3, y: 9 }])
+        );
+
+        assert_eq!(sq.manhattan_distance(&sq), 0);
+        assert_eq!(sq.manhattan_distance(&Square { x: 0, y: 7 }), 3);
+        assert_eq!(sq.manhattan_distance(&Square { x: 10, y: 0 }), 14);
+    }
+
+    /// Test that the orthogonal neighbors of a square are the squares
+    /// next to it on the board, in board order
+    #[test]
+    fn test_orthogonal_neighbors() {
+        let neighbors = |x, y| Square { x, y }.orthogonal_neighbors().collect::<Vec<_>>();
+        assert_eq!(
+            neighbors(0, 0),
+            vec![Square { x: 1, y: 0 }, Square { x: 0, y: 1 }]
+        );
+        assert_eq!(
+            neighbors(10, 4),
+            vec![
+                Square { x: 10, y: 3 },
+                Square { x: 9, y: 4 },
+                Square { x: 10, y: 5 },
+            ]
+        );
+        assert_eq!(
+            neighbors(5, 5),
+            vec![
+                Square { x: 5, y: 4 },
+                Square { x: 4, y: 5 },
+                Square { x: 6, y: 5 },
+                Square { x: 5, y: 6 },
+            ]
+        );
+    }
+
+    /// Test that each square has its own index and converts back
+    /// from it
+    #[test]
+    fn test_index_round_trip() {
+        let mut indices = HashSet::new();
+        for sq in Square::iter() {
+            assert_eq!(Square::from_index(sq.to_index()), sq);
+            assert!(indices.insert(sq.to_index()));
+        }
+        assert_eq!(indices, (0..11 * 11).collect());
+        assert_eq!(THRONE.to_index(), 60);
+        assert_eq!(Square::from_index(12), Square { x: 1, y: 1 });
+    }
+
+    /// Test that iterating over a map yields exactly the inserted
+    /// entries in board order
+    #[test]
+    fn test_square_map_iter() {
+        let mut map = SquareMap::default();
+        assert!(map.is_empty());
+        assert_eq!(map.len(), 0);
+        map.insert(Square { x: 3, y: 7 }, 'a');
+        map.insert(Square { x: 10, y: 0 }, 'b');
+        map.insert(Square { x: 0, y: 10 }, 'c');
+        map.insert(Square { x: 0, y: 7 }, 'd');
+        map.insert(Square { x: 3, y: 7 }, 'e');
+        assert!(!map.is_empty());
+        assert_eq!(map.len(), 4);
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![
+                (Square { x: 10, y: 0 }, &'b'),
+                (Square { x: 0, y: 7 }, &'d'),
+                (Square { x: 3, y: 7 }, &'e'),
+                (Square { x: 0, y: 10 }, &'c'),
+            ]
+        );
+        assert_eq!(
+            map.keys().collect::<Vec<_>>(),
+            vec![
+                Square { x: 10, y: 0 },
+                Square { x: 0, y: 7 },
+                Square { x: 3, y: 7 },
+                Square { x: 0, y: 10 },
+            ]
+        );
+    }
+
     /// Test that the [`AttackIter`] yeilds values in the outer
     /// three layers first and yields no restricted squares.
     #[test]
@@ -501,4 +783,32 @@ mod test_spaces {
 
         assert_eq!(visited.len(), 121);
     }
+
+    /// Test union, intersection and difference of sets of squares
+    #[test]
+    fn test_square_set_algebra() {
+        let attackers = SquareSet::from(Role::Attacker.starting_squares().map(|sq| (sq, ())));
+        let defenders = SquareSet::from(Role::Defender.starting_squares().map(|sq| (sq, ())));
+        let empty = SquareSet::default();
+
+        let union = attackers.union(&defenders);
+        assert_eq!(union.count(), attackers.count() + defenders.count());
+        assert!(attackers.intersection(&defenders).is_empty());
+        assert_eq!(attackers.difference(&defenders).count(), attackers.count());
+        assert_eq!(union.difference(&defenders).count(), attackers.count());
+        assert!(union.difference(&defenders).keys().eq(attackers.keys()));
+        assert!(union.intersection(&defenders).keys().eq(defenders.keys()));
+        assert!(attackers.union(&empty).keys().eq(attackers.keys()));
+        assert!(attackers.intersection(&empty).is_empty());
+        assert!(attackers.difference(&attackers).is_empty());
+
+        let row = SquareSet::from((0..11).map(|x| (Square { x, y: 3 }, ())));
+        let column = SquareSet::from((0..11).map(|y| (Square { x: 3, y }, ())));
+        assert_eq!(
+            row.intersection(&column).keys().collect::<Vec<_>>(),
+            vec![Square { x: 3, y: 3 }]
+        );
+        assert_eq!(row.union(&column).count(), 21);
+        assert_eq!(row.difference(&column).count(), 10);
+    }
 }
@@ -1,9 +1,10 @@
 //! Hnefatafl is symmetric with respect to the symmetries of the square,
 //! the groupd D8. This contains utilities to exploit that symmetry.
 
-use rustc_hash::{FxHashMap, FxHashSet};
-use serde::{Deserialize, Serialize};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::game::Play;
 use crate::game::board::Board;
 use crate::game::space::{Space, Square};
 
@@ -52,6 +53,20 @@ impl D8Generator {
             }
         }
     }
+
+    /// Apply a generator of D8 to a single square
+    pub fn apply_square(&self, square: &Square) -> Square {
+        match self {
+            D8Generator::F => Square {
+                x: square.x,
+                y: 10 - square.y,
+            },
+            D8Generator::FR => Square {
+                x: square.y,
+                y: square.x,
+            },
+        }
+    }
 }
 
 /// An element of D8 expressed as a word in the two chosen generators
@@ -69,6 +84,46 @@ impl D8Element {
             }
         }
     }
+
+    /// Apply a D8 element to a single square. A piece on `square` is
+    /// moved to the returned square by [`D8Element::apply`].
+    pub fn apply_square(&self, square: &Square) -> Square {
+        self.0
+            .iter()
+            .map_while(|g| g.as_ref())
+            .fold(*square, |sq, g| g.apply_square(&sq))
+    }
+
+    /// Apply a D8 element to both squares of a play
+    pub fn apply_play(&self, play: &Play) -> Play {
+        Play {
+            role: play.role,
+            from: self.apply_square(&play.from),
+            to: self.apply_square(&play.to),
+        }
+    }
+
+    /// Both generators are their own inverses, so the inverse
+    /// of a word is the reversed word.
+    pub fn inverse(&self) -> Self {
+        let length = self.0.iter().take_while(|g| g.is_some()).count();
+        let mut word = [None; 4];
+        word[..length].copy_from_slice(&self.0[..length]);
+        word[..length].reverse();
+        Self(word)
+    }
+}
+
+/// Find the element of D8 mapping `board` to the orientation with the smallest
+/// bitboard. All symmetric boards are mapped to the same canonical board.
+pub fn canonical_symmetry(board: &Board) -> D8Element {
+    D8.into_iter()
+        .min_by_key(|d8| {
+            let mut b = board.clone();
+            d8.apply(&mut b);
+            b.as_bitboard()
+        })
+        .unwrap()
 }
 
 /// The group D8 using the standard presentation
@@ -107,10 +162,21 @@ pub const D8: [D8Element; 8] = [
     D8Element([Some(D8Generator::F), Some(D8Generator::FR), None, None]),
 ];
 
+/// The bitboard of the canonical orientation of a board. It is stored
+/// alongside symmetric hashes to detect collisions.
+#[cfg(feature = "verify-hashes")]
+type Canonical = [u8; 30];
+/// Without collision detection, nothing is stored alongside the hashes.
+#[cfg(not(feature = "verify-hashes"))]
+type Canonical = ();
+
 /// For each symmetry of a board, compute a byte
 /// vector. Return a hash of the sum of these vectors.
-/// This provides a hash that is invariant under board symmetries
-fn symmetric_hash(board: &Board) -> [u8; 32] {
+/// This provides a hash that is invariant under board symmetries.
+///
+/// Also returns the data needed to check that two boards with
+/// the same hash really are symmetric.
+fn symmetric_hash(board: &Board) -> ([u8; 32], Canonical) {
     use sha2::{Digest, Sha256};
     let mut bytes = [[0u8; 30]; 8];
     for (ix, d8) in D8.iter().enumerate() {
@@ -123,64 +189,170 @@ fn symmetric_hash(board: &Board) -> [u8; 32] {
     for b in bytes {
         hasher.update(b);
     }
-    hasher.finalize().into()
+    #[cfg(feature = "verify-hashes")]
+    let canonical = bytes[0];
+    #[cfg(not(feature = "verify-hashes"))]
+    let canonical = ();
+    (hasher.finalize().into(), canonical)
 }
 
 /// A hash map for storing data about boards that are not affected
 /// by the natural symmetries of the board.
-#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
-pub struct NormalizedBoardMap<V>(FxHashMap<[u8; 32], V>);
+///
+/// With the `verify-hashes` feature, hash collisions are detected and
+/// never return data belonging to a different board.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NormalizedBoardMap<V>(FxHashMap<[u8; 32], (Canonical, V)>);
+
+/// The hashes are not valid map keys in all formats (e.g. JSON),
+/// so the map is serialized as a list of entries.
+impl<V: Serialize> Serialize for NormalizedBoardMap<V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            self.0
+                .iter()
+                .map(|(hash, (canonical, value))| (hash, canonical, value)),
+        )
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for NormalizedBoardMap<V> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let entries = Vec::<([u8; 32], Canonical, V)>::deserialize(deserializer)?;
+        Ok(Self(
+            entries
+                .into_iter()
+                .map(|(hash, canonical, value)| (hash, (canonical, value)))
+                .collect(),
+        ))
+    }
+}
+
+/// Log if a hash was found but belongs to a different board
+fn verify(found: &Canonical, expected: &Canonical) -> bool {
+    if found == expected {
+        true
+    } else {
+        tracing::warn!("Symmetric hash collision detected");
+        false
+    }
+}
 
 impl<V> NormalizedBoardMap<V> {
+    /// Insert a value for a board. Only returns the previous value
+    /// if it was stored for the same board.
     #[allow(dead_code)]
     pub fn insert(&mut self, board: &Board, value: V) -> Option<V> {
-        self.0.insert(symmetric_hash(board), value)
+        let (hash, canonical) = symmetric_hash(board);
+        self.0
+            .insert(hash, (canonical, value))
+            .filter(|(found, _)| verify(found, &canonical))
+            .map(|(_, value)| value)
     }
 
     #[allow(dead_code)]
     pub fn contains_key(&self, board: &Board) -> bool {
-        self.0.contains_key(&symmetric_hash(board))
+        self.get(board).is_some()
     }
 
     #[allow(dead_code)]
     pub fn remove(&mut self, board: &Board) -> Option<V> {
-        self.0.remove(&symmetric_hash(board))
+        let (hash, canonical) = symmetric_hash(board);
+        if !verify(&self.0.get(&hash)?.0, &canonical) {
+            return None;
+        }
+        self.0.remove(&hash).map(|(_, value)| value)
     }
 
     #[allow(dead_code)]
     pub fn get(&self, board: &Board) -> Option<&V> {
-        self.0.get(&symmetric_hash(board))
+        let (hash, canonical) = symmetric_hash(board);
+        self.0
+            .get(&hash)
+            .filter(|(found, _)| verify(found, &canonical))
+            .map(|(_, value)| value)
     }
 
     #[allow(dead_code)]
     pub fn get_mut(&mut self, board: &Board) -> Option<&mut V> {
-        self.0.get_mut(&symmetric_hash(board))
+        let (hash, canonical) = symmetric_hash(board);
+        self.0
+            .get_mut(&hash)
+            .filter(|(found, _)| verify(found, &canonical))
+            .map(|(_, value)| value)
+    }
+
+    /// The number of boards (up to symmetry) in the map
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    /// Iterate over the symmetric hashes of the boards and their values
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8; 32], &V)> {
+        self.0.iter().map(|(hash, (_, value))| (hash, value))
+    }
+
+    /// Keep only the entries for which `keep` returns true when given the
+    /// symmetric hash of the board and its value
+    #[allow(dead_code)]
+    pub fn retain(&mut self, mut keep: impl FnMut(&[u8; 32], &mut V) -> bool) {
+        self.0.retain(|hash, (_, value)| keep(hash, value))
     }
 }
 
 /// A hash set version of [`NormalizedBoardMap`]
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
-pub struct NormalizedBoards(FxHashSet<[u8; 32]>);
+pub struct NormalizedBoards(FxHashMap<[u8; 32], Canonical>);
 
 impl NormalizedBoards {
+    /// Returns whether the board was not already present. A board whose
+    /// hash collides with a different board replaces it.
     pub fn insert(&mut self, board: &Board) -> bool {
-        self.0.insert(symmetric_hash(board))
+        let (hash, canonical) = symmetric_hash(board);
+        match self.0.insert(hash, canonical) {
+            Some(found) => !verify(&found, &canonical),
+            None => true,
+        }
     }
 
     pub fn contains(&self, board: &Board) -> bool {
-        self.0.contains(&symmetric_hash(board))
+        let (hash, canonical) = symmetric_hash(board);
+        self.0
+            .get(&hash)
+            .is_some_and(|found| verify(found, &canonical))
     }
 
     pub fn remove(&mut self, board: &Board) -> bool {
-        self.0.remove(&symmetric_hash(board))
+        if !self.contains(board) {
+            return false;
+        }
+        self.0.remove(&symmetric_hash(board).0).is_some()
     }
 }
 
 #[cfg(test)]
 mod test_symmetries {
     use super::*;
-    use crate::game::{Play, PositionsTracker, Status};
     use crate::game::space::Role;
+    use crate::game::{Play, PositionsTracker, Status};
 
     #[test]
     fn test_symmetric_hash() {
@@ -216,4 +388,80 @@ mod test_symmetries {
             }
         }
     }
+
+    /// Test counting, iterating over and pruning the entries of the map
+    #[test]
+    fn test_board_map_entries() {
+        let mut map = NormalizedBoardMap::default();
+        assert!(map.is_empty());
+        let boards = (0..6)
+            .map(|x| {
+                let mut board = Board::empty();
+                board.set(&Square { x: 5, y: 5 }, Space::King);
+                board.set(&Square { x, y: 1 }, Space::Occupied(Role::Attacker));
+                board
+            })
+            .collect::<Vec<_>>();
+        for (value, board) in boards.iter().enumerate() {
+            assert!(map.insert(board, value).is_none());
+        }
+        // symmetric boards share an entry
+        let mut flipped = boards[0].clone();
+        D8Generator::F.apply(&mut flipped);
+        assert_eq!(map.insert(&flipped, 0), Some(0));
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.iter().count(), 6);
+        let mut values = map.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2, 3, 4, 5]);
+        assert!(
+            map.iter()
+                .all(|(hash, value)| *hash == symmetric_hash(&boards[*value]).0)
+        );
+
+        map.retain(|_, value| {
+            *value *= 10;
+            *value % 20 == 0
+        });
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&boards[2]), Some(&20));
+        assert_eq!(map.get(&boards[3]), None);
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&boards[0]), None);
+    }
+
+    /// Test that boards whose hashes collide are never mistaken
+    /// for one another
+    #[cfg(feature = "verify-hashes")]
+    #[test]
+    fn test_hash_collisions() {
+        let board = Board::default();
+        let mut other = Board::default();
+        other.set(&Square { x: 5, y: 1 }, Space::Empty);
+        other.set(&Square { x: 8, y: 1 }, Space::Occupied(Role::Attacker));
+
+        // simulate a collision by storing the first board under the
+        // hash of the second
+        let (hash, _) = symmetric_hash(&other);
+        let (_, canonical) = symmetric_hash(&board);
+        let mut map = NormalizedBoardMap::default();
+        assert!(map.insert(&other, 2).is_none());
+        map.0.insert(hash, (canonical, 1));
+        assert_eq!(map.get(&other), None);
+        assert!(!map.contains_key(&other));
+        assert_eq!(map.get_mut(&other), None);
+        assert_eq!(map.remove(&other), None);
+        // the colliding entry is replaced by the correct board
+        assert_eq!(map.insert(&other, 2), None);
+        assert_eq!(map.get(&other), Some(&2));
+
+        let mut set = NormalizedBoards::default();
+        set.0.insert(hash, canonical);
+        assert!(!set.contains(&other));
+        assert!(!set.remove(&other));
+        assert!(set.insert(&other));
+        assert!(set.contains(&other));
+        assert!(!set.insert(&other));
+    }
 }
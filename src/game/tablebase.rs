@@ -0,0 +1,401 @@
+//! An endgame tablebase holds the result of perfect play from every
+//! position with only a few pieces left, so that the engine can play
+//! such endgames without searching.
+//!
+//! The tables are built by retrograde analysis. Positions which are
+//! already decided are labeled first and then, pass by pass, positions
+//! which can force their way into a labeled position are labeled too.
+//! Whatever is left at the end can't be forced either way and is drawn.
+//!
+//! Repetitions and the move limit are ignored, so a drawn position is
+//! one neither side can win by force. A table only holds for the rules
+//! it was built under.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::board::Board;
+use crate::game::rules::Rules;
+use crate::game::space::{EXIT_SQUARES, RESTRICTED_SQUARES, Role, Space, Square};
+use crate::game::symmetries::{NormalizedBoardMap, NormalizedBoards};
+use crate::game::{Play, PositionsTracker, Status};
+
+/// The result of a position for the side to move with perfect play,
+/// along with the number of plies until the game is over
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Outcome {
+    Win(u16),
+    Loss(u16),
+    #[default]
+    Draw,
+}
+
+impl Outcome {
+    /// The outcome for `role` of a game that has ended with `status`
+    fn from_status(status: Status, role: Role) -> Option<Self> {
+        match status {
+            Status::Ongoing => None,
+            Status::Draw => Some(Outcome::Draw),
+            status if status == role.victory() => Some(Outcome::Win(0)),
+            Status::Resigned(resigned) if resigned != role => Some(Outcome::Win(0)),
+            _ => Some(Outcome::Loss(0)),
+        }
+    }
+
+    /// A score for ranking outcomes for the side to move. Quicker wins
+    /// and slower losses are better.
+    fn score(&self) -> i32 {
+        match self {
+            Outcome::Win(plies) => i32::from(u16::MAX) - i32::from(*plies),
+            Outcome::Draw => 0,
+            Outcome::Loss(plies) => i32::from(*plies) - i32::from(u16::MAX),
+        }
+    }
+}
+
+/// Where a move from a position in the table being built leads
+enum Child {
+    /// A position whose outcome for its side to move is already known
+    Known(Outcome),
+    /// The index of a position in the table being built
+    Position(usize),
+}
+
+/// The outcomes of every position with at most the given number of
+/// pieces, looked up independently of their orientation
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Tablebase {
+    /// The most attackers in any covered position
+    pub attackers: u8,
+    /// The most defenders in any covered position, not counting the king
+    pub defenders: u8,
+    /// The rules the outcomes were worked out under
+    pub rules: Rules,
+    attackers_to_move: NormalizedBoardMap<Outcome>,
+    defenders_to_move: NormalizedBoardMap<Outcome>,
+}
+
+impl Tablebase {
+    /// Build the table for the king and up to `defenders` defenders against
+    /// up to `attackers` attackers under `rules`. The number of positions
+    /// grows very quickly, so this is only feasible for a handful of pieces.
+    pub fn generate(attackers: u8, defenders: u8, rules: Rules) -> Self {
+        let mut table = Self {
+            attackers,
+            defenders,
+            rules,
+            ..Default::default()
+        };
+        // captures only ever lead to positions with less material,
+        // whose outcomes are then already known
+        for a in 0..=attackers {
+            for d in 0..=defenders {
+                table.add_material(a, d);
+            }
+        }
+        table
+    }
+
+    /// Read a tablebase from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Write the tablebase to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Check if the table has few enough pieces to be covered by the table
+    pub fn covers(&self, board: &Board) -> bool {
+        let (attackers, defenders) = board.material();
+        board.king_square().is_some()
+            && attackers <= self.attackers
+            && defenders <= self.defenders + 1
+    }
+
+    /// The outcome of a position for the side to move, if it is covered
+    pub fn probe(&self, board: &Board, turn: Role) -> Option<Outcome> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.outcomes(turn).get(board).copied()
+    }
+
+    /// The move with the best outcome for the side to move, if the
+    /// position is covered and the game is played under the table's
+    /// `rules`. Only moves which `previous_boards` allows are considered.
+    pub fn best_play(
+        &self,
+        board: &Board,
+        turn: Role,
+        previous_boards: &PositionsTracker,
+        rules: &Rules,
+    ) -> Option<(Play, Outcome)> {
+        if !self.covers(board) || *rules != self.rules {
+            return None;
+        }
+        board
+            .legal_moves_sorted(&turn)
+            .into_iter()
+            .filter_map(|play| {
+                let (child, _, status) = board
+                    .play_internal_with_rules(&play, &Status::Ongoing, previous_boards, rules)
+                    .ok()?;
+                let outcome = match Outcome::from_status(status, turn.opposite()) {
+                    Some(outcome) => outcome,
+                    None => self.probe(&child, turn.opposite())?,
+                };
+                Some((play, Self::parent_outcome(outcome)))
+            })
+            .max_by_key(|(_, outcome)| outcome.score())
+    }
+
+    /// The outcome of a move for the side making it, given the outcome
+    /// for the opponent afterwards
+    fn parent_outcome(child: Outcome) -> Outcome {
+        match child {
+            Outcome::Win(plies) => Outcome::Loss(plies + 1),
+            Outcome::Loss(plies) => Outcome::Win(plies + 1),
+            Outcome::Draw => Outcome::Draw,
+        }
+    }
+
+    fn outcomes(&self, turn: Role) -> &NormalizedBoardMap<Outcome> {
+        match turn {
+            Role::Attacker => &self.attackers_to_move,
+            Role::Defender => &self.defenders_to_move,
+        }
+    }
+
+    /// Label every position with exactly this material
+    fn add_material(&mut self, attackers: u8, defenders: u8) {
+        let boards = Self::enumerate(attackers, defenders);
+        let mut indices = NormalizedBoardMap::default();
+        for (ix, board) in boards.iter().enumerate() {
+            indices.insert(board, ix);
+        }
+        // positions are indexed by the board followed by the side to move
+        let positions = boards
+            .iter()
+            .flat_map(|board| [(board, Role::Attacker), (board, Role::Defender)])
+            .collect::<Vec<_>>();
+        let tracker = PositionsTracker::Counter(0);
+        let mut outcomes = positions
+            .iter()
+            .map(|(board, turn)| {
+                Outcome::from_status(board.compute_status(*turn, &tracker, &self.rules), *turn)
+            })
+            .collect::<Vec<_>>();
+        let children = positions
+            .iter()
+            .zip(&outcomes)
+            .map(|((board, turn), outcome)| match outcome {
+                Some(_) => vec![],
+                None => self.children(board, *turn, &indices),
+            })
+            .collect::<Vec<_>>();
+
+        // The outcome of a child is only used in the pass after the one
+        // in which it is reached, so that wins are as quick and losses
+        // as slow as possible.
+        let known = |outcomes: &[Option<Outcome>], child: &Child| match child {
+            Child::Known(outcome) => Some(*outcome),
+            Child::Position(ix) => outcomes[*ix],
+        };
+        let longest_known = children
+            .iter()
+            .flatten()
+            .filter_map(|child| match child {
+                Child::Known(Outcome::Win(plies) | Outcome::Loss(plies)) => Some(*plies),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        let mut plies = 1;
+        loop {
+            let labeled = (0..positions.len())
+                .filter(|ix| outcomes[*ix].is_none())
+                .filter_map(|ix| {
+                    let mut all_lost = true;
+                    for child in &children[ix] {
+                        match known(&outcomes, child) {
+                            Some(Outcome::Loss(p)) if p + 1 == plies => {
+                                return Some((ix, Outcome::Win(plies)));
+                            }
+                            Some(Outcome::Win(p)) if p < plies => {}
+                            _ => all_lost = false,
+                        }
+                    }
+                    all_lost.then_some((ix, Outcome::Loss(plies)))
+                })
+                .collect::<Vec<_>>();
+            if labeled.is_empty() && plies > longest_known {
+                break;
+            }
+            for (ix, outcome) in labeled {
+                outcomes[ix] = Some(outcome);
+            }
+            plies += 1;
+        }
+
+        for ((board, turn), outcome) in positions.into_iter().zip(outcomes) {
+            let outcomes = match turn {
+                Role::Attacker => &mut self.attackers_to_move,
+                Role::Defender => &mut self.defenders_to_move,
+            };
+            outcomes.insert(board, outcome.unwrap_or_default());
+        }
+    }
+
+    /// Where each move from a position leads. Moves which end the game or
+    /// capture pieces lead to known outcomes, the rest to other positions
+    /// with the same material.
+    fn children(
+        &self,
+        board: &Board,
+        turn: Role,
+        indices: &NormalizedBoardMap<usize>,
+    ) -> Vec<Child> {
+        let tracker = PositionsTracker::Counter(0);
+        let opponent = turn.opposite();
+        board
+            .legal_moves_sorted(&turn)
+            .into_iter()
+            .filter_map(|play| {
+                let (child, captures, status) = board
+                    .play_internal_with_rules(&play, &Status::Ongoing, &tracker, &self.rules)
+                    .ok()?;
+                if let Some(outcome) = Outcome::from_status(status, opponent) {
+                    return Some(Child::Known(outcome));
+                }
+                if !captures.is_empty() {
+                    return self.probe(&child, opponent).map(Child::Known);
+                }
+                let ix = indices.get(&child)?;
+                let side = match opponent {
+                    Role::Attacker => 0,
+                    Role::Defender => 1,
+                };
+                Some(Child::Position(2 * ix + side))
+            })
+            .collect()
+    }
+
+    /// Every board with the given material, up to symmetry. The king may
+    /// be anywhere but the corners, the other pieces anywhere but the
+    /// restricted squares.
+    fn enumerate(attackers: u8, defenders: u8) -> Vec<Board> {
+        let mut seen = NormalizedBoards::default();
+        let mut boards = vec![];
+        // every board has a symmetry with the king in this triangle
+        let kings =
+            Square::iter().filter(|sq| sq.x <= 5 && sq.y <= sq.x && !EXIT_SQUARES.contains(sq));
+        for king in kings {
+            let free = Square::iter()
+                .filter(|sq| *sq != king && !RESTRICTED_SQUARES.contains(sq))
+                .collect::<Vec<_>>();
+            for attacking in combinations(&free, attackers as usize) {
+                let rest = free
+                    .iter()
+                    .filter(|sq| !attacking.contains(sq))
+                    .copied()
+                    .collect::<Vec<_>>();
+                for defending in combinations(&rest, defenders as usize) {
+                    let mut board = Board::empty();
+                    board.set(&king, Space::King);
+                    for sq in &attacking {
+                        board.set(sq, Space::Occupied(Role::Attacker));
+                    }
+                    for sq in &defending {
+                        board.set(sq, Space::Occupied(Role::Defender));
+                    }
+                    if seen.insert(&board) {
+                        boards.push(board);
+                    }
+                }
+            }
+        }
+        boards
+    }
+}
+
+/// Every way of choosing `k` of the squares, in order
+fn combinations(squares: &[Square], k: usize) -> Vec<Vec<Square>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    (0..squares.len())
+        .flat_map(|ix| {
+            combinations(&squares[ix + 1..], k - 1)
+                .into_iter()
+                .map(move |mut rest| {
+                    rest.insert(0, squares[ix]);
+                    rest
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_tablebase {
+    use super::*;
+
+    /// Test that with a lone attacker, a king who can run to the edge with
+    /// two ways into a corner wins, and the attackers lose
+    #[test]
+    fn test_forced_escape() {
+        let table = Tablebase::generate(1, 0, Rules::default());
+        let mut board = Board::empty();
+        board.set(&Square { x: 2, y: 2 }, Space::King);
+        board.set(&Square { x: 8, y: 8 }, Space::Occupied(Role::Attacker));
+        assert_eq!(table.probe(&board, Role::Defender), Some(Outcome::Win(3)));
+        let tracker = PositionsTracker::Counter(0);
+        let (play, outcome) = table
+            .best_play(&board, Role::Defender, &tracker, &Rules::default())
+            .expect("Test failed");
+        assert_eq!(outcome, Outcome::Win(3));
+        let (child, _, _) = board
+            .play_internal(&play, &Status::Ongoing, &tracker)
+            .expect("Test failed");
+        assert_eq!(table.probe(&child, Role::Attacker), Some(Outcome::Loss(2)));
+
+        // the same position in another orientation
+        let mut flipped = Board::empty();
+        flipped.set(&Square { x: 8, y: 2 }, Space::King);
+        flipped.set(&Square { x: 2, y: 8 }, Space::Occupied(Role::Attacker));
+        assert_eq!(table.probe(&flipped, Role::Defender), Some(Outcome::Win(3)));
+
+        // a king next to an open edge escapes straight away
+        let mut board = Board::empty();
+        board.set(&Square { x: 0, y: 5 }, Space::King);
+        board.set(&Square { x: 8, y: 8 }, Space::Occupied(Role::Attacker));
+        assert_eq!(table.probe(&board, Role::Defender), Some(Outcome::Win(1)));
+        // too many pieces for the table
+        assert_eq!(table.probe(&Board::default(), Role::Attacker), None);
+        // the table doesn't hold under other rules
+        let rules = Rules {
+            edge_king_capture: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            table.best_play(&board, Role::Defender, &tracker, &rules),
+            None
+        );
+    }
+
+    /// Test that a tablebase survives being written to a file
+    #[test]
+    fn test_save_load() {
+        let table = Tablebase::generate(0, 1, Rules::default());
+        let dir = tempfile::tempdir().expect("Test failed");
+        let path = dir.path().join("tablebase.json");
+        table.save(&path).expect("Test failed");
+        assert_eq!(Tablebase::load(&path).expect("Test failed"), table);
+    }
+}
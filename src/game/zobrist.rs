@@ -0,0 +1,66 @@
+//! Zobrist hashing of boards. Each piece on each square is assigned a
+//! random key and a board hashes to the XOR of the keys of its pieces,
+//! so that moving a piece only requires updating the keys it touches.
+
+use crate::game::space::{Role, Space, Square};
+
+/// The seed the keys are generated from. Fixing it keeps hashes stable
+/// between runs.
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A random key for each piece type on each square, indexed by
+/// [`Square::to_index`] and then by attacker, defender and king.
+const KEYS: [[u64; 3]; 11 * 11] = generate_keys();
+
+/// The SplitMix64 generator. It is simple enough to run at compile time.
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys() -> [[u64; 3]; 11 * 11] {
+    let mut keys = [[0; 3]; 11 * 11];
+    let mut state = SEED;
+    let mut ix = 0;
+    while ix < 11 * 11 {
+        let mut piece = 0;
+        while piece < 3 {
+            let (next, key) = splitmix64(state);
+            state = next;
+            keys[ix][piece] = key;
+            piece += 1;
+        }
+        ix += 1;
+    }
+    keys
+}
+
+/// The key of a space on a square. Empty squares do not contribute to
+/// the hash.
+pub fn key(square: &Square, space: Space) -> u64 {
+    let piece = match space {
+        Space::Empty => return 0,
+        Space::Occupied(Role::Attacker) => 0,
+        Space::Occupied(Role::Defender) => 1,
+        Space::King => 2,
+    };
+    KEYS[square.to_index()][piece]
+}
+
+#[cfg(test)]
+mod test_zobrist {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Test that no two keys are the same, or zero
+    #[test]
+    fn test_keys_are_distinct() {
+        let keys = KEYS.iter().flatten().copied().collect::<HashSet<_>>();
+        assert_eq!(keys.len(), 3 * 11 * 11);
+        assert!(!keys.contains(&0));
+        assert_eq!(key(&Square { x: 3, y: 4 }, Space::Empty), 0);
+    }
+}
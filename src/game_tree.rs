@@ -1,9 +1,26 @@
-use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::fmt::{Debug, Formatter};
 
+use serde::{Deserialize, Serialize};
+
+use crate::alpha_beta::{CachedPolicy, GameNode, ScoredFromSideToMove, alphabeta};
 use crate::game::board::Board;
-use crate::game::space::{AttackerIter, DefenderIter, EXIT_SQUARES, Role, Square};
-use crate::game::{NormalizedBoards, Play, PositionsTracker, Status};
+use crate::game::rules::Rules;
+use crate::game::space::{
+    AttackerIter, DefenderIter, EXIT_SQUARES, Role, Space, Square, SquareIter,
+};
+use crate::game::{NormalizedBoards, Play, PlayError, PositionsTracker, Status};
+use crate::mcts::float_to_scaled_i64;
+
+/// The positions expanded by [`GameTreeNode::expand`], up to symmetry, with
+/// the most depth each was expanded to with the attackers and with the
+/// defenders to move
+#[cfg(test)]
+pub type SeenPositions = crate::game::NormalizedBoardMap<[usize; 2]>;
+
+/// The reward for winning the game, before it is scaled, large enough
+/// that it outweighs any evaluation of an ongoing game
+pub const WIN_REWARD: f64 = 10000.0;
 
 /// Determine if a position is "quiet" or not.
 /// Currently, we define threats as the ability
@@ -22,6 +39,28 @@ pub trait SelectionPolicy {
     /// Get the heuristic's evaluation of the position
     /// for the defending player
     fn eval_defender(&self, child: &Self::TreeNode) -> i64;
+    /// Get the heuristic's evaluation of the position for
+    /// the player whose turn it is
+    fn eval(&self, node: &Self::TreeNode) -> i64
+    where
+        Self::TreeNode: GameNode,
+    {
+        match node.turn() {
+            Role::Attacker => self.eval_attacker(node),
+            Role::Defender => self.eval_defender(node),
+        }
+    }
+    /// The scaled reward of a finished game for the player whose turn it
+    /// is, or `None` if the game is ongoing. Evaluators check this first,
+    /// so that all policies agree on the value of won and lost games.
+    fn terminal_value(&self, node: &GameTreeNode) -> Option<i64>
+    where
+        Self: SelectionPolicy<TreeNode = GameTreeNode>,
+    {
+        node.status
+            .result_for(&node.turn)
+            .map(|result| float_to_scaled_i64(result * WIN_REWARD))
+    }
     /// Given a game node and two indices of it children, figure out which one is better
     /// to explore.
     fn compare_children(
@@ -32,12 +71,46 @@ pub trait SelectionPolicy {
     ) -> std::cmp::Ordering;
 }
 
+/// The boards preceding the current one in a game, most recent first.
+/// Only the last few are kept, as many as the limit allows.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RecentBoards {
+    limit: usize,
+    boards: VecDeque<Board>,
+}
+
+impl RecentBoards {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            boards: VecDeque::with_capacity(limit),
+        }
+    }
+
+    /// Record the board that was just moved away from
+    pub fn push(&mut self, board: &Board) {
+        if self.limit == 0 {
+            return;
+        }
+        self.boards.truncate(self.limit - 1);
+        self.boards.push_front(board.clone());
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Board> {
+        self.boards.iter()
+    }
+}
+
 #[derive(Clone)]
 pub struct GameTreeNode {
     pub status: Status,
     pub previous_boards: PositionsTracker,
     pub turn: Role,
     pub current_board: Board,
+    pub rules: Rules,
+    /// The boards before the current one, for encoding the history
+    /// of the game. None are kept unless asked for.
+    pub recent_boards: RecentBoards,
 }
 
 impl Debug for GameTreeNode {
@@ -56,6 +129,8 @@ impl PartialEq for GameTreeNode {
             && self.previous_boards == other.previous_boards
             && self.turn == other.turn
             && self.current_board == other.current_board
+            && self.rules == other.rules
+            && self.recent_boards == other.recent_boards
     }
 }
 impl Eq for GameTreeNode {}
@@ -67,50 +142,181 @@ impl GameTreeNode {
             previous_boards: positions_tracker,
             turn: Default::default(),
             current_board: Default::default(),
+            rules: Default::default(),
+            recent_boards: Default::default(),
         }
     }
 
+    /// Keep enough of the boards before the current one to encode
+    /// `history_planes` positions in total
+    pub fn with_history_planes(self, history_planes: usize) -> Self {
+        Self {
+            recent_boards: RecentBoards::new(history_planes.saturating_sub(1)),
+            ..self
+        }
+    }
+
+    /// Hand the move to `turn`, e.g. for variants where the defenders
+    /// move first
+    pub fn with_turn(self, turn: Role) -> Self {
+        Self { turn, ..self }
+    }
+
     fn play(
         &self,
         from: Square,
         to: Square,
         normalized_games: &mut NormalizedBoards,
+        normalize: bool,
     ) -> Option<Self> {
         let play = Play {
             role: self.turn,
             from,
             to,
         };
-        if let Ok((board, _, status)) =
-            self.current_board
-                .play_internal(&play, &self.status, &self.previous_boards)
+        if let Ok((board, _, status)) = self.current_board.play_internal_with_rules(
+            &play,
+            &self.status,
+            &self.previous_boards,
+            &self.rules,
+        ) && (!normalize || normalized_games.insert(&board))
         {
-            if normalized_games.insert(&board) {
-                let mut game = self.clone();
-                game.previous_boards.insert(&board);
-                game.current_board = board;
-                game.status = status;
-                game.turn = game.turn.opposite();
-                return Some(game);
-            };
-        }
+            return Some(self.advance(board, status));
+        };
         None
     }
 
+    /// Play a specific move and return the resulting node. Unlike the
+    /// child generators, symmetric positions are not discarded.
+    ///
+    /// Errors on an illegal move
+    pub fn apply_play(&self, play: &Play) -> Result<Self, PlayError> {
+        if play.role != self.turn {
+            return Err(PlayError::WrongTurn {
+                square: play.from,
+                found: play.role,
+            });
+        }
+        let (board, _, status) = self.current_board.play_internal_with_rules(
+            play,
+            &self.status,
+            &self.previous_boards,
+            &self.rules,
+        )?;
+        Ok(self.advance(board, status))
+    }
+
+    /// Create the node following this one given the resulting board and status
+    fn advance(&self, board: Board, status: Status) -> Self {
+        let mut game = self.clone();
+        game.previous_boards.insert(&board);
+        game.recent_boards.push(&self.current_board);
+        game.current_board = board;
+        game.status = status;
+        game.turn = game.turn.opposite();
+        game
+    }
+
     /// Get a vector of child games from this game by checking all
     /// legal moves. We discard children that are symmetrically
     /// equivalent to others.
     pub fn get_children(&self) -> Vec<GameTreeNode> {
+        self.get_children_with(true)
+    }
+
+    /// Like [`GameTreeNode::get_children`], but symmetrically equivalent
+    /// children are only discarded if `normalize` is set. Otherwise, there
+    /// is a child for every legal move.
+    pub fn get_children_with(&self, normalize: bool) -> Vec<GameTreeNode> {
+        self.get_plays_with(normalize)
+            .into_iter()
+            .map(|(_, node)| node)
+            .collect()
+    }
+
+    /// Like [`GameTreeNode::get_children`], but also returns the play
+    /// leading to each child.
+    pub fn get_plays(&self) -> Vec<(Play, GameTreeNode)> {
+        self.get_plays_with(true)
+    }
+
+    /// Like [`GameTreeNode::get_children_with`], but also returns the play
+    /// leading to each child.
+    pub fn get_plays_with(&self, normalize: bool) -> Vec<(Play, GameTreeNode)> {
         let mut normalized = NormalizedBoards::default();
-        let mut children = vec![];
+        let mut plays = vec![];
         for from in Square::iter() {
             for to in Square::iter() {
-                if let Some(node) = self.play(from, to, &mut normalized) {
-                    children.push(node);
+                if let Some(node) = self.play(from, to, &mut normalized, normalize) {
+                    let play = Play {
+                        role: self.turn,
+                        from,
+                        to,
+                    };
+                    plays.push((play, node));
+                }
+            }
+        }
+        plays
+    }
+
+    /// Expand the tree below this node to the given depth and return the
+    /// number of nodes expanded. If a `seen` map is given, it should be shared
+    /// by the whole search: a position already expanded anywhere in it to at
+    /// least the remaining depth is not expanded again, so transpositions are
+    /// searched once and the tree is traversed as a DAG. Positions are told
+    /// apart by their boards and the side to move.
+    #[cfg(test)]
+    pub fn expand(&self, depth: usize, mut seen: Option<&mut SeenPositions>) -> usize {
+        if depth == 0 || self.is_terminal() {
+            return 0;
+        }
+        if let Some(seen) = seen.as_deref_mut() {
+            let side = match self.turn {
+                Role::Attacker => 0,
+                Role::Defender => 1,
+            };
+            match seen.get_mut(&self.current_board) {
+                // reached before with at least as much depth left
+                Some(depths) if depths[side] >= depth => return 0,
+                Some(depths) => depths[side] = depth,
+                None => {
+                    let mut depths = [0; 2];
+                    depths[side] = depth;
+                    seen.insert(&self.current_board, depths);
                 }
             }
         }
-        children
+        1 + self
+            .get_children()
+            .iter()
+            .map(|child| child.expand(depth - 1, seen.as_deref_mut()))
+            .sum::<usize>()
+    }
+
+    /// Search every legal move to the given depth and return them with
+    /// their scores for the side to move, best first. The evaluations of
+    /// positions are shared between the searches.
+    pub fn evaluate_children<S>(
+        &self,
+        policy: &S,
+        depth: usize,
+    ) -> Vec<(Play, ScoredFromSideToMove)>
+    where
+        S: SelectionPolicy<TreeNode = GameTreeNode>,
+    {
+        let policy = CachedPolicy::new(policy);
+        let mut scored = self
+            .get_plays_with(false)
+            .into_iter()
+            .map(|(play, child)| {
+                // the search scores the child for the opponent, who moves next
+                let score = -alphabeta::<GameSummary, _, _>(&child, &policy, depth);
+                (play, score)
+            })
+            .collect::<Vec<_>>();
+        scored.sort_by_key(|(play, score)| (std::cmp::Reverse(*score), play.sort_key()));
+        scored
     }
 
     /// Get an iterator over the child games from this game by checking all
@@ -122,6 +328,12 @@ impl GameTreeNode {
     /// three rows and for defenders, we look at moves from the inner
     /// 5 x 5 square to the outer three rows.
     pub fn children(self) -> ChildIterator {
+        self.children_with(true)
+    }
+
+    /// Like [`GameTreeNode::children`], but symmetrically equivalent
+    /// children are only discarded if `normalize` is set.
+    pub fn children_with(self, normalize: bool) -> ChildIterator {
         let from = match self.turn {
             Role::Attacker => ChildIteratorType::Attacker(Default::default()),
             Role::Defender => ChildIteratorType::Defender(Default::default()),
@@ -129,10 +341,56 @@ impl GameTreeNode {
         ChildIterator {
             node: self,
             from,
+            current: None,
             to: ChildIteratorType::Attacker(Default::default()),
             normalized: Default::default(),
+            normalize,
         }
     }
+
+    /// Get an iterator over the child games from this game, along with
+    /// the play that produced each of them. See [`GameTreeNode::children`].
+    pub fn plays(self) -> PlayIterator {
+        PlayIterator(self.children())
+    }
+
+    /// The number of legal moves in this position. Unlike the child
+    /// generators, symmetric positions are not discarded.
+    pub fn legal_move_count(&self) -> usize {
+        if self.is_terminal() {
+            return 0;
+        }
+        // defenders may not repeat positions and the king's moves may be
+        // limited, so we must check where each of their moves lead
+        let checked = matches!(self.previous_boards, PositionsTracker::Previous(_))
+            || self.rules.king_move_range.is_some();
+        if self.turn == Role::Defender && checked {
+            let mut count = 0;
+            self.current_board.for_each_move(&self.turn, |from, to| {
+                let play = Play {
+                    role: self.turn,
+                    from,
+                    to,
+                };
+                if self
+                    .current_board
+                    .play_internal_with_rules(
+                        &play,
+                        &self.status,
+                        &self.previous_boards,
+                        &self.rules,
+                    )
+                    .is_ok()
+                {
+                    count += 1;
+                }
+            });
+            count
+        } else {
+            self.current_board.mobility(&self.turn)
+        }
+    }
+
     pub fn is_terminal(&self) -> bool {
         !matches!(self.status, Status::Ongoing)
     }
@@ -141,9 +399,20 @@ impl GameTreeNode {
         &self,
         policy: &S,
     ) -> GameTreeNode {
-        let legal_actions = match self.threats() {
-            Threats::Quiet => self.get_children(),
-            Threats::Plays(threats) => threats,
+        let legal_actions = match self.turn {
+            // the attackers' threats are found among all of their moves,
+            // so only generate those once
+            Role::Attacker => {
+                let children = self.get_children();
+                match self.winning_children(&children) {
+                    Threats::Quiet => children,
+                    Threats::Plays(threats) => threats,
+                }
+            }
+            Role::Defender => match self.threats() {
+                Threats::Quiet => self.get_children(),
+                Threats::Plays(threats) => threats,
+            },
         };
         if legal_actions.is_empty() {
             unreachable!();
@@ -155,59 +424,51 @@ impl GameTreeNode {
             .unwrap()
     }
 
+    /// The reward for `for_player` at the end of the game. A game that
+    /// has not finished scores as a draw.
     pub fn get_result(&self, for_player: &Role) -> f64 {
-        match for_player {
-            Role::Attacker => match self.status {
-                Status::AttackersWin => 1.0,
-                Status::DefendersWin => -1.0,
-                Status::Ongoing => unreachable!(),
-                Status::Draw => 0.0,
-            },
-            Role::Defender => match self.status {
-                Status::AttackersWin => -1.0,
-                Status::DefendersWin => 1.0,
-                Status::Ongoing => unreachable!(),
-                Status::Draw => 0.0,
-            },
-        }
+        self.status.result_for(for_player).unwrap_or(0.0)
     }
 
-    /// Return a list of threats. If there are none, label the position
-    /// quiet. This is subjective and will be used to tweak the performance
-    /// of the final AI in the endgame.
+    /// Return a list of threats for the player to move. If there are none,
+    /// label the position quiet. This is subjective and will be used to
+    /// tweak the performance of the final AI in the endgame.
+    ///
+    /// For defenders, threats are the king escaping. For attackers, they
+    /// are moves which win immediately by capturing or surrounding the king.
     pub fn threats(&self) -> Threats {
-        if let Role::Defender = self.turn {
-            let mut boards = HashSet::with_capacity(4);
-            let Some(king) = self.current_board.find_the_king() else {
-                return Threats::Quiet;
-            };
-            let mut threats = Vec::with_capacity(4);
-            for corner in EXIT_SQUARES {
-                let play = Play {
-                    role: Role::Defender,
-                    from: king,
-                    to: corner,
+        let children = match self.turn {
+            Role::Defender => {
+                let Some(king) = self.current_board.king_square() else {
+                    return Threats::Quiet;
                 };
-                let mut game = self.clone();
-                if let Ok((_, status)) =
-                    game.current_board
-                        .play(&play, &game.status, &mut game.previous_boards)
-                {
-                    game.current_board.normalize();
-                    game.status = status;
-                    game.turn = game.turn.opposite();
-                    if boards.insert(game.current_board.clone()) {
-                        threats.push(game)
-                    }
-                }
-            }
-            if threats.is_empty() {
-                Threats::Quiet
-            } else {
-                Threats::Plays(threats)
+                let mut normalized = NormalizedBoards::default();
+                EXIT_SQUARES
+                    .into_iter()
+                    .filter_map(|corner| self.play(king, corner, &mut normalized, true))
+                    .collect()
             }
-        } else {
+            Role::Attacker => self.get_children(),
+        };
+        self.winning_children(&children)
+    }
+
+    /// The children in which the player to move has won, with their boards
+    /// normalized. Symmetric children should already have been discarded.
+    fn winning_children(&self, children: &[GameTreeNode]) -> Threats {
+        let threats = children
+            .iter()
+            .filter(|child| child.status == self.turn.victory())
+            .map(|child| {
+                let mut child = child.clone();
+                child.current_board.normalize();
+                child
+            })
+            .collect::<Vec<_>>();
+        if threats.is_empty() {
             Threats::Quiet
+        } else {
+            Threats::Plays(threats)
         }
     }
 }
@@ -217,15 +478,8 @@ impl GameTreeNode {
 pub enum ChildIteratorType {
     Attacker(AttackerIter),
     Defender(DefenderIter),
-}
-
-impl ChildIteratorType {
-    fn reset(&mut self) {
-        *self = match self {
-            ChildIteratorType::Attacker(_) => ChildIteratorType::Attacker(Default::default()),
-            ChildIteratorType::Defender(_) => ChildIteratorType::Defender(Default::default()),
-        }
-    }
+    /// Every square, including the restricted ones only the king may use
+    All(SquareIter),
 }
 
 impl Iterator for ChildIteratorType {
@@ -235,44 +489,95 @@ impl Iterator for ChildIteratorType {
         match self {
             ChildIteratorType::Attacker(iter) => iter.next(),
             ChildIteratorType::Defender(iter) => iter.next(),
+            ChildIteratorType::All(iter) => iter.next(),
         }
     }
 }
 
 /// A iterator over child nodes of a node in the game tree.
-/// Only returns normalized boards in an attempt to reduce
-/// the branching factor.
+/// If `normalize` is set, only returns normalized boards in an
+/// attempt to reduce the branching factor.
 pub struct ChildIterator {
     pub node: GameTreeNode,
     pub from: ChildIteratorType,
+    /// The square moves are currently being tried from
+    pub current: Option<Square>,
     pub to: ChildIteratorType,
     pub normalized: NormalizedBoards,
+    pub normalize: bool,
 }
 
-impl Iterator for ChildIterator {
-    type Item = GameTreeNode;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        for from in self.from.by_ref() {
+impl ChildIterator {
+    /// Get the next child node and the play that produced it
+    fn next_play(&mut self) -> Option<(Play, GameTreeNode)> {
+        loop {
+            // carry on with the moves from the last square before
+            // moving on to the next
+            let from = match self.current {
+                Some(from) => from,
+                None => {
+                    let from = *self.current.insert(self.from.next()?);
+                    // only the king may move to the restricted squares
+                    self.to = if self.node.current_board.get(&from) == Space::King {
+                        ChildIteratorType::All(Default::default())
+                    } else {
+                        ChildIteratorType::Attacker(Default::default())
+                    };
+                    from
+                }
+            };
             for to in self.to.by_ref() {
-                if let Some(node) = self.node.play(from, to, &mut self.normalized) {
-                    return Some(node);
+                if let Some(node) = self
+                    .node
+                    .play(from, to, &mut self.normalized, self.normalize)
+                {
+                    let play = Play {
+                        role: self.node.turn,
+                        from,
+                        to,
+                    };
+                    return Some((play, node));
                 }
             }
-            self.to.reset();
+            self.current = None;
         }
-        None
+    }
+}
+
+impl Iterator for ChildIterator {
+    type Item = GameTreeNode;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_play().map(|(_, node)| node)
+    }
+}
+
+/// A iterator over child nodes of a node in the game tree which also
+/// yields the play leading to each child.
+pub struct PlayIterator(ChildIterator);
+
+impl Iterator for PlayIterator {
+    type Item = (Play, GameTreeNode);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_play()
     }
 }
 
 /// An abbreviated view of a game state. Used when game history is
 /// not needed to minimize space usage.
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Hash, PartialEq, Eq, Serialize)]
 pub struct GameSummary {
     pub status: Status,
     pub moves: usize,
+    /// The number of times the current position occurred before
+    pub repetitions: usize,
     pub turn: Role,
     pub current_board: Board,
+    /// The boards before the current one, most recent first. Only
+    /// kept if the node was asked to, see [`GameTreeNode::with_history_planes`].
+    #[serde(default)]
+    pub history: Vec<Board>,
 }
 
 impl From<&GameTreeNode> for GameSummary {
@@ -280,8 +585,521 @@ impl From<&GameTreeNode> for GameSummary {
         Self {
             status: node.status,
             moves: node.previous_boards.len(),
+            // the current board has already been counted, unless
+            // this is the start of the game
+            repetitions: node
+                .previous_boards
+                .occurrences(&node.current_board)
+                .saturating_sub(1),
             turn: node.turn,
             current_board: node.current_board.clone(),
+            history: node.recent_boards.iter().cloned().collect(),
+        }
+    }
+}
+
+impl GameSummary {
+    /// The game as seen by the side to move, posed as the attackers. On the
+    /// defenders' turn, the attackers and defenders swap pieces, so that
+    /// the same encoding always describes the side to move as "us".
+    pub fn canonical_perspective(&self) -> GameSummary {
+        match self.turn {
+            Role::Attacker => self.clone(),
+            Role::Defender => GameSummary {
+                status: match self.status {
+                    Status::AttackersWin => Status::DefendersWin,
+                    Status::DefendersWin => Status::AttackersWin,
+                    Status::Resigned(role) => Status::Resigned(role.opposite()),
+                    status => status,
+                },
+                turn: Role::Attacker,
+                current_board: self.current_board.flip_perspective(),
+                history: self.history.iter().map(Board::flip_perspective).collect(),
+                ..*self
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_game_tree {
+    use super::*;
+    use crate::alpha_beta::heuristic::HeuristicPolicy;
+    use crate::game::LiveGame;
+    use crate::game::space::Space;
+    use crate::mcts::float_to_scaled_i64;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::BTreeSet;
+
+    /// Test that every yielded play transforms the parent
+    /// into the yielded child
+    #[test]
+    fn test_plays() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let children = root.clone().children().collect::<Vec<_>>();
+        let mut count = 0;
+        for (play, child) in root.clone().plays() {
+            assert_eq!(play.role, root.turn);
+            let (board, _, status) = root
+                .current_board
+                .play_internal(&play, &root.status, &root.previous_boards)
+                .expect("Test failed");
+            assert_eq!(board, child.current_board);
+            assert_eq!(status, child.status);
+            assert_eq!(child.turn, root.turn.opposite());
+            assert_eq!(child, children[count]);
+            count += 1;
+        }
+        assert_eq!(count, children.len());
+    }
+
+    /// Test that without normalization, there is a child for every legal
+    /// move and each child keeps the orientation of the actual board
+    #[test]
+    fn test_children_without_normalization() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let plays = root.get_plays_with(false);
+        assert_eq!(plays.len(), root.current_board.mobility(&root.turn));
+        assert!(plays.len() > root.get_plays().len());
+        for (play, child) in &plays {
+            let (board, _, _) = root
+                .current_board
+                .play_internal(play, &root.status, &root.previous_boards)
+                .expect("Test failed");
+            assert_eq!(board, child.current_board);
+        }
+        // all four rotations of the same move are kept
+        let mut boards = plays
+            .iter()
+            .map(|(_, child)| child.current_board.clone())
+            .collect::<Vec<_>>();
+        boards.retain(|board| board.same_up_to_symmetry(&plays[0].1.current_board));
+        assert!(boards.len() > 1);
+        let children = root.get_children_with(false);
+        let lazy = root.clone().children_with(false).collect::<Vec<_>>();
+        assert!(lazy.len() > root.clone().children().count());
+        assert!(lazy.iter().all(|child| children.contains(child)));
+    }
+
+    /// Test that every move generator agrees with the reference one,
+    /// which tries every pair of squares, on random positions
+    #[test]
+    fn test_move_generators_agree() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for ix in 0..50 {
+            let (board, turn) = Board::random_legal_position(&mut rng, ix);
+            let root = GameTreeNode {
+                turn,
+                current_board: board,
+                ..GameTreeNode::new(PositionsTracker::Counter(0))
+            };
+            let reference = root
+                .get_plays_with(false)
+                .into_iter()
+                .map(|(play, _)| play)
+                .collect::<BTreeSet<_>>();
+            assert_eq!(reference.len(), root.current_board.mobility(&turn));
+            assert_eq!(reference.len(), root.legal_move_count());
+            let sorted = root.current_board.legal_moves_sorted(&turn);
+            assert_eq!(sorted.len(), reference.len());
+            assert_eq!(sorted.into_iter().collect::<BTreeSet<_>>(), reference);
+            let lazy = PlayIterator(root.clone().children_with(false))
+                .map(|(play, _)| play)
+                .collect::<Vec<_>>();
+            assert_eq!(lazy.len(), reference.len());
+            assert_eq!(lazy.into_iter().collect::<BTreeSet<_>>(), reference);
+        }
+    }
+
+    /// A policy which scores every position by whose turn it is
+    struct RolePolicy;
+
+    impl SelectionPolicy for RolePolicy {
+        type TreeNode = GameTreeNode;
+
+        fn eval_attacker(&self, _: &GameTreeNode) -> i64 {
+            1
+        }
+
+        fn eval_defender(&self, _: &GameTreeNode) -> i64 {
+            -1
+        }
+
+        fn compare_children(
+            &self,
+            _: &GameTreeNode,
+            _: &GameTreeNode,
+            _: &GameTreeNode,
+        ) -> std::cmp::Ordering {
+            std::cmp::Ordering::Equal
         }
     }
+
+    /// Test that `eval` uses the evaluation for the player to move
+    #[test]
+    fn test_eval_dispatches_on_turn() {
+        let mut node = GameTreeNode::new(PositionsTracker::Counter(0));
+        for role in [Role::Attacker, Role::Defender] {
+            node.turn = role;
+            let expected = match role {
+                Role::Attacker => RolePolicy.eval_attacker(&node),
+                Role::Defender => RolePolicy.eval_defender(&node),
+            };
+            assert_eq!(RolePolicy.eval(&node), expected);
+        }
+        assert_ne!(
+            RolePolicy.eval_attacker(&node),
+            RolePolicy.eval_defender(&node)
+        );
+    }
+
+    /// Test that the children are ranked by how good they are for the
+    /// side to move
+    #[test]
+    fn test_evaluate_children() {
+        let board = [
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+            ".O.........",
+            "K.O........",
+            "OO.........",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ];
+        let mut game = LiveGame {
+            current_board: Board::try_from(board).expect("Test failed"),
+            turn: Role::Defender,
+            ..Default::default()
+        };
+        let root = GameTreeNode::from(&mut game);
+        let scored = root.evaluate_children(&HeuristicPolicy::default(), 2);
+        assert_eq!(scored.len(), root.current_board.mobility(&Role::Defender));
+        assert!(scored.windows(2).all(|w| w[0].1 >= w[1].1));
+        let win = ScoredFromSideToMove(float_to_scaled_i64(10000.0));
+        // running up the edge wins, whether straight into the corner or
+        // next move, and stepping between the attackers is the worst move
+        let corner = Play {
+            role: Role::Defender,
+            from: Square { x: 0, y: 5 },
+            to: Square { x: 0, y: 0 },
+        };
+        assert_eq!(scored[0].1, win);
+        assert!(scored.contains(&(corner, win)));
+        let (last, score) = scored.last().expect("Test failed");
+        assert_eq!(
+            last,
+            &Play {
+                role: Role::Defender,
+                from: Square { x: 0, y: 5 },
+                to: Square { x: 1, y: 5 },
+            }
+        );
+        assert!(*score < win);
+    }
+
+    /// Test that resigning is a loss for the side that resigned
+    #[test]
+    fn test_resigned_result() {
+        let mut game = LiveGame::default();
+        game.resign();
+        assert_eq!(game.status, Status::Resigned(Role::Attacker));
+        let node = GameTreeNode::from(&mut game);
+        assert!(node.is_terminal());
+        assert_eq!(node.get_result(&Role::Attacker), -1.0);
+        assert_eq!(node.get_result(&Role::Defender), 1.0);
+
+        let mut game = LiveGame {
+            turn: Role::Defender,
+            ..Default::default()
+        };
+        game.resign();
+        assert_eq!(game.status, Status::Resigned(Role::Defender));
+        let node = GameTreeNode::from(&mut game);
+        assert_eq!(node.get_result(&Role::Attacker), 1.0);
+        assert_eq!(node.get_result(&Role::Defender), -1.0);
+
+        // a finished game cannot be resigned
+        game.status = Status::Draw;
+        game.resign();
+        assert_eq!(game.status, Status::Draw);
+    }
+
+    /// Test that counting legal moves agrees with trying every move
+    #[test]
+    fn test_legal_move_count() {
+        let tried = |node: &GameTreeNode| {
+            let mut count = 0;
+            for from in Square::iter() {
+                for to in Square::iter() {
+                    let play = Play {
+                        role: node.turn,
+                        from,
+                        to,
+                    };
+                    if node.apply_play(&play).is_ok() {
+                        count += 1;
+                    }
+                }
+            }
+            count
+        };
+        let mut root = GameTreeNode::new(PositionsTracker::Counter(0));
+        assert_eq!(root.legal_move_count(), tried(&root));
+        assert_eq!(root.current_board.mobility(&Role::Attacker), 116);
+        root.turn = Role::Defender;
+        assert_eq!(root.legal_move_count(), tried(&root));
+        assert_eq!(root.current_board.mobility(&Role::Defender), 60);
+
+        // the king may move to the corners but others can't
+        // stop on the throne
+        let board = Board::try_from([
+            "...........",
+            "K..........",
+            "...........",
+            "...........",
+            "...........",
+            "..O........",
+            "...........",
+            "...........",
+            ".......X...",
+            "...........",
+            ".........O.",
+        ])
+        .expect("Test failed");
+        let mut node = GameTreeNode {
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        assert_eq!(node.legal_move_count(), tried(&node));
+        node.turn = Role::Defender;
+        assert_eq!(node.legal_move_count(), tried(&node));
+
+        // defenders may not repeat positions
+        let mut previous = node.clone();
+        previous.previous_boards = PositionsTracker::Previous(Default::default());
+        let repeated = previous
+            .apply_play(&Play {
+                role: Role::Defender,
+                from: Square { x: 0, y: 1 },
+                to: Square { x: 0, y: 2 },
+            })
+            .expect("Test failed");
+        previous.previous_boards = repeated.previous_boards;
+        assert_eq!(previous.legal_move_count(), tried(&previous));
+        assert_eq!(previous.legal_move_count(), node.legal_move_count() - 1);
+
+        // the king may only step to a neighbouring square
+        let mut limited = node.clone();
+        limited.rules.king_move_range = Some(1);
+        assert_eq!(limited.legal_move_count(), tried(&limited));
+        assert!(limited.legal_move_count() < node.legal_move_count());
+
+        node.status = Status::Draw;
+        assert_eq!(node.legal_move_count(), 0);
+    }
+
+    /// Test that illegal plays are rejected and legal
+    /// ones advance the game
+    #[test]
+    fn test_apply_play() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let err = root
+            .apply_play(&Play {
+                role: Role::Defender,
+                from: Square { x: 5, y: 3 },
+                to: Square { x: 5, y: 2 },
+            })
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PlayError::WrongTurn {
+                found: Role::Defender,
+                ..
+            }
+        ));
+        let err = root
+            .apply_play(&Play {
+                role: Role::Attacker,
+                from: Square { x: 5, y: 3 },
+                to: Square { x: 5, y: 2 },
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Attempted to move the defender on F8, which belongs to the opposite player"
+        );
+        let err = root
+            .apply_play(&Play {
+                role: Role::Attacker,
+                from: Square { x: 5, y: 1 },
+                to: Square { x: 5, y: 4 },
+            })
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PlayError::MoveThroughPiece(Square { x: 5, y: 3 })
+        ));
+        let err = root
+            .apply_play(&Play {
+                role: Role::Attacker,
+                from: Square { x: 3, y: 0 },
+                to: Square { x: 0, y: 0 },
+            })
+            .unwrap_err();
+        assert!(matches!(err, PlayError::RestrictedSquare));
+        let err = root
+            .apply_play(&Play {
+                role: Role::Attacker,
+                from: Square { x: 3, y: 0 },
+                to: Square { x: 4, y: 1 },
+            })
+            .unwrap_err();
+        assert!(matches!(err, PlayError::StraightLine));
+
+        let play = Play {
+            role: Role::Attacker,
+            from: Square { x: 5, y: 1 },
+            to: Square { x: 8, y: 1 },
+        };
+        let child = root.apply_play(&play).expect("Test failed");
+        assert_eq!(child.turn, Role::Defender);
+        assert_eq!(child.status, Status::Ongoing);
+        assert_eq!(child.previous_boards.len(), 1);
+        assert_eq!(child.current_board.get(&play.from), Space::Empty);
+        assert_eq!(
+            child.current_board.get(&play.to),
+            Space::Occupied(Role::Attacker)
+        );
+
+        // the mirror image of the above play is not discarded
+        let mirrored = root
+            .apply_play(&Play {
+                role: Role::Attacker,
+                from: Square { x: 5, y: 1 },
+                to: Square { x: 2, y: 1 },
+            })
+            .expect("Test failed");
+        assert_ne!(child.current_board, mirrored.current_board);
+
+        let mut finished = child.clone();
+        finished.status = Status::DefendersWin;
+        let err = finished
+            .apply_play(&Play {
+                role: Role::Defender,
+                from: Square { x: 5, y: 3 },
+                to: Square { x: 5, y: 2 },
+            })
+            .unwrap_err();
+        assert!(matches!(err, PlayError::GameFinished));
+    }
+
+    /// Test that children of a node taken from a live game never
+    /// repeat a position from the game's history on the defenders' turn
+    #[test]
+    fn test_children_respect_history() {
+        let attacker_out = Play {
+            role: Role::Attacker,
+            from: Square { x: 0, y: 3 },
+            to: Square { x: 1, y: 3 },
+        };
+        let attacker_back = Play {
+            role: Role::Attacker,
+            from: Square { x: 1, y: 3 },
+            to: Square { x: 0, y: 3 },
+        };
+        let defender_out = Play {
+            role: Role::Defender,
+            from: Square { x: 5, y: 3 },
+            to: Square { x: 5, y: 2 },
+        };
+        let defender_back = Play {
+            role: Role::Defender,
+            from: Square { x: 5, y: 2 },
+            to: Square { x: 5, y: 3 },
+        };
+        let mut game = LiveGame::default();
+        game.play(&attacker_out).expect("Test failed");
+        game.play(&defender_out).expect("Test failed");
+        let repeated = game.current_board.clone();
+        game.play(&attacker_back).expect("Test failed");
+        game.play(&defender_back).expect("Test failed");
+        game.play(&attacker_out).expect("Test failed");
+
+        let node = GameTreeNode::from(&mut game);
+        assert_eq!(node.turn, Role::Defender);
+        // the attackers have repeated the position after their first move
+        assert_eq!(GameSummary::from(&node).repetitions, 1);
+        let err = node.apply_play(&defender_out).unwrap_err();
+        assert!(matches!(err, PlayError::RepeatedPosition));
+        assert!(
+            node.get_plays()
+                .iter()
+                .all(|(play, child)| *play != defender_out && child.current_board != repeated)
+        );
+        assert!(
+            node.clone()
+                .children()
+                .all(|child| child.current_board != repeated)
+        );
+        assert_eq!(
+            node.legal_move_count(),
+            node.current_board.mobility(&Role::Defender) - 1
+        );
+
+        // without the history, the repeating move is generated
+        let forgetful = GameTreeNode {
+            previous_boards: PositionsTracker::Counter(node.previous_boards.len()),
+            ..node
+        };
+        assert!(
+            forgetful
+                .get_plays()
+                .iter()
+                .any(|(play, child)| *play == defender_out && child.current_board == repeated)
+        );
+    }
+
+    /// Test that sharing the set of expanded positions across the search
+    /// skips transpositions
+    #[test]
+    fn test_expand_with_seen_positions() {
+        // the king can shuffle along the edge and return to his square
+        // by different routes while the attackers have few moves
+        let board = Board::try_from([
+            ".OOOOOOOOO.",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOO.OOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            "OOOOOOOOOOO",
+            ".O..K..OOO.",
+        ])
+        .expect("Test failed");
+        let node = GameTreeNode {
+            turn: Role::Defender,
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        assert_eq!(node.expand(0, None), 0);
+        assert_eq!(node.expand(1, None), 1);
+        assert_eq!(node.expand(4, None), 64);
+        // returning the king to his square transposes
+        let mut seen = SeenPositions::default();
+        assert_eq!(node.expand(4, Some(&mut seen)), 60);
+        // the positions are only expanded once
+        assert_eq!(node.expand(4, Some(&mut seen)), 0);
+        // positions reached with more depth left are expanded again
+        let mut seen = SeenPositions::default();
+        assert_eq!(node.expand(2, Some(&mut seen)), node.expand(2, None));
+        assert_eq!(node.expand(4, Some(&mut seen)), 60);
+    }
 }
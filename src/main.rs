@@ -1,10 +1,18 @@
 use std::io;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process::exit;
 use std::str::FromStr;
+use std::time::Duration;
 
-use crate::game::space::{Role, Square};
-use crate::game::{EngineRole, LiveGame, Play, Status};
+use crate::game::board::Board;
+use crate::game::clocks::Clocks;
+use crate::game::opening_book::OpeningBook;
+use crate::game::space::{Role, Space, Square};
+use crate::game::tablebase::Tablebase;
+use crate::game::{EngineRole, LiveGame, Play, PositionsTracker, RECENT_MOVES, Status};
+use crate::mcts::RolloutPolicy;
+use crate::nn::{LrSchedule, TrainConfig};
 use clap::{Parser, Subcommand};
 use tracing_subscriber::fmt::SubscriberBuilder;
 
@@ -14,30 +22,193 @@ mod game_tree;
 mod mcts;
 mod nn;
 
+const POSITION_HELP: &str =
+    "Start from the position in this file, given as 11 rows of the board like \"...OOOOO...\".";
+
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
 struct Args {
     #[command(subcommand)]
     command: Commands,
+    #[arg(long, global = true, help = "Log details of the engine's search.")]
+    verbose: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     #[command(about = "Make moves on a board in a non-game setting.")]
-    Explore,
+    Explore {
+        #[arg(long, help = POSITION_HELP)]
+        position: Option<PathBuf>,
+        #[arg(long, default_value = "attacker", help = "The side to move first.")]
+        turn: Role,
+    },
     #[command(about = "Play against a rudimentary AI")]
-    Play { role: Role },
+    Play {
+        role: Role,
+        #[arg(long, help = POSITION_HELP)]
+        position: Option<PathBuf>,
+        #[arg(long, default_value = "attacker", help = "The side to move first.")]
+        turn: Role,
+        #[arg(long, help = "A JSON opening book for the AI to play from.")]
+        book: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "A JSON endgame tablebase for the AI to play from, see the tablebase command."
+        )]
+        tablebase: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Have the AI consider every move instead of merging symmetric ones."
+        )]
+        no_symmetry: bool,
+        #[arg(long, help = "Give each side this many seconds for the whole game.")]
+        time: Option<u64>,
+        #[arg(
+            long,
+            requires = "time",
+            default_value_t = 0,
+            help = "Add this many seconds to a side's clock after each of their moves."
+        )]
+        increment: u64,
+    },
+    #[command(about = "Continue a game written with the save command.")]
+    Resume {
+        #[arg(help = "The JSON file the game was saved to.")]
+        file: PathBuf,
+        #[arg(long, help = "A JSON opening book for the AI to play from.")]
+        book: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "A JSON endgame tablebase for the AI to play from, see the tablebase command."
+        )]
+        tablebase: Option<PathBuf>,
+    },
     #[command(about = "Train an AI via self play.")]
     Train {
         #[arg(help = "The number of improved versions to create.")]
         iterations: u64,
+        #[arg(long, default_value_t = 1e-2, help = "The initial learning rate.")]
+        lr: f64,
+        #[arg(
+            long,
+            help = "Multiply the learning rate by this factor as training progresses."
+        )]
+        lr_decay: Option<f64>,
+        #[arg(
+            long,
+            requires = "lr_decay",
+            help = "Decay the learning rate by --lr-decay every this many training steps."
+        )]
+        lr_step: Option<usize>,
+        #[arg(
+            long,
+            default_value_t = 1,
+            help = "The number of threads to run playouts on."
+        )]
+        threads: usize,
+        #[arg(
+            long,
+            default_value = "nn",
+            help = "How playouts choose their moves, either heuristic or nn."
+        )]
+        policy: RolloutPolicy,
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "Seed the choice of random start, see --random-starts."
+        )]
+        seed: u64,
+        #[arg(
+            long,
+            default_value_t = 1,
+            help = "The number of positions, the current one and those before it, the networks see."
+        )]
+        history_planes: usize,
+        #[arg(
+            long,
+            help = "Search from a random variation of the standard setup, chosen by --seed."
+        )]
+        random_starts: bool,
+        #[arg(
+            long,
+            help = "Keep the statistics of each search in this directory and add to them on later runs."
+        )]
+        stats: Option<PathBuf>,
+        #[arg(long, help = "Do not report progress while training.")]
+        quiet: bool,
+    },
+    #[command(about = "Play games against itself and record them as training data.")]
+    SelfPlay {
+        #[arg(help = "The number of games to play.")]
+        games: u64,
+        #[arg(help = "The directory to write the games to.")]
+        out_dir: PathBuf,
+        #[arg(
+            long,
+            default_value_t = 100,
+            help = "The number of playouts to search before each move."
+        )]
+        iterations: u64,
+        #[arg(long, default_value_t = 0, help = "Seed the random choice of moves.")]
+        seed: u64,
+        #[arg(
+            long,
+            help = "Start each game from a random variation of the standard setup."
+        )]
+        random_starts: bool,
+        #[arg(
+            long,
+            default_value_t = 400,
+            help = "The number of moves after which a game is stopped and counted as a draw."
+        )]
+        max_plies: u64,
+    },
+    #[command(about = "Measure the speed of the search on a fixed set of positions.")]
+    Bench {
+        #[arg(long, default_value_t = 3, help = "How many plies deep to search.")]
+        depth: usize,
+        #[arg(
+            long,
+            default_value_t = 20,
+            help = "The number of positions to search."
+        )]
+        positions: usize,
+    },
+    #[command(about = "Solve every endgame with a few pieces and write the results to a file.")]
+    Tablebase {
+        #[arg(help = "The most attackers in a position.")]
+        attackers: u8,
+        #[arg(help = "The most defenders in a position, not counting the king.")]
+        defenders: u8,
+        #[arg(help = "The JSON file to write the tablebase to.")]
+        out: PathBuf,
+    },
+    #[command(about = "Step through a game recorded by self-play.")]
+    Replay {
+        #[arg(help = "The JSON file the game was written to.")]
+        file: PathBuf,
     },
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum GameCommand {
     Undo,
     Redo,
+    Resign,
+    /// Leave without finishing the game
+    Quit,
+    OfferDraw,
+    /// Write the game to a file so that it can be resumed later
+    Save(PathBuf),
+    /// Put a piece on a square or clear it, ignoring the rules
+    Set(Square, Space),
+    /// Change whose turn it is, ignoring the rules
+    Side(Role),
+    /// Finish editing the board and resume playing
+    Done,
+    /// Suggest a move for the side to move without playing it
+    Hint,
     Play([Square; 2]),
 }
 
@@ -45,34 +216,183 @@ impl FromStr for GameCommand {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["set", square, space] => {
+                let mut chars = space.chars();
+                let (Some(ch), None) = (chars.next(), chars.next()) else {
+                    return Err(anyhow::Error::msg(format!(
+                        "Could not parse piece '{space}'"
+                    )));
+                };
+                let space = Space::try_from(ch.to_ascii_uppercase())?;
+                return Ok(Self::Set(Square::from_str(square)?, space));
+            }
+            ["side", role] => return Ok(Self::Side(Role::from_str(&role.to_lowercase())?)),
+            ["save", file] => return Ok(Self::Save(PathBuf::from(file))),
+            _ => {}
+        }
         match s {
             "u" | "undo" => Ok(Self::Undo),
             "r" | "redo" => Ok(Self::Redo),
+            "resign" => Ok(Self::Resign),
+            "q" | "quit" => Ok(Self::Quit),
+            "d" | "draw" => Ok(Self::OfferDraw),
+            "done" => Ok(Self::Done),
+            "h" | "hint" => Ok(Self::Hint),
             play => {
-                let mut squares = play.split("->");
-                let from = Square::from_str(squares.next().ok_or_else(|| {
-                    anyhow::Error::msg(format!("Could not parse input '{play}'"))
-                })?)?;
-                let to = Square::from_str(squares.next().ok_or_else(|| {
-                    anyhow::Error::msg(format!("Could not parse input '{play}'"))
-                })?)?;
+                // the side to move is filled in when the play is made
+                let Play { from, to, .. } = Play::from_algebraic(play, Role::Attacker)?;
                 Ok(Self::Play([from, to]))
             }
         }
     }
 }
 
-#[allow(dead_code)]
 fn init_logging() {
-    SubscriberBuilder::default().with_ansi(true).init();
+    SubscriberBuilder::default()
+        .with_ansi(true)
+        .with_max_level(tracing::Level::DEBUG)
+        .init();
 }
 
 fn main() {
     let cli = Args::parse();
+    if cli.verbose {
+        init_logging();
+    }
     match cli.command {
-        Commands::Explore => explore(None),
-        Commands::Train { iterations } => mcts::train(iterations as usize),
-        Commands::Play { role } => explore(Some(role)),
+        Commands::Explore { position, turn } => explore(LiveGame::from_position(
+            load_optional(position, load_position, "position"),
+            turn,
+        )),
+        Commands::Train {
+            iterations,
+            lr,
+            lr_decay,
+            lr_step,
+            threads,
+            policy,
+            seed,
+            history_planes,
+            random_starts,
+            stats,
+            quiet,
+        } => {
+            let schedule = match (lr_decay, lr_step) {
+                (Some(factor), Some(every)) => LrSchedule::Step { every, factor },
+                (Some(factor), None) => LrSchedule::Exponential { factor },
+                (None, _) => LrSchedule::Constant,
+            };
+            // the networks train on all symmetries of each position
+            let config = TrainConfig {
+                lr,
+                schedule,
+                symmetric: true,
+                history_planes,
+                ..Default::default()
+            };
+            if let Err(e) = mcts::train(
+                iterations as usize,
+                config,
+                policy,
+                threads,
+                random_starts.then_some(seed),
+                stats.as_deref(),
+                quiet,
+            ) {
+                println!("Training failed: {e}");
+                exit(1)
+            }
+        }
+        Commands::SelfPlay {
+            games,
+            out_dir,
+            iterations,
+            seed,
+            random_starts,
+            max_plies,
+        } => {
+            if let Err(e) = mcts::self_play(
+                games as usize,
+                out_dir,
+                iterations as usize,
+                seed,
+                random_starts,
+                max_plies as usize,
+            ) {
+                println!("Self-play failed: {e}");
+                exit(1)
+            }
+        }
+        Commands::Play {
+            role,
+            position,
+            turn,
+            book,
+            tablebase,
+            no_symmetry,
+            time,
+            increment,
+        } => {
+            let engine = EngineRole::from(role.opposite());
+            let clocks = time
+                .map(|time| Clocks::new(Duration::from_secs(time), Duration::from_secs(increment)));
+            let game =
+                LiveGame::from_position(load_optional(position, load_position, "position"), turn);
+            explore(LiveGame {
+                engine: Some(if no_symmetry {
+                    engine.without_symmetry()
+                } else {
+                    engine
+                }),
+                opening_book: load_optional(book, OpeningBook::load, "opening book"),
+                tablebase: load_optional(tablebase, Tablebase::load, "tablebase"),
+                clocks,
+                ..game
+            })
+        }
+        Commands::Resume {
+            file,
+            book,
+            tablebase,
+        } => {
+            let game = match LiveGame::load(file) {
+                Ok(game) => game,
+                Err(e) => {
+                    println!("Could not load game: {e}");
+                    exit(1)
+                }
+            };
+            explore(LiveGame {
+                opening_book: load_optional(book, OpeningBook::load, "opening book"),
+                tablebase: load_optional(tablebase, Tablebase::load, "tablebase"),
+                ..game
+            })
+        }
+        Commands::Bench { depth, positions } => {
+            println!("{}", alpha_beta::bench::bench(depth, positions))
+        }
+        Commands::Tablebase {
+            attackers,
+            defenders,
+            out,
+        } => {
+            let tablebase = Tablebase::generate(attackers, defenders, Default::default());
+            if let Err(e) = tablebase.save(out) {
+                println!("Could not write tablebase: {e}");
+                exit(1)
+            }
+        }
+        Commands::Replay { file } => {
+            let plays = match mcts::SelfPlayGame::load(file) {
+                Ok(game) => game.moves.into_iter().map(|m| m.play).collect::<Vec<_>>(),
+                Err(e) => {
+                    println!("Could not load game: {e}");
+                    exit(1)
+                }
+            };
+            replay(&plays)
+        }
     }
     // let mut game = LiveGame::default();
     // game.engine = Some(EngineRole::from(Role::Attacker));
@@ -100,17 +420,96 @@ fn user_input() -> GameCommand {
     }
 }
 
-fn explore(role: Option<Role>) {
-    let mut game = LiveGame {
-        engine: role.map(|r| EngineRole::from(r.opposite())),
-        ..Default::default()
-    };
+/// Load a file if one is given, exiting if it can't be read
+fn load_optional<T: Default>(
+    path: Option<PathBuf>,
+    load: impl Fn(PathBuf) -> anyhow::Result<T>,
+    what: &str,
+) -> T {
+    match path.map(load).transpose() {
+        Ok(loaded) => loaded.unwrap_or_default(),
+        Err(e) => {
+            println!("Could not load {what}: {e}");
+            exit(1)
+        }
+    }
+}
+
+/// Read a board from a file holding its rows as written by
+/// [`Board::to_ascii`], one per line
+fn load_position(path: PathBuf) -> anyhow::Result<Board> {
+    let rows = std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let rows: [String; 11] = rows
+        .try_into()
+        .map_err(|_| anyhow::Error::msg("A position must have 11 rows"))?;
+    Board::from_ascii(&rows)
+}
+
+fn explore(mut game: LiveGame) {
+    game.start_clocks();
+    // the engine waits while the board is being edited
+    let mut editing = false;
     loop {
-        game.engine_play();
+        if !editing {
+            game.engine_play();
+        }
         println!("{}", game);
-        match user_input() {
+        let command = user_input();
+        if editing && matches!(command, GameCommand::Done | GameCommand::Play(_)) {
+            if let Err(e) = game.current_board.validate_playable() {
+                println!("The position can't be played: {e}");
+                continue;
+            }
+            editing = false;
+        }
+        match command {
             GameCommand::Undo => game.undo(),
             GameCommand::Redo => game.redo(),
+            GameCommand::Resign => game.resign(),
+            GameCommand::Quit => exit(0),
+            GameCommand::OfferDraw => {
+                let offering = game.turn;
+                game.offer_draw();
+                match (game.status, game.draw_offer) {
+                    (Status::Draw, _) => {}
+                    (_, None) => println!("The draw offer was declined"),
+                    _ => println!("{offering:?}s offer a draw"),
+                }
+            }
+            GameCommand::Set(square, space) => {
+                edit(&mut game);
+                // there can only be one king
+                if space == Space::King
+                    && let Some(king) = game.current_board.king_square()
+                {
+                    game.current_board.set(&king, Space::Empty);
+                }
+                game.current_board.set(&square, space);
+                editing = true;
+            }
+            GameCommand::Side(role) => {
+                edit(&mut game);
+                game.turn = role;
+                editing = true;
+            }
+            GameCommand::Save(file) => {
+                if let Err(e) = game.save(&file) {
+                    println!("Could not save the game: {e}");
+                }
+            }
+            GameCommand::Done => {}
+            GameCommand::Hint => {
+                if let Err(e) = game.current_board.validate_playable() {
+                    println!("The position can't be played: {e}");
+                } else {
+                    hint(&mut game)
+                }
+            }
             GameCommand::Play([from, to]) => {
                 if let Err(e) = game.play(&Play {
                     role: game.turn,
@@ -121,16 +520,110 @@ fn explore(role: Option<Role>) {
                 }
             }
         }
-        match game.status {
-            Status::AttackersWin => {
-                println!("Attackers win!");
-                exit(0)
-            }
-            Status::DefendersWin => {
-                println!("Defenders win!");
-                exit(0)
-            }
-            _ => {}
+        if let Some(result) = game.result() {
+            println!("{}! {result}.", game.status);
+            exit(0)
+        }
+    }
+}
+
+/// Step backwards and forwards through the moves of a game with undo
+/// and redo. No new moves can be made.
+fn replay(plays: &[Play]) {
+    let mut game = match LiveGame::replay(plays) {
+        Ok(game) => game,
+        Err(e) => {
+            println!("Could not replay game: {e}");
+            exit(1)
         }
+    };
+    loop {
+        let ply = game.history.len();
+        match ply.checked_sub(1).map(|ix| &plays[ix]) {
+            Some(play) => println!("Move {ply}/{}: {} -> {}", plays.len(), play.from, play.to),
+            None => println!("Move 0/{}", plays.len()),
+        }
+        println!("{}", game.render_with_moves(RECENT_MOVES).trim_end());
+        match user_input() {
+            GameCommand::Undo => game.undo(),
+            GameCommand::Redo => game.redo(),
+            GameCommand::Quit => exit(0),
+            GameCommand::Hint => hint(&mut game),
+            _ => println!("The replay is read-only, use undo and redo or q to quit"),
+        }
+    }
+}
+
+/// Print the engine's suggestion for the side to move
+fn hint(game: &mut LiveGame) {
+    match game.hint() {
+        Some((play, score)) => println!("Hint: {} (score {})", play.to_algebraic(), score.0),
+        None => println!("There is no move to suggest"),
+    }
+}
+
+/// Editing the board starts a new game from the edited position
+fn edit(game: &mut LiveGame) {
+    game.status = Status::Ongoing;
+    game.previous_boards = PositionsTracker::Previous(Default::default());
+    game.history.clear();
+    game.ahead.clear();
+    game.draw_offer = None;
+}
+
+#[cfg(test)]
+mod test_commands {
+    use super::*;
+
+    /// Test parsing the commands for editing the board
+    #[test]
+    fn test_parse_editor_commands() {
+        let f6 = Square::from_str("f6").unwrap();
+        assert_eq!(
+            GameCommand::from_str("set F6 K").unwrap(),
+            GameCommand::Set(f6, Space::King)
+        );
+        assert_eq!(
+            GameCommand::from_str("set a1 .").unwrap(),
+            GameCommand::Set(Square { x: 0, y: 10 }, Space::Empty)
+        );
+        assert_eq!(
+            GameCommand::from_str(" set  f6 o ").unwrap(),
+            GameCommand::Set(f6, Space::Occupied(Role::Attacker))
+        );
+        assert_eq!(
+            GameCommand::from_str("set f6 X").unwrap(),
+            GameCommand::Set(f6, Space::Occupied(Role::Defender))
+        );
+        assert_eq!(
+            GameCommand::from_str("side attacker").unwrap(),
+            GameCommand::Side(Role::Attacker)
+        );
+        assert_eq!(
+            GameCommand::from_str("side Defender").unwrap(),
+            GameCommand::Side(Role::Defender)
+        );
+        assert_eq!(GameCommand::from_str("done").unwrap(), GameCommand::Done);
+        assert_eq!(GameCommand::from_str("hint").unwrap(), GameCommand::Hint);
+        assert_eq!(
+            GameCommand::from_str("resign").unwrap(),
+            GameCommand::Resign
+        );
+        assert_eq!(GameCommand::from_str("q").unwrap(), GameCommand::Quit);
+        assert_eq!(GameCommand::from_str("quit").unwrap(), GameCommand::Quit);
+        assert_eq!(
+            GameCommand::from_str("save game.json").unwrap(),
+            GameCommand::Save(PathBuf::from("game.json"))
+        );
+        assert!(GameCommand::from_str("set f6").is_err());
+        assert!(GameCommand::from_str("set f6 KK").is_err());
+        assert!(GameCommand::from_str("set f6 Q").is_err());
+        assert!(GameCommand::from_str("set l6 K").is_err());
+        assert!(GameCommand::from_str("side king").is_err());
+        // moves are still understood
+        assert_eq!(
+            GameCommand::from_str("a8->b8").unwrap(),
+            GameCommand::Play([Square { x: 0, y: 3 }, Square { x: 1, y: 3 }])
+        );
     }
 }
@@ -1,18 +1,23 @@
+mod progress;
 mod selection;
+mod self_play;
 mod train;
 
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-use candle_core::{Module, Tensor};
-//use rayon::prelude::*;
-pub use train::train;
+use candle_core::Module;
+pub use progress::ProgressReport;
+use rayon::prelude::*;
+pub use self_play::{SelfPlayGame, self_play};
+pub use train::{RolloutPolicy, train};
 
 use crate::game::Status;
 use crate::game::space::Role;
-use crate::game_tree::GameTreeNode;
-use crate::mcts::selection::NNSelectionPolicy;
-use crate::nn::TaflNNet;
+use crate::game_tree::{GameSummary, GameTreeNode, SelectionPolicy};
+use crate::mcts::progress::{Progress, REPORT_INTERVAL};
+use crate::mcts::selection::{ExplorationSchedule, HeuristicRollout, NNSelectionPolicy};
+use crate::nn::{TaflNNet, TrainConfig, to_value};
 
 /// Internal representation of a fixed-point value for rewards
 /// This allows atomic operations on floating point rewards
@@ -27,20 +32,103 @@ pub fn float_to_scaled_i64(value: f64) -> i64 {
 pub fn scaled_i64_to_float(value: i64) -> f64 {
     (value as f64) / REWARD_SCALE
 }
+/// A policy which chooses the moves made in MCTS playouts
+pub trait PlayoutPolicy: SelectionPolicy<TreeNode = GameTreeNode> + Sync + Sized {
+    /// This policy with the given exploration constant. Policies which
+    /// don't explore are returned unchanged.
+    fn with_exploration(&self, exploration_constant: f64) -> Self;
+}
+
+impl PlayoutPolicy for NNSelectionPolicy {
+    fn with_exploration(&self, exploration_constant: f64) -> Self {
+        Self {
+            exploration_constant,
+            ..self.clone()
+        }
+    }
+}
+
+impl PlayoutPolicy for HeuristicRollout {
+    fn with_exploration(&self, exploration_constant: f64) -> Self {
+        Self {
+            stats: self.stats.with_exploration(exploration_constant),
+        }
+    }
+}
+
 /// Run Monte Carlo tree search on the given starting position for the given
-/// number of iterations. Return the selection policy afterwards.
-pub fn mcts(root: &GameTreeNode, policy: &NNSelectionPolicy, iterations: usize) {
+/// number of iterations. Moves are chosen by `policy` and the results are
+/// recorded in the statistics of `stats`, which may be the same policy.
+/// Before each playout, the exploration constant of the policy is set by
+/// the schedule. Every few playouts, `on_progress` is told how far the
+/// search has got.
+///
+/// With more than one thread, the playouts are run in parallel. Nodes on
+/// the path of an unfinished playout are given a virtual loss so that the
+/// threads spread out over the tree.
+pub fn mcts(
+    root: &GameTreeNode,
+    policy: &impl PlayoutPolicy,
+    stats: &NNSelectionPolicy,
+    iterations: usize,
+    schedule: &ExplorationSchedule,
+    threads: usize,
+    on_progress: impl FnMut(&ProgressReport) + Send,
+) {
     println!("Playing {iterations} games");
-    for _ in 0..iterations {
-        simulate_random_playout(root, policy);
+    let mut progress = Progress::new(iterations, REPORT_INTERVAL, on_progress);
+    if threads <= 1 {
+        for iteration in 0..iterations {
+            let policy = policy.with_exploration(schedule.at(iteration));
+            simulate_random_playout(root, &policy, stats);
+            progress.tick();
+        }
+        return;
     }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    let progress = Mutex::new(progress);
+    pool.install(|| {
+        (0..iterations).into_par_iter().for_each(|iteration| {
+            let policy = policy.with_exploration(schedule.at(iteration));
+            playout(root, &policy, stats, true);
+            progress.lock().unwrap().tick();
+        })
+    });
 }
-pub fn simulate_random_playout(node: &GameTreeNode, policy: &NNSelectionPolicy) -> f64 {
+
+/// Play a game out from `node` with moves chosen by `policy`, recording
+/// the result in the statistics of `stats`. Returns the result for the
+/// player to move.
+pub fn simulate_random_playout(
+    node: &GameTreeNode,
+    policy: &impl SelectionPolicy<TreeNode = GameTreeNode>,
+    stats: &NNSelectionPolicy,
+) -> f64 {
+    playout(node, policy, stats, false)
+}
+
+/// See [`simulate_random_playout`]. If `virtual_loss` is set, the positions
+/// along the way are given a virtual loss until the game is over.
+fn playout(
+    node: &GameTreeNode,
+    policy: &impl SelectionPolicy<TreeNode = GameTreeNode>,
+    stats: &NNSelectionPolicy,
+    virtual_loss: bool,
+) -> f64 {
     let mut current_state = node.clone();
     let for_player = node.turn;
     let mut path = Vec::from([current_state.clone()]);
+    if virtual_loss {
+        stats.add_virtual_loss(&current_state);
+    }
     while !current_state.is_terminal() {
         current_state = current_state.select_child(policy);
+        if virtual_loss {
+            stats.add_virtual_loss(&current_state);
+        }
         path.push(current_state.clone());
     }
     if current_state.status == Status::AttackersWin {
@@ -49,7 +137,10 @@ pub fn simulate_random_playout(node: &GameTreeNode, policy: &NNSelectionPolicy)
     let attacker_rewards = current_state.get_result(&Role::Attacker);
     let defender_rewards = current_state.get_result(&Role::Defender);
     for game in path {
-        policy.update_stats(&game, attacker_rewards, defender_rewards);
+        if virtual_loss {
+            stats.remove_virtual_loss(&game);
+        }
+        stats.update_stats(&game, attacker_rewards, defender_rewards);
     }
     match for_player {
         Role::Attacker => attacker_rewards,
@@ -67,13 +158,13 @@ pub enum NNetRole {
 
 impl NNetRole {
     /// Open training neural network
-    pub fn training(p: impl AsRef<Path>) -> Self {
-        NNetRole::Training(Arc::new(Mutex::new(TaflNNet::new(p))))
+    pub fn training(p: impl AsRef<Path>, config: TrainConfig) -> Self {
+        NNetRole::Training(Arc::new(Mutex::new(TaflNNet::new(p, config))))
     }
 
     /// Open playing neural network
     pub fn playing(p: impl AsRef<Path>) -> Self {
-        NNetRole::Playing(Arc::new(Mutex::new(TaflNNet::new(p))))
+        NNetRole::Playing(Arc::new(Mutex::new(TaflNNet::new(p, Default::default()))))
     }
 
     /// Get the inner pointer
@@ -84,18 +175,13 @@ impl NNetRole {
         }
     }
 
-    /// Evaluate the inner [`TaflNNet`] on the given tensor and
+    /// Evaluate the inner [`TaflNNet`] on the given game and
     /// cast it to a float
-    fn eval(&self, tensor: &Tensor) -> f64 {
-        self.inner()
-            .lock()
-            .unwrap()
-            .forward(tensor)
-            .unwrap()
-            .max(0)
-            .unwrap()
-            .to_scalar::<f64>()
-            .unwrap()
+    fn eval(&self, game: &GameSummary) -> f64 {
+        let nn = self.inner().lock().unwrap();
+        let tensor = selection::input_tensor(game, nn.history_planes()).unwrap();
+        let output = nn.forward(&tensor).unwrap();
+        to_value(&output).unwrap()
     }
 
     /// A helper function to help policies determine if a
@@ -107,10 +193,12 @@ impl NNetRole {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::Ordering;
 
+    use super::*;
     use crate::game::board::Board;
-    use crate::game::space::{Role, Square};
-    use crate::game::{Play, PositionsTracker};
+    use crate::game::space::{Role, Space, Square};
+    use crate::game::{Play, PositionsTracker, Status};
     use crate::game_tree::{GameTreeNode, Threats};
 
     #[test]
@@ -133,11 +221,13 @@ mod tests {
             previous_boards: PositionsTracker::Counter(0),
             turn: Role::Attacker,
             current_board: Board::try_from(board).expect("Test failed"),
+            rules: Default::default(),
+            recent_boards: Default::default(),
         };
 
         assert_eq!(Threats::Quiet, game.threats());
         game.turn = Role::Defender;
-        let expected_plays = vec![
+        let expected_plays = [
             Play {
                 role: Role::Defender,
                 from: Square { x: 0, y: 8 },
@@ -187,8 +277,10 @@ mod tests {
             previous_boards: PositionsTracker::Counter(0),
             turn: Role::Defender,
             current_board: Board::try_from(board).expect("Test failed"),
+            rules: Default::default(),
+            recent_boards: Default::default(),
         };
-        let expected_plays = vec![Play {
+        let expected_plays = [Play {
             role: Role::Defender,
             from: Square { x: 0, y: 8 },
             to: Square { x: 0, y: 10 },
@@ -230,6 +322,8 @@ mod tests {
             previous_boards: PositionsTracker::Counter(0),
             turn: Role::Defender,
             current_board: Board::try_from(board).expect("Test failed"),
+            rules: Default::default(),
+            recent_boards: Default::default(),
         };
         assert_eq!(Threats::Quiet, game.threats());
         let board = [
@@ -250,7 +344,143 @@ mod tests {
             previous_boards: PositionsTracker::Counter(0),
             turn: Role::Defender,
             current_board: Board::try_from(board).expect("Test failed"),
+            rules: Default::default(),
+            recent_boards: Default::default(),
+        };
+        assert_eq!(Threats::Quiet, game.threats());
+    }
+
+    /// Test that attackers able to capture the king have threats
+    #[test]
+    fn test_attacker_threats() {
+        let board = [
+            "...........",
+            "...........",
+            "...O.......",
+            "..OKO......",
+            "...........",
+            "...........",
+            "...O.......",
+            "...........",
+            "...........",
+            ".........X.",
+            "...........",
+        ];
+        let mut game = GameTreeNode {
+            status: Default::default(),
+            previous_boards: PositionsTracker::Counter(0),
+            turn: Role::Attacker,
+            current_board: Board::try_from(board).expect("Test failed"),
+            rules: Default::default(),
+            recent_boards: Default::default(),
         };
+        let play = Play {
+            role: Role::Attacker,
+            from: Square { x: 3, y: 6 },
+            to: Square { x: 3, y: 4 },
+        };
+        let mut expected = game.apply_play(&play).expect("Test failed");
+        expected.current_board.normalize();
+        match game.threats() {
+            Threats::Quiet => panic!("Test failed"),
+            Threats::Plays(games) => {
+                assert_eq!(games.len(), 1);
+                assert_eq!(games[0].current_board, expected.current_board);
+                assert_eq!(games[0].status, Status::AttackersWin);
+            }
+        }
+
+        // without the capture, the position is quiet
+        game.current_board.set(&Square { x: 3, y: 6 }, Space::Empty);
+        game.current_board
+            .set(&Square { x: 6, y: 6 }, Space::Occupied(Role::Attacker));
         assert_eq!(Threats::Quiet, game.threats());
     }
+
+    /// Test that running playouts in parallel visits the tree as often as
+    /// running them one after another and leaves no virtual losses behind
+    #[test]
+    fn test_parallel_mcts() {
+        let board = [
+            "...........",
+            "...........",
+            ".X.........",
+            ".X.........",
+            ".X.........",
+            ".X.........",
+            "...........",
+            ".X.........",
+            "KX.........",
+            ".X.........",
+            "...........",
+        ];
+        let root = GameTreeNode {
+            status: Default::default(),
+            previous_boards: PositionsTracker::Counter(0),
+            turn: Role::Defender,
+            current_board: Board::try_from(board).expect("Test failed"),
+            rules: Default::default(),
+            recent_boards: Default::default(),
+        };
+        let schedule = ExplorationSchedule {
+            start: 0.2,
+            end: 0.2,
+            decay: 1.0,
+        };
+        // the totals over every visited position
+        let totals = |threads: usize| {
+            let policy = NNSelectionPolicy::default();
+            mcts(&root, &policy, &policy, 40, &schedule, threads, |_| {});
+            assert_eq!(policy.get_visits(&root), 40);
+            let stats = policy.stats_map.lock().unwrap();
+            stats
+                .values()
+                .fold((0, 0, 0), |(visits, attacker, defender), s| {
+                    (
+                        visits + s.visits.load(Ordering::Relaxed),
+                        attacker + s.attacker_rewards.load(Ordering::Relaxed),
+                        defender + s.defender_rewards.load(Ordering::Relaxed),
+                    )
+                })
+        };
+        let sequential = totals(1);
+        assert_eq!(sequential.0, 80);
+        assert_eq!(totals(4), sequential);
+    }
+
+    /// Test that a playout with moves chosen by the heuristic is played
+    /// to the end and recorded
+    #[test]
+    fn test_heuristic_playout() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let stats = NNSelectionPolicy::default();
+        let rollout = HeuristicRollout {
+            stats: stats.clone(),
+        };
+        let result = simulate_random_playout(&root, &rollout, &stats);
+        assert!([-1.0, 0.0, 1.0].contains(&result));
+        assert_eq!(stats.get_visits(&root), 1);
+        let recorded = stats.stats_map.lock().unwrap();
+        assert!(recorded.len() > 1);
+        assert!(recorded.keys().any(|game| game.status != Status::Ongoing));
+    }
+
+    /// Test that playouts guided by the heuristic explore different
+    /// lines rather than replaying the same game
+    #[test]
+    fn test_heuristic_playouts_differ() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let stats = NNSelectionPolicy::default();
+        let rollout = HeuristicRollout {
+            stats: stats.clone(),
+        };
+        simulate_random_playout(&root, &rollout, &stats);
+        simulate_random_playout(&root, &rollout, &stats);
+        let first_moves = root
+            .get_children()
+            .iter()
+            .filter(|child| stats.get_visits(child) > 0)
+            .count();
+        assert_eq!(first_moves, 2);
+    }
 }
@@ -0,0 +1,113 @@
+//! Report how far a long run of playouts has got, so that training
+//! gives some sense of how long it has left.
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How many playouts to complete between reports
+pub const REPORT_INTERVAL: usize = 10;
+
+/// A snapshot of the progress of a run of playouts
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProgressReport {
+    pub completed: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+}
+
+impl ProgressReport {
+    /// The number of playouts completed per second
+    pub fn rate(&self) -> f64 {
+        self.completed as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The estimated time until the remaining playouts are completed
+    pub fn eta(&self) -> Duration {
+        if self.completed == 0 {
+            return Duration::ZERO;
+        }
+        let remaining = self.total.saturating_sub(self.completed) as f64;
+        self.elapsed.mul_f64(remaining / self.completed as f64)
+    }
+}
+
+impl fmt::Display for ProgressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} games, {:.2} games/s, elapsed {}s, eta {}s",
+            self.completed,
+            self.total,
+            self.rate(),
+            self.elapsed.as_secs(),
+            self.eta().as_secs(),
+        )
+    }
+}
+
+/// Counts completed playouts and calls back with a report every
+/// `interval` playouts and once they are all done
+pub struct Progress<F: FnMut(&ProgressReport)> {
+    total: usize,
+    interval: usize,
+    completed: usize,
+    start: Instant,
+    callback: F,
+}
+
+impl<F: FnMut(&ProgressReport)> Progress<F> {
+    pub fn new(total: usize, interval: usize, callback: F) -> Self {
+        Self {
+            total,
+            interval: interval.max(1),
+            completed: 0,
+            start: Instant::now(),
+            callback,
+        }
+    }
+
+    /// Record that a playout has been completed
+    pub fn tick(&mut self) {
+        self.completed += 1;
+        if self.completed.is_multiple_of(self.interval) || self.completed == self.total {
+            (self.callback)(&ProgressReport {
+                completed: self.completed,
+                total: self.total,
+                elapsed: self.start.elapsed(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_progress {
+    use super::*;
+
+    /// Test that a report is made every interval and at the end
+    #[test]
+    fn test_report_count() {
+        let mut reports = vec![];
+        let mut progress = Progress::new(10, 3, |report: &ProgressReport| {
+            reports.push(report.completed)
+        });
+        for _ in 0..10 {
+            progress.tick();
+        }
+        assert_eq!(reports, vec![3, 6, 9, 10]);
+    }
+
+    /// Test the rate and time remaining are worked out from the elapsed time
+    #[test]
+    fn test_eta() {
+        let report = ProgressReport {
+            completed: 5,
+            total: 20,
+            elapsed: Duration::from_secs(10),
+        };
+        assert_eq!(report.rate(), 0.5);
+        assert_eq!(report.eta(), Duration::from_secs(30));
+        assert_eq!(
+            report.to_string(),
+            "5/20 games, 0.50 games/s, elapsed 10s, eta 30s"
+        );
+    }
+}
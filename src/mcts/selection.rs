@@ -1,14 +1,33 @@
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
 use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
 use candle_core::{Device, Tensor};
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::game::Status;
+use crate::alpha_beta::heuristic::heuristic;
+use crate::game::Play;
+use crate::game::board::Board;
 use crate::game::space::{Role, Space, Square};
 use crate::game_tree::{GameSummary, GameTreeNode, SelectionPolicy};
 use crate::mcts::{NNetRole, float_to_scaled_i64, scaled_i64_to_float};
+use crate::nn::input_channels;
+
+/// The reward counted against a node for each parallel playout
+/// currently passing through it
+const VIRTUAL_LOSS: f64 = 1.0;
+
+/// The heuristic's evaluation is unbounded: its corner penalties alone
+/// reach several units and finished games score thousands. Before being
+/// blended with a network's evaluation in [-1, 1], it is squashed with
+/// `tanh(h / HEURISTIC_SQUASH)`, so this is roughly the size of a clear
+/// advantage.
+const HEURISTIC_SQUASH: f64 = 4.0;
 
 #[derive(Default, Debug)]
 pub struct Stats {
@@ -17,6 +36,42 @@ pub struct Stats {
     pub defender_rewards: AtomicI64,
 }
 
+/// The values currently held by [`Stats`], used to serialize it
+#[derive(Deserialize, Serialize)]
+struct StatsValues {
+    visits: u64,
+    attacker_rewards: i64,
+    defender_rewards: i64,
+}
+
+impl Serialize for Stats {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        StatsValues {
+            visits: self.visits.load(Ordering::Relaxed),
+            attacker_rewards: self.attacker_rewards.load(Ordering::Relaxed),
+            defender_rewards: self.defender_rewards.load(Ordering::Relaxed),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Stats {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let values = StatsValues::deserialize(deserializer)?;
+        Ok(Self {
+            visits: AtomicU64::new(values.visits),
+            attacker_rewards: AtomicI64::new(values.attacker_rewards),
+            defender_rewards: AtomicI64::new(values.defender_rewards),
+        })
+    }
+}
+
 impl Stats {
     pub fn increment_visits(&self) {
         self.visits.fetch_add(1, Ordering::Relaxed);
@@ -39,31 +94,68 @@ impl TryFrom<&GameSummary> for Tensor {
     type Error = candle_core::Error;
 
     fn try_from(game: &GameSummary) -> Result<Self, Self::Error> {
-        let (attackers, defenders) = Square::iter()
-            .map(|sq| match game.current_board.get(&sq) {
-                Space::Occupied(Role::Attacker) => (1f64, 0f64),
-                Space::Occupied(Role::Defender) => (0.0, 1.0),
-                Space::King => (0.0, 2.0),
-                Space::Empty => (0.0, 0.0),
+        input_tensor(game, 1)
+    }
+}
+
+/// Encode a game as the input of a network looking at `history_planes`
+/// positions, the current one followed by those before it. The game is
+/// seen from the side to move, see [`GameSummary::canonical_perspective`].
+pub fn input_tensor(game: &GameSummary, history_planes: usize) -> candle_core::Result<Tensor> {
+    let game = game.canonical_perspective();
+    // a binary mask of the squares holding the given piece,
+    // empty if there is no board
+    fn plane(board: Option<&Board>, piece: Space) -> impl Iterator<Item = f64> + '_ {
+        Square::iter().map(move |sq| {
+            if board.is_some_and(|board| board.get(&sq) == piece) {
+                1f64
+            } else {
+                0f64
+            }
+        })
+    }
+    let boards = std::iter::once(&game.current_board)
+        .chain(game.history.iter())
+        .map(Some)
+        .chain(std::iter::repeat(None))
+        .take(history_planes);
+
+    Tensor::from_vec(
+        boards
+            .flat_map(|board| {
+                plane(board, Space::Occupied(Role::Attacker))
+                    .chain(plane(board, Space::Occupied(Role::Defender)))
+                    .chain(plane(board, Space::King))
             })
-            .unzip::<_, _, Vec<_>, Vec<_>>();
-
-        Tensor::from_vec(
-            attackers
-                .into_iter()
-                .chain(defenders)
-                .chain(
-                    [if game.turn == Role::Attacker {
-                        1f64
-                    } else {
-                        0f64
-                    }; 11 * 11],
-                )
-                .chain([game.moves as f64; 11 * 11])
-                .collect(),
-            (4, 11, 11),
-            &Device::Cpu,
-        )
+            .chain(
+                [if game.turn == Role::Attacker {
+                    1f64
+                } else {
+                    0f64
+                }; 11 * 11],
+            )
+            .chain([game.moves as f64; 11 * 11])
+            .chain([game.repetitions as f64; 11 * 11])
+            .collect(),
+        (input_channels(history_planes), 11, 11),
+        &Device::Cpu,
+    )
+}
+
+/// How the exploration constant of a search changes over its playouts. It
+/// starts at `start` and approaches `end`, closing the gap by a factor
+/// of `decay` each playout.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExplorationSchedule {
+    pub start: f64,
+    pub end: f64,
+    pub decay: f64,
+}
+
+impl ExplorationSchedule {
+    /// The exploration constant to use for the given playout
+    pub fn at(&self, iteration: usize) -> f64 {
+        self.end + (self.start - self.end) * self.decay.powi(iteration as i32)
     }
 }
 
@@ -77,6 +169,16 @@ pub struct NNSelectionPolicy {
     pub defender_nn: Option<NNetRole>,
     pub exploration_constant: f64,
     pub stats_map: Arc<Mutex<HashMap<GameSummary, Stats>>>,
+    /// How much randomness to use when sampling moves by their visit
+    /// counts. At zero, the most visited move is always chosen.
+    pub temperature: f64,
+    /// The seed for the random choices made when sampling moves, so
+    /// that self-play can be reproduced
+    pub seed: u64,
+    /// The weight given to the networks' evaluations, with the rest given
+    /// to the alpha-beta heuristic. Lower values help while the networks
+    /// are still weak.
+    pub heuristic_blend: f64,
 }
 
 impl Default for NNSelectionPolicy {
@@ -86,6 +188,9 @@ impl Default for NNSelectionPolicy {
             defender_nn: None,
             exploration_constant: 0.2,
             stats_map: Arc::new(Mutex::new(Default::default())),
+            temperature: 0.0,
+            seed: 0,
+            heuristic_blend: 1.0,
         }
     }
 }
@@ -95,25 +200,30 @@ impl NNSelectionPolicy {
     /// is not present
     pub fn fallback_eval(&self, child: &GameTreeNode) -> f64 {
         let child_summary = child.into();
-        let base = if let Some(stats) = self.stats_map.lock().unwrap().get(&child_summary) {
+        if let Some(stats) = self.stats_map.lock().unwrap().get(&child_summary) {
             scaled_i64_to_float(match child.turn {
                 Role::Attacker => stats.attacker_rewards.load(Ordering::Relaxed),
                 Role::Defender => stats.defender_rewards.load(Ordering::Relaxed),
             }) / std::cmp::max(stats.visits.load(Ordering::Relaxed), 1) as f64
         } else {
-            match child_summary.status {
-                Status::AttackersWin => match child.turn {
-                    Role::Attacker => 1.0,
-                    Role::Defender => -1.0,
-                },
-                Status::DefendersWin => match child.turn {
-                    Role::Attacker => -1.0,
-                    Role::Defender => 1.0,
-                },
-                Status::Draw | Status::Ongoing => 0.0,
-            }
-        };
-        base
+            child_summary.status.result_for(&child.turn).unwrap_or(0.0)
+        }
+    }
+
+    /// Mix the evaluation of the network (or the fallback) with the heuristic
+    /// according to [`NNSelectionPolicy::heuristic_blend`]. The heuristic is
+    /// first squashed into [-1, 1], the range of the network's evaluations,
+    /// so that the blend is too.
+    fn blend(&self, child: &GameTreeNode, nn_eval: f64) -> i64 {
+        let lambda = self.heuristic_blend;
+        if lambda >= 1.0 {
+            return float_to_scaled_i64(nn_eval);
+        }
+        let squashed = (scaled_i64_to_float(heuristic(child).0) / HEURISTIC_SQUASH).tanh();
+        if lambda <= 0.0 {
+            return float_to_scaled_i64(squashed);
+        }
+        float_to_scaled_i64(lambda * nn_eval + (1.0 - lambda) * squashed)
     }
 
     /// Get the number of times this game has been visited
@@ -127,6 +237,83 @@ impl NNSelectionPolicy {
             .unwrap_or_default()
     }
 
+    /// Set the temperature used when sampling moves
+    pub fn with_temperature(self, temperature: f64) -> Self {
+        Self {
+            temperature,
+            ..self
+        }
+    }
+
+    /// Set the seed used for random choices
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// The number of times a child has been visited. Threats are searched
+    /// with normalized boards, so both orientations are looked up.
+    pub fn get_child_visits(&self, child: &GameTreeNode) -> u64 {
+        let mut normalized = child.clone();
+        normalized.current_board.normalize();
+        if normalized.current_board == child.current_board {
+            self.get_visits(child)
+        } else {
+            self.get_visits(child) + self.get_visits(&normalized)
+        }
+    }
+
+    /// Pick a child of `parent` with probability proportional to
+    /// `visits^(1/T)`, where `T` is the temperature
+    pub fn select_child_sampled(
+        &self,
+        parent: &GameTreeNode,
+        rng: &mut impl Rng,
+    ) -> Option<(Play, GameTreeNode)> {
+        let mut plays = parent.get_plays();
+        let visits = plays
+            .iter()
+            .map(|(_, child)| self.get_child_visits(child))
+            .collect::<Vec<_>>();
+        let ix = sample_by_visits(&visits, self.temperature, rng)?;
+        Some(plays.swap_remove(ix))
+    }
+
+    /// Write the statistics gathered so far to a JSON file
+    pub fn export_stats(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let stats = self.stats_map.lock().unwrap();
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), &stats.iter().collect::<Vec<_>>())?;
+        Ok(())
+    }
+
+    /// Read statistics written by [`NNSelectionPolicy::export_stats`] and
+    /// add them to those gathered so far
+    pub fn import_stats(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::open(path)?;
+        let imported: Vec<(GameSummary, Stats)> = serde_json::from_reader(BufReader::new(file))?;
+        let mut stats = self.stats_map.lock().unwrap();
+        for (summary, new) in imported {
+            match stats.entry(summary) {
+                Entry::Occupied(entry) => {
+                    let current = entry.get();
+                    current
+                        .visits
+                        .fetch_add(new.visits.into_inner(), Ordering::Relaxed);
+                    current
+                        .attacker_rewards
+                        .fetch_add(new.attacker_rewards.into_inner(), Ordering::Relaxed);
+                    current
+                        .defender_rewards
+                        .fetch_add(new.defender_rewards.into_inner(), Ordering::Relaxed);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(new);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Update the statistics for a visited node in the tree
     pub fn update_stats(&self, game: &GameTreeNode, attacker_rewards: f64, defender_rewards: f64) {
         let mut stats = self.stats_map.lock().unwrap();
@@ -147,34 +334,119 @@ impl NNSelectionPolicy {
         }
     }
 
+    /// Count a visit to a node on the path of an unfinished playout as a
+    /// loss for both sides, so that other threads are steered away from it.
+    /// Undone by [`NNSelectionPolicy::remove_virtual_loss`].
+    pub fn add_virtual_loss(&self, game: &GameTreeNode) {
+        let mut stats = self.stats_map.lock().unwrap();
+        let stats = stats.entry(game.into()).or_default();
+        stats.increment_visits();
+        stats.add_rewards(Role::Attacker, -VIRTUAL_LOSS);
+        stats.add_rewards(Role::Defender, -VIRTUAL_LOSS);
+    }
+
+    /// Undo [`NNSelectionPolicy::add_virtual_loss`] once the playout is over
+    pub fn remove_virtual_loss(&self, game: &GameTreeNode) {
+        let stats = self.stats_map.lock().unwrap();
+        if let Some(stats) = stats.get(&game.into()) {
+            stats.visits.fetch_sub(1, Ordering::Relaxed);
+            stats.add_rewards(Role::Attacker, VIRTUAL_LOSS);
+            stats.add_rewards(Role::Defender, VIRTUAL_LOSS);
+        }
+    }
+
     /// An adjustment added to a positions score to encourage exploration vs. exploitation
-    /// This factor should be tightened as models get stronger.
-    fn exploration_adjustment(&self, parent: &GameTreeNode, child: &GameTreeNode) -> f64 {
-        let child_visits = self.get_visits(child) as f64;
+    /// This factor should be tightened as models get stronger. Children which have
+    /// never been visited have an unbounded adjustment, given as `None`.
+    fn exploration_adjustment(&self, parent: &GameTreeNode, child: &GameTreeNode) -> Option<f64> {
+        let child_visits = self.get_visits(child);
+        if child_visits == 0 {
+            return None;
+        }
         let parent_visits = std::cmp::max(self.get_visits(parent), 1) as f64;
-        self.exploration_constant * (parent_visits.ln() / child_visits).sqrt()
+        Some(self.exploration_constant * (parent_visits.ln() / child_visits as f64).sqrt())
+    }
+
+    /// Compare two children of `parent` by their evaluations, adding the
+    /// exploration adjustment if `training`. Unvisited children come first,
+    /// and are compared by their evaluations among themselves.
+    fn compare_evals(
+        &self,
+        parent: &GameTreeNode,
+        (child1, mut eval1): (&GameTreeNode, i64),
+        (child2, mut eval2): (&GameTreeNode, i64),
+        training: bool,
+    ) -> std::cmp::Ordering {
+        if training {
+            match (
+                self.exploration_adjustment(parent, child1),
+                self.exploration_adjustment(parent, child2),
+            ) {
+                (Some(adjustment1), Some(adjustment2)) => {
+                    eval1 += float_to_scaled_i64(adjustment1);
+                    eval2 += float_to_scaled_i64(adjustment2);
+                }
+                (None, None) => {}
+                (adjustment1, adjustment2) => {
+                    return adjustment1.is_none().cmp(&adjustment2.is_none());
+                }
+            }
+        }
+        eval1.cmp(&eval2)
+    }
+}
+
+/// Choose an index with probability proportional to `visits^(1/T)`. As the
+/// temperature approaches zero, this tends to the first most visited index,
+/// which is what is chosen at a temperature of zero.
+pub fn sample_by_visits(visits: &[u64], temperature: f64, rng: &mut impl Rng) -> Option<usize> {
+    let max = *visits.iter().max()?;
+    let greedy = visits.iter().position(|v| *v == max);
+    if temperature <= 0.0 || max == 0 {
+        return greedy;
     }
+    // scale by the maximum so that the weights don't overflow
+    let weights = visits
+        .iter()
+        .map(|v| (*v as f64 / max as f64).powf(1.0 / temperature))
+        .collect::<Vec<_>>();
+    let mut choice = rng.random::<f64>() * weights.iter().sum::<f64>();
+    for (ix, weight) in weights.iter().enumerate() {
+        if choice < *weight {
+            return Some(ix);
+        }
+        choice -= weight;
+    }
+    greedy
 }
 
 impl SelectionPolicy for NNSelectionPolicy {
     type TreeNode = GameTreeNode;
 
     fn eval_attacker(&self, child: &GameTreeNode) -> i64 {
-        let tensor = (&GameSummary::from(child)).try_into().unwrap();
-        float_to_scaled_i64(
+        if let Some(value) = self.terminal_value(child) {
+            return value;
+        }
+        let game = GameSummary::from(child);
+        self.blend(
+            child,
             self.attacker_nn
                 .as_ref()
-                .map(|nn| nn.eval(&tensor))
+                .map(|nn| nn.eval(&game))
                 .unwrap_or_else(|| self.fallback_eval(child)),
         )
     }
 
     fn eval_defender(&self, child: &GameTreeNode) -> i64 {
-        let tensor = (&GameSummary::from(child)).try_into().unwrap();
-        float_to_scaled_i64(
+        if let Some(value) = self.terminal_value(child) {
+            return value;
+        }
+        let game = GameSummary::from(child);
+        self.blend(
+            child,
             self.defender_nn
                 .as_ref()
-                .map(|nn| nn.eval(&tensor))
+                .map(|nn| nn.eval(&game))
                 .unwrap_or_else(|| self.fallback_eval(child)),
         )
     }
@@ -186,28 +458,438 @@ impl SelectionPolicy for NNSelectionPolicy {
         child2: &GameTreeNode,
     ) -> std::cmp::Ordering {
         match parent.turn {
-            Role::Defender => {
-                let mut eval1 = self.eval_attacker(child1);
-                let mut eval2 = self.eval_attacker(child2);
-                if NNetRole::is_training(self.attacker_nn.as_ref()) {
-                    eval1 += float_to_scaled_i64(self.exploration_adjustment(parent, child1));
-                    eval2 += float_to_scaled_i64(self.exploration_adjustment(parent, child2));
-                }
-                eval1
-                    .partial_cmp(&eval2)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            }
-            Role::Attacker => {
-                let mut eval1 = self.eval_defender(child1);
-                let mut eval2 = self.eval_defender(child2);
-                if NNetRole::is_training(self.defender_nn.as_ref()) {
-                    eval1 += float_to_scaled_i64(self.exploration_adjustment(parent, child1));
-                    eval2 += float_to_scaled_i64(self.exploration_adjustment(parent, child2));
-                }
-                eval1
-                    .partial_cmp(&eval2)
-                    .unwrap_or(std::cmp::Ordering::Equal)
+            Role::Defender => self.compare_evals(
+                parent,
+                (child1, self.eval_attacker(child1)),
+                (child2, self.eval_attacker(child2)),
+                NNetRole::is_training(self.attacker_nn.as_ref()),
+            ),
+            Role::Attacker => self.compare_evals(
+                parent,
+                (child1, self.eval_defender(child1)),
+                (child2, self.eval_defender(child2)),
+                NNetRole::is_training(self.defender_nn.as_ref()),
+            ),
+        }
+    }
+}
+
+/// Chooses the moves of playouts while there is no network yet. Children
+/// are picked by UCT over the statistics of `stats`, with the heuristic as
+/// a prior whose weight fades as a child is visited. Children which have
+/// not been visited are tried first, the best by the heuristic first.
+#[derive(Clone)]
+pub struct HeuristicRollout {
+    pub stats: NNSelectionPolicy,
+}
+
+impl HeuristicRollout {
+    /// The heuristic's evaluation of `child` for the player moving into it
+    fn prior(&self, child: &GameTreeNode) -> f64 {
+        -scaled_i64_to_float(heuristic(child).0)
+    }
+
+    /// The UCT score of `child` for the player to move in `parent`, or
+    /// `None` if it has never been visited
+    fn uct(&self, parent: &GameTreeNode, child: &GameTreeNode) -> Option<f64> {
+        let exploration = self.stats.exploration_adjustment(parent, child)?;
+        let (visits, rewards) = {
+            let stats = self.stats.stats_map.lock().unwrap();
+            let stats = stats.get(&child.into())?;
+            let rewards = match parent.turn {
+                Role::Attacker => stats.attacker_rewards.load(Ordering::Relaxed),
+                Role::Defender => stats.defender_rewards.load(Ordering::Relaxed),
+            };
+            (stats.visits.load(Ordering::Relaxed) as f64, rewards)
+        };
+        Some(
+            scaled_i64_to_float(rewards) / visits
+                + exploration
+                + self.prior(child) / (visits + 1.0),
+        )
+    }
+}
+
+impl SelectionPolicy for HeuristicRollout {
+    type TreeNode = GameTreeNode;
+
+    fn eval_attacker(&self, child: &GameTreeNode) -> i64 {
+        heuristic(child).0
+    }
+
+    fn eval_defender(&self, child: &GameTreeNode) -> i64 {
+        heuristic(child).0
+    }
+
+    fn compare_children(
+        &self,
+        parent: &GameTreeNode,
+        child1: &GameTreeNode,
+        child2: &GameTreeNode,
+    ) -> std::cmp::Ordering {
+        match (self.uct(parent, child1), self.uct(parent, child2)) {
+            (Some(uct1), Some(uct2)) => uct1.total_cmp(&uct2),
+            (None, None) => self.prior(child1).total_cmp(&self.prior(child2)),
+            (uct1, uct2) => uct1.is_none().cmp(&uct2.is_none()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_selection {
+    use super::*;
+    use crate::alpha_beta::heuristic::HeuristicPolicy;
+    use crate::game::{PositionsTracker, Status};
+    use crate::game_tree::WIN_REWARD;
+
+    /// Test that sampling is random at high temperatures and greedy
+    /// at zero temperature
+    #[test]
+    fn test_sample_by_visits() {
+        let mut rng = rand::rng();
+        let visits = [3, 10, 9, 10];
+        let samples = (0..100)
+            .map(|_| sample_by_visits(&visits, 100.0, &mut rng).expect("Test failed"))
+            .collect::<Vec<_>>();
+        assert!(samples.iter().any(|ix| *ix != 1));
+        for _ in 0..20 {
+            assert_eq!(sample_by_visits(&visits, 0.0, &mut rng), Some(1));
+        }
+        assert_eq!(sample_by_visits(&[0, 0], 1.0, &mut rng), Some(0));
+        assert_eq!(sample_by_visits(&[], 1.0, &mut rng), None);
+    }
+
+    /// Test that at zero temperature, the sampled child is the
+    /// most visited one
+    #[test]
+    fn test_select_child_sampled() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let policy = NNSelectionPolicy::default();
+        let plays = root.get_plays();
+        let (best_play, best) = plays[7].clone();
+        for _ in 0..3 {
+            policy.update_stats(&best, 1.0, -1.0);
+        }
+        policy.update_stats(&plays[2].1, 1.0, -1.0);
+        let mut rng = rand::rng();
+        assert_eq!(
+            policy.select_child_sampled(&root, &mut rng),
+            Some((best_play, best))
+        );
+
+        let policy = policy.with_temperature(100.0);
+        let samples = (0..100)
+            .map(|_| {
+                policy
+                    .select_child_sampled(&root, &mut rng)
+                    .expect("Test failed")
+            })
+            .collect::<Vec<_>>();
+        assert!(samples.iter().any(|(play, _)| *play != plays[7].0));
+    }
+
+    /// Test that the heuristic blend moves the evaluation between the
+    /// heuristic and the network's (or fallback's) evaluation
+    #[test]
+    fn test_heuristic_blend() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let children = root.get_children();
+        let policy = NNSelectionPolicy::default();
+        for child in &children[..5] {
+            policy.update_stats(child, 1.0, -1.0);
+        }
+        let heuristic = NNSelectionPolicy {
+            heuristic_blend: 0.0,
+            ..policy.clone()
+        };
+        let half = NNSelectionPolicy {
+            heuristic_blend: 0.5,
+            ..policy.clone()
+        };
+        let squashed = |child: &GameTreeNode| {
+            (scaled_i64_to_float(HeuristicPolicy::default().eval_attacker(child))
+                / HEURISTIC_SQUASH)
+                .tanh()
+        };
+        for child in &children[..10] {
+            let pure = float_to_scaled_i64(policy.fallback_eval(child));
+            assert_eq!(policy.eval_attacker(child), pure);
+            assert_eq!(policy.eval_defender(child), pure);
+            let heuristic_eval = float_to_scaled_i64(squashed(child));
+            assert_eq!(heuristic.eval_attacker(child), heuristic_eval);
+            assert_eq!(heuristic.eval_defender(child), heuristic_eval);
+            let mixed = (pure + heuristic_eval) as f64 / 2.0;
+            assert!((half.eval_attacker(child) as f64 - mixed).abs() <= 1.0);
+        }
+    }
+
+    /// Test that blending keeps evaluations within the network's range,
+    /// even where the heuristic's own evaluation is far outside it
+    #[test]
+    fn test_heuristic_blend_range() {
+        // attackers next to every corner, which the heuristic penalizes
+        let board = Board::try_from([
+            ".O.......O.",
+            "O.........O",
+            "...........",
+            "...........",
+            "...........",
+            ".....K.....",
+            "...........",
+            "...........",
+            "...........",
+            "O.........O",
+            ".O.......O.",
+        ])
+        .expect("Test failed");
+        let child = GameTreeNode {
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        assert!(scaled_i64_to_float(HeuristicPolicy::default().eval_attacker(&child)).abs() > 1.0);
+        for heuristic_blend in [0.0, 0.25, 0.5, 0.75] {
+            let policy = NNSelectionPolicy {
+                heuristic_blend,
+                ..Default::default()
+            };
+            for nn_eval in [-1.0, 0.0, 1.0] {
+                let blended = scaled_i64_to_float(policy.blend(&child, nn_eval));
+                assert!((-1.0..=1.0).contains(&blended));
             }
         }
     }
+
+    /// Test that while training, unvisited children are explored before
+    /// visited ones, whatever the latter's rewards
+    #[test]
+    fn test_unvisited_children_explored_first() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let children = root.get_children();
+        let policy = NNSelectionPolicy::default();
+        for _ in 0..4 {
+            policy.update_stats(&root, 0.0, 0.0);
+        }
+        policy.update_stats(&children[0], 1.0, -1.0);
+        for _ in 0..3 {
+            policy.update_stats(&children[1], -1.0, 1.0);
+        }
+        let eval = |ix: usize| (&children[ix], policy.eval_defender(&children[ix]));
+        let (unvisited, low, high) = (2, 0, 1);
+        assert!(eval(low).1 < eval(unvisited).1);
+        assert!(eval(high).1 > eval(unvisited).1);
+        for visited in [low, high] {
+            assert_eq!(
+                policy.compare_evals(&root, eval(unvisited), eval(visited), true),
+                std::cmp::Ordering::Greater
+            );
+            assert_eq!(
+                policy.compare_evals(&root, eval(visited), eval(unvisited), true),
+                std::cmp::Ordering::Less
+            );
+        }
+        // outside of training only the evaluations count
+        assert_eq!(
+            policy.compare_evals(&root, eval(unvisited), eval(high), false),
+            std::cmp::Ordering::Less
+        );
+        // a visit to the parent alone gives no bonus, rather than NaN
+        let policy = NNSelectionPolicy::default();
+        policy.update_stats(&root, 0.0, 0.0);
+        policy.update_stats(&children[0], 0.0, 0.0);
+        assert_eq!(
+            policy.exploration_adjustment(&root, &children[0]),
+            Some(0.0)
+        );
+        assert_eq!(policy.exploration_adjustment(&root, &children[1]), None);
+    }
+
+    /// Test that the heuristic and the network's policy agree on the
+    /// value of finished games, whether or not they have statistics
+    #[test]
+    fn test_terminal_value() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let policy = NNSelectionPolicy::default();
+        assert_eq!(policy.terminal_value(&root), None);
+        let win = float_to_scaled_i64(WIN_REWARD);
+        for (status, turn, value) in [
+            (Status::AttackersWin, Role::Attacker, win),
+            (Status::AttackersWin, Role::Defender, -win),
+            (Status::Resigned(Role::Attacker), Role::Defender, win),
+            (Status::Draw, Role::Attacker, 0),
+        ] {
+            let node = GameTreeNode {
+                status,
+                turn,
+                ..root.clone()
+            };
+            policy.update_stats(&node, 1.0, 1.0);
+            assert_eq!(policy.terminal_value(&node), Some(value));
+            assert_eq!(
+                HeuristicPolicy::default().terminal_value(&node),
+                Some(value)
+            );
+            assert_eq!(policy.eval(&node), value);
+            assert_eq!(HeuristicPolicy::default().eval(&node), value);
+        }
+    }
+
+    /// Test that exported statistics can be imported into a fresh policy
+    /// and are added to the statistics already present
+    #[test]
+    fn test_export_import_stats() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0));
+        let children = root.get_children();
+        let policy = NNSelectionPolicy::default();
+        policy.update_stats(&root, 0.5, -0.5);
+        policy.update_stats(&root, 1.0, -1.0);
+        policy.update_stats(&children[3], -1.0, 1.0);
+        let dir = tempfile::tempdir().expect("Test failed");
+        let path = dir.path().join("stats.json");
+        policy.export_stats(&path).expect("Test failed");
+
+        let imported = NNSelectionPolicy::default();
+        imported.import_stats(&path).expect("Test failed");
+        for node in [&root, &children[3], &children[4]] {
+            assert_eq!(imported.get_visits(node), policy.get_visits(node));
+            assert_eq!(imported.fallback_eval(node), policy.fallback_eval(node));
+        }
+        assert_eq!(imported.get_visits(&root), 2);
+        assert_eq!(imported.stats_map.lock().unwrap().len(), 2);
+
+        imported.import_stats(&path).expect("Test failed");
+        assert_eq!(imported.get_visits(&root), 4);
+        assert_eq!(imported.fallback_eval(&root), policy.fallback_eval(&root));
+    }
+
+    /// Test that a decaying schedule explores less as playouts go on
+    #[test]
+    fn test_exploration_schedule() {
+        let schedule = ExplorationSchedule {
+            start: 1.0,
+            end: 0.2,
+            decay: 0.5,
+        };
+        assert_eq!(schedule.at(0), 1.0);
+        assert!((schedule.at(1) - 0.6).abs() < 1e-9);
+        assert!(schedule.at(0) > schedule.at(99));
+        assert!((schedule.at(99) - 0.2).abs() < 1e-9);
+        let constant = ExplorationSchedule {
+            start: 0.5,
+            end: 0.5,
+            decay: 0.9,
+        };
+        assert_eq!(constant.at(0), constant.at(99));
+    }
+
+    /// Test that earlier positions are encoded after the current one,
+    /// most recent first, and that missing positions are left empty
+    #[test]
+    fn test_history_planes() {
+        let root = GameTreeNode::new(PositionsTracker::Counter(0)).with_history_planes(3);
+        let play = Play {
+            role: Role::Attacker,
+            from: Square { x: 0, y: 3 },
+            to: Square { x: 1, y: 3 },
+        };
+        let child = root.apply_play(&play).expect("Test failed");
+        let game = GameSummary::from(&child);
+        assert_eq!(game.history, vec![root.current_board.clone()]);
+
+        let input = input_tensor(&game, 3).expect("Test failed");
+        assert_eq!(input.dims(), &[input_channels(3), 11, 11]);
+        let slices = input.to_vec3::<f64>().expect("Test failed");
+        let pieces = |slice: &Vec<Vec<f64>>| slice.iter().flatten().sum::<f64>();
+        // the current position, with the defenders to move
+        assert_eq!(slices[1][1][3], 1.0);
+        assert_eq!(slices[1][0][3], 0.0);
+        // the position before it
+        assert_eq!(slices[4][1][3], 0.0);
+        assert_eq!(slices[4][0][3], 1.0);
+        assert_eq!(pieces(&slices[3]), pieces(&slices[0]));
+        assert_eq!(pieces(&slices[4]), pieces(&slices[1]));
+        assert_eq!(pieces(&slices[5]), 1.0);
+        // no position before the start of the game
+        assert!(slices[6..9].iter().all(|s| pieces(s) == 0.0));
+        // the turn is encoded after the history
+        assert!(slices[9].iter().flatten().all(|v| *v == 1.0));
+
+        // a single plane of history is the default encoding
+        let input = input_tensor(&game, 1).expect("Test failed");
+        assert_eq!(
+            input.to_vec3::<f64>().expect("Test failed"),
+            Tensor::try_from(&game)
+                .and_then(|t| t.to_vec3::<f64>())
+                .expect("Test failed")
+        );
+        assert_eq!(input.dims(), &[input_channels(1), 11, 11]);
+
+        // only as many positions as needed are kept
+        let grandchild = child
+            .apply_play(&Play {
+                role: Role::Defender,
+                from: Square { x: 5, y: 3 },
+                to: Square { x: 4, y: 3 },
+            })
+            .and_then(|g| {
+                g.apply_play(&Play {
+                    role: Role::Attacker,
+                    from: Square { x: 1, y: 3 },
+                    to: Square { x: 2, y: 3 },
+                })
+            })
+            .expect("Test failed");
+        let history = GameSummary::from(&grandchild).history;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1], child.current_board);
+    }
+
+    /// Test that the attackers' view of a position and the defenders'
+    /// view of its mirror are encoded the same
+    #[test]
+    fn test_canonical_perspective() {
+        let board = Board::try_from([
+            "...OOOOO...",
+            "...X....O..",
+            "...........",
+            "O....X....O",
+            "O...X.X...O",
+            "OO.XXKXX.OO",
+            "O...XXX...O",
+            "O....X....O",
+            "...........",
+            ".....O.....",
+            "...O.OOO...",
+        ])
+        .expect("Test failed");
+        let game = GameSummary {
+            status: Default::default(),
+            moves: 4,
+            repetitions: 0,
+            turn: Role::Attacker,
+            current_board: board.clone(),
+            history: vec![Board::default()],
+        };
+        let mirror = GameSummary {
+            turn: Role::Defender,
+            current_board: board.flip_perspective(),
+            history: vec![Board::default().flip_perspective()],
+            ..game.clone()
+        };
+        assert_eq!(mirror.canonical_perspective(), game);
+        assert_eq!(game.canonical_perspective(), game);
+        for history_planes in [1, 2] {
+            let encode = |game: &GameSummary| {
+                input_tensor(game, history_planes)
+                    .and_then(|t| t.to_vec3::<f64>())
+                    .expect("Test failed")
+            };
+            assert_eq!(encode(&game), encode(&mirror));
+        }
+        // the side to move comes first
+        let slices = Tensor::try_from(&mirror)
+            .and_then(|t| t.to_vec3::<f64>())
+            .expect("Test failed");
+        assert_eq!(slices[0][0][3], 1.0);
+        assert_eq!(slices[1][3][1], 1.0);
+    }
 }
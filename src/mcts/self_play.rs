@@ -0,0 +1,289 @@
+//! Play complete games with MCTS and record them to disk so that
+//! they can be inspected and used to retrain the networks.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::game::board::Board;
+use crate::game::{Play, PositionsTracker, Status};
+use crate::game_tree::GameTreeNode;
+use crate::mcts::NNetRole;
+use crate::mcts::selection::NNSelectionPolicy;
+use crate::mcts::simulate_random_playout;
+use crate::mcts::train::{ATTACKER_NN_FILE_PREFIX, DEFENDER_NN_FILE_PREFIX};
+
+/// The number of moves at the start of a game which are sampled by
+/// their visit counts rather than chosen greedily
+const SAMPLED_PLIES: usize = 10;
+
+/// A move made during self-play along with the number of times
+/// each candidate move was visited by the search.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SelfPlayMove {
+    pub play: Play,
+    pub visits: Vec<(Play, u64)>,
+}
+
+/// The record of a complete self-play game
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SelfPlayGame {
+    pub moves: Vec<SelfPlayMove>,
+    pub result: Status,
+}
+
+impl SelfPlayGame {
+    /// Read a game from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Write the game to a JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
+
+/// Play the given number of games from the starting position and write
+/// each of them to `out_dir`. The latest networks are used if they
+/// exist, otherwise moves are selected heuristically. Running again with
+/// the same seed and networks plays the same games. If `random_starts` is
+/// set, each game starts from a perturbed setup instead, see
+/// [`Board::random_start`]. Games still going after `max_plies` moves
+/// are recorded as draws.
+pub fn self_play(
+    games: usize,
+    out_dir: impl AsRef<Path>,
+    iterations: usize,
+    seed: u64,
+    random_starts: bool,
+    max_plies: usize,
+) -> anyhow::Result<()> {
+    let network = |prefix: &str| {
+        let file = format!("{prefix}_v0.model");
+        Path::new(&file).exists().then(|| NNetRole::playing(file))
+    };
+    let policy = NNSelectionPolicy {
+        attacker_nn: network(ATTACKER_NN_FILE_PREFIX),
+        defender_nn: network(DEFENDER_NN_FILE_PREFIX),
+        ..Default::default()
+    }
+    .with_temperature(1.0)
+    .with_seed(seed);
+    record_games(
+        &GameTreeNode::new(PositionsTracker::Counter(0)),
+        &policy,
+        games,
+        iterations,
+        random_starts,
+        max_plies,
+        out_dir,
+    )
+}
+
+/// Play games from `start` and write them to `out_dir` as `game_<n>.json`.
+/// Each game gets its own random number generator, seeded from the policy's
+/// seed and the game's index. With `random_starts`, the board of `start` is
+/// replaced by a random one for each game.
+fn record_games(
+    start: &GameTreeNode,
+    policy: &NNSelectionPolicy,
+    games: usize,
+    iterations: usize,
+    random_starts: bool,
+    max_plies: usize,
+    out_dir: impl AsRef<Path>,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&out_dir)?;
+    for ix in 0..games {
+        let mut rng = StdRng::seed_from_u64(policy.seed.wrapping_add(ix as u64));
+        let start = if random_starts {
+            GameTreeNode {
+                current_board: Board::random_start(&mut rng),
+                ..start.clone()
+            }
+        } else {
+            start.clone()
+        };
+        let game = play_game(&start, policy, iterations, max_plies, &mut rng);
+        println!("Game {ix}: {}", game.result);
+        game.save(out_dir.as_ref().join(format!("game_{ix}.json")))?;
+    }
+    Ok(())
+}
+
+/// Play a game to the end. Before each move, run the given number of
+/// playouts. The first moves are sampled according to their visit counts
+/// for variety, after which the most visited move is made. A game which
+/// lasts `max_plies` moves is stopped there and counted as a draw.
+fn play_game(
+    start: &GameTreeNode,
+    policy: &NNSelectionPolicy,
+    iterations: usize,
+    max_plies: usize,
+    rng: &mut impl Rng,
+) -> SelfPlayGame {
+    let mut node = start.clone();
+    let mut moves = vec![];
+    let greedy = policy.clone().with_temperature(0.0);
+    while !node.is_terminal() {
+        if moves.len() >= max_plies {
+            return SelfPlayGame {
+                moves,
+                result: Status::Draw,
+            };
+        }
+        for _ in 0..iterations {
+            simulate_random_playout(&node, policy, policy);
+        }
+        let visits = node
+            .get_plays()
+            .iter()
+            .map(|(play, child)| (play.clone(), policy.get_child_visits(child)))
+            .collect::<Vec<_>>();
+        let sampler = if moves.len() < SAMPLED_PLIES {
+            policy
+        } else {
+            &greedy
+        };
+        let Some((play, child)) = sampler.select_child_sampled(&node, rng) else {
+            break;
+        };
+        moves.push(SelfPlayMove { play, visits });
+        node = child;
+    }
+    SelfPlayGame {
+        moves,
+        result: node.status,
+    }
+}
+
+#[cfg(test)]
+mod test_self_play {
+    use super::*;
+    use crate::game::space::Role;
+    use std::collections::HashMap;
+    use std::sync::atomic::Ordering;
+
+    /// Test that games where the king can immediately escape are
+    /// recorded and can be read back
+    #[test]
+    fn test_record_games() {
+        let board = Board::try_from([
+            "...........",
+            "..........K",
+            "...........",
+            "...........",
+            ".....O.....",
+            "....O.O....",
+            ".....O.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let start = GameTreeNode {
+            turn: Role::Defender,
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        let dir = tempfile::tempdir().expect("Test failed");
+        record_games(&start, &Default::default(), 2, 5, false, 10, dir.path())
+            .expect("Test failed");
+        for ix in 0..2 {
+            let game = SelfPlayGame::load(dir.path().join(format!("game_{ix}.json")))
+                .expect("Test failed");
+            assert_eq!(game.result, Status::DefendersWin);
+            assert_eq!(game.moves.len(), 1);
+            let SelfPlayMove { play, visits } = &game.moves[0];
+            assert_eq!(play.role, Role::Defender);
+            assert!(visits.iter().any(|(p, v)| p == play && *v > 0));
+        }
+    }
+
+    /// Test that self-play with the same seed is reproducible
+    #[test]
+    fn test_seeded_self_play() {
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "...........",
+            "...O.......",
+            "...........",
+            ".....K.....",
+            "...........",
+            ".......O...",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let start = GameTreeNode {
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        let run = || {
+            let policy = NNSelectionPolicy::default()
+                .with_temperature(1.0)
+                .with_seed(7);
+            let mut rng = StdRng::seed_from_u64(policy.seed);
+            let game = play_game(&start, &policy, 3, 100, &mut rng);
+            let visits = policy
+                .stats_map
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(summary, stats)| {
+                    (
+                        (
+                            summary.current_board.to_string(),
+                            summary.turn,
+                            summary.moves,
+                        ),
+                        stats.visits.load(Ordering::Relaxed),
+                    )
+                })
+                .collect::<HashMap<_, _>>();
+            (game, visits)
+        };
+        let (game, visits) = run();
+        assert!(!visits.is_empty());
+        assert_eq!((game, visits), run());
+    }
+
+    /// Test that a game which can't be decided before the ply cap
+    /// is stopped there and recorded as a draw
+    #[test]
+    fn test_max_plies() {
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "..O........",
+            "...........",
+            ".....X.....",
+            "....XKX....",
+            ".....X.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let start = GameTreeNode {
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        let dir = tempfile::tempdir().expect("Test failed");
+        record_games(&start, &Default::default(), 1, 2, false, 4, dir.path()).expect("Test failed");
+        let game = SelfPlayGame::load(dir.path().join("game_0.json")).expect("Test failed");
+        assert_eq!(game.result, Status::Draw);
+        assert_eq!(game.moves.len(), 4);
+    }
+}
@@ -1,49 +1,184 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
 
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
 use crate::game::PositionsTracker;
+use crate::game::board::Board;
 use crate::game::space::Role;
 use crate::game_tree::{GameSummary, GameTreeNode};
-use crate::mcts::selection::{NNSelectionPolicy, Stats};
-use crate::mcts::{NNetRole, scaled_i64_to_float};
+use crate::mcts::selection::{
+    ExplorationSchedule, HeuristicRollout, NNSelectionPolicy, Stats, input_tensor,
+};
+use crate::mcts::{NNetRole, ProgressReport, mcts, scaled_i64_to_float};
+use crate::nn::TrainConfig;
 use candle_core::{Device, Tensor};
 
 pub const ATTACKER_NN_FILE_PREFIX: &str = "hnefatafl_attacker";
 pub const DEFENDER_NN_FILE_PREFIX: &str = "hnefatafl_defender";
 
-pub fn train(iterations: usize) {
+/// Explore widely at the start of each search and settle on the
+/// exploration constant used when playing
+const EXPLORATION: ExplorationSchedule = ExplorationSchedule {
+    start: 1.414,
+    end: 0.2,
+    decay: 0.995,
+};
+
+/// The policy choosing the moves of the playouts while training
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RolloutPolicy {
+    /// The handcrafted heuristic, for before any network exists
+    Heuristic,
+    /// The networks being trained
+    #[default]
+    Nn,
+}
+
+impl FromStr for RolloutPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(string: &str) -> anyhow::Result<Self> {
+        match string {
+            "heuristic" => Ok(Self::Heuristic),
+            "nn" => Ok(Self::Nn),
+            _ => Err(anyhow::Error::msg(format!(
+                "Error trying to convert '{string}' to a rollout policy!"
+            ))),
+        }
+    }
+}
+
+/// Train the networks by searching from the starting position. Progress
+/// is printed as the search runs unless `quiet` is set. The playouts of
+/// each search are spread over `threads` threads and their moves are
+/// chosen by `rollout`.
+///
+/// With a `random_start` seed, the searches start from a random
+/// variation of the standard setup chosen by it instead, see
+/// [`Board::random_start`].
+///
+/// With a `stats_dir`, each search adds to the statistics an earlier run
+/// left there and writes them back when it is done.
+pub fn train(
+    iterations: usize,
+    config: TrainConfig,
+    rollout: RolloutPolicy,
+    threads: usize,
+    random_start: Option<u64>,
+    stats_dir: Option<&Path>,
+    quiet: bool,
+) -> anyhow::Result<()> {
+    let report = |progress: &ProgressReport| {
+        if !quiet {
+            println!("{progress}");
+        }
+    };
+    let game = start(config.history_planes, random_start);
+    let stats_file =
+        |prefix: &str| stats_dir.map(|dir| dir.join(format!("{prefix}_v0_stats.json")));
     // v0 runs
     {
-        let defender_nn = NNetRole::training(format!("{}_v0.model", DEFENDER_NN_FILE_PREFIX));
-        let stats = Arc::new(Mutex::new(Default::default()));
-        let selection_policy = NNSelectionPolicy {
-            attacker_nn: None,
-            defender_nn: None,
-            exploration_constant: 1.414,
-            stats_map: stats.clone(),
-        };
-        let game = GameTreeNode::new(PositionsTracker::Counter(0));
-        crate::mcts::mcts(&game, &selection_policy, iterations);
+        let defender_nn =
+            NNetRole::training(format!("{}_v0.model", DEFENDER_NN_FILE_PREFIX), config);
+        let stats = search(
+            &game,
+            rollout,
+            (None, None),
+            iterations,
+            threads,
+            stats_file(DEFENDER_NN_FILE_PREFIX),
+            report,
+        )?;
         println!("Finished search");
-        let stats = Arc::into_inner(stats).unwrap().into_inner().unwrap();
         backpropagate(defender_nn, &stats);
     }
     {
-        let attacker_nn = NNetRole::training(format!("{}_v0.model", ATTACKER_NN_FILE_PREFIX));
+        let attacker_nn =
+            NNetRole::training(format!("{}_v0.model", ATTACKER_NN_FILE_PREFIX), config);
         let defender_nn = NNetRole::playing(format!("{}_v0.model", DEFENDER_NN_FILE_PREFIX));
-        let stats = Arc::new(Mutex::new(Default::default()));
-        let selection_policy = NNSelectionPolicy {
-            attacker_nn: Some(attacker_nn.clone()),
-            defender_nn: Some(defender_nn.clone()),
-            exploration_constant: 1.414,
-            stats_map: stats.clone(),
-        };
-        let game = GameTreeNode::new(PositionsTracker::Counter(0));
-        crate::mcts::mcts(&game, &selection_policy, iterations);
-        let stats = Arc::into_inner(stats).unwrap().into_inner().unwrap();
+        let stats = search(
+            &game,
+            rollout,
+            (Some(attacker_nn.clone()), Some(defender_nn)),
+            iterations,
+            threads,
+            stats_file(ATTACKER_NN_FILE_PREFIX),
+            report,
+        )?;
         backpropagate(attacker_nn, &stats);
     }
+    Ok(())
+}
+
+/// The position training searches from: the standard setup, or with a
+/// `random_start` seed a variation of it chosen by the seed
+fn start(history_planes: usize, random_start: Option<u64>) -> GameTreeNode {
+    let mut game =
+        GameTreeNode::new(PositionsTracker::Counter(0)).with_history_planes(history_planes);
+    if let Some(seed) = random_start {
+        game.current_board = Board::random_start(&mut StdRng::seed_from_u64(seed));
+    }
+    game
+}
+
+/// Search from `game` with the given attacker and defender networks and
+/// return the statistics gathered. If there is a `stats_file`, the search
+/// starts from the statistics in it, if any, and writes them back after.
+fn search(
+    game: &GameTreeNode,
+    rollout: RolloutPolicy,
+    (attacker_nn, defender_nn): (Option<NNetRole>, Option<NNetRole>),
+    iterations: usize,
+    threads: usize,
+    stats_file: Option<PathBuf>,
+    report: impl FnMut(&ProgressReport) + Send,
+) -> anyhow::Result<HashMap<GameSummary, Stats>> {
+    let stats = Arc::new(Mutex::new(Default::default()));
+    let policy = NNSelectionPolicy {
+        attacker_nn,
+        defender_nn,
+        exploration_constant: EXPLORATION.start,
+        stats_map: stats.clone(),
+        heuristic_blend: 1.0,
+        ..Default::default()
+    };
+    if let Some(path) = &stats_file
+        && path.exists()
+    {
+        policy.import_stats(path)?;
+    }
+    match rollout {
+        RolloutPolicy::Nn => mcts(
+            game,
+            &policy,
+            &policy,
+            iterations,
+            &EXPLORATION,
+            threads,
+            report,
+        ),
+        RolloutPolicy::Heuristic => mcts(
+            game,
+            &HeuristicRollout {
+                stats: policy.clone(),
+            },
+            &policy,
+            iterations,
+            &EXPLORATION,
+            threads,
+            report,
+        ),
+    }
+    if let Some(path) = &stats_file {
+        policy.export_stats(path)?;
+    }
+    drop(policy);
+    Ok(Arc::into_inner(stats).unwrap().into_inner().unwrap())
 }
 
 fn backpropagate(nn: NNetRole, stats: &HashMap<GameSummary, Stats>) {
@@ -52,21 +187,91 @@ fn backpropagate(nn: NNetRole, stats: &HashMap<GameSummary, Stats>) {
     };
     let mut nn = Arc::into_inner(nn_ptr).unwrap().into_inner().unwrap();
     println!("Training...");
-    for (game_pos, stats) in stats {
-        for board in game_pos.current_board.symmetries() {
-            let game = GameSummary {
-                current_board: board,
-                ..game_pos.clone()
-            };
-            let tensor = Tensor::try_from(&game).unwrap();
-            let rewards = scaled_i64_to_float(match game.turn {
-                Role::Attacker => stats.attacker_rewards.load(Ordering::Relaxed),
-                Role::Defender => stats.defender_rewards.load(Ordering::Relaxed),
-            });
-            // normalize the rewards
-            let rewards = rewards / stats.visits.load(Ordering::Relaxed) as f64;
-            let rewards = Tensor::new(&[rewards], &Device::Cpu).unwrap();
-            nn.train(&tensor, &rewards, 10).unwrap()
-        }
+    // the orientations of each class of positions the searches reached
+    let mut orientations = HashMap::<(Role, u64), HashSet<&Board>>::new();
+    for game in stats.keys() {
+        orientations
+            .entry((game.turn, game.current_board.canonical_zobrist()))
+            .or_default()
+            .insert(&game.current_board);
+    }
+    for (game, stats) in stats {
+        let tensor = input_tensor(game, nn.history_planes()).unwrap();
+        let rewards = scaled_i64_to_float(match game.turn {
+            Role::Attacker => stats.attacker_rewards.load(Ordering::Relaxed),
+            Role::Defender => stats.defender_rewards.load(Ordering::Relaxed),
+        });
+        // normalize the rewards
+        let rewards = rewards / stats.visits.load(Ordering::Relaxed) as f64;
+        let rewards = Tensor::new(&[rewards], &Device::Cpu).unwrap();
+        // each orientation reached is its own entry, so share the weight
+        // between them to count every class of positions once
+        let reached = orientations[&(game.turn, game.current_board.canonical_zobrist())].len();
+        debug_assert!(reached <= game.current_board.symmetry_class_size());
+        let weight = 1.0 / reached as f64;
+        nn.train_weighted(&tensor, &rewards, weight).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test_train {
+    use super::*;
+
+    /// Test that training starts from the standard setup unless random
+    /// starts are asked for, which the seed picks
+    #[test]
+    fn test_start() {
+        assert_eq!(start(1, None).current_board, Board::default());
+        let random = start(1, Some(3)).current_board;
+        random.validate_playable().expect("Test failed");
+        assert_eq!(random, start(1, Some(3)).current_board);
+        assert!((0..10).any(|seed| start(1, Some(seed)).current_board != Board::default()));
+    }
+
+    /// Test that a search with a statistics file adds to the statistics
+    /// of the one before it
+    #[test]
+    fn test_search_stats_file() {
+        // few pieces, so that the playouts are quick
+        let board = Board::try_from([
+            "...........",
+            "...........",
+            "..O........",
+            "...........",
+            ".....X.....",
+            "....XKX....",
+            ".....X.....",
+            "...........",
+            "...........",
+            "...........",
+            "...........",
+        ])
+        .expect("Test failed");
+        let game = GameTreeNode {
+            current_board: board,
+            ..GameTreeNode::new(PositionsTracker::Counter(0))
+        };
+        let dir = tempfile::tempdir().expect("Test failed");
+        let path = dir.path().join("stats.json");
+        let run = || {
+            let stats = search(
+                &game,
+                RolloutPolicy::Heuristic,
+                (None, None),
+                5,
+                1,
+                Some(path.clone()),
+                |_| {},
+            )
+            .expect("Test failed");
+            stats
+                .values()
+                .map(|stats| stats.visits.load(Ordering::Relaxed))
+                .sum::<u64>()
+        };
+        let first = run();
+        assert!(first > 0);
+        assert!(path.exists());
+        assert!(run() > first);
     }
 }
@@ -1,41 +1,120 @@
 //! A DCNN for training Hnefatafl using self-play.
 //!
 //! The data for an ongoing Hnefatafl game is as follows:
-//!  * An 11 x 11 board with attacker positions
-//!  * An 11 x 11 board with defender positions
+//!  * An 11 x 11 board with the positions of the side to move
+//!  * An 11 x 11 board with the positions of the other side
+//!  * An 11 x 11 board with the king's position
 //!  * A boolean indication if it is the attacker's turn
 //!  * A total move count
+//!  * The number of times the current position occurred before
+//!
+//! The game is always presented as if the attackers were to move, with
+//! the attackers and defenders swapped on the defenders' turn, so the
+//! turn is always set. The output is the value for the side to move.
 //!
 //! Following the approach of AlphaZero, c.f. https://arxiv.org/pdf/1712.01815,
-//! we represent board state as an (2T + 3) x 11 x 11 image stack, i.e.
-//! 2T + 2 input channels of 11 x 11 boards where T is the amount of historical
-//! data, currently only a value of 1 is supported.
+//! we represent board state as an (3T + 3) x 11 x 11 image stack, i.e.
+//! 3T + 3 input channels of 11 x 11 boards where T is the number of
+//! positions of history, see [`TrainConfig::history_planes`].
 //!
-//! Two of the 11 x 11 slices are for the piece positions. The last 3 slice
-//! contains the game metadata.
+//! The first 3T 11 x 11 slices are binary masks of the piece positions,
+//! three for the current position followed by three for each position
+//! before it. They are left empty for positions before the start of the
+//! game. The last 3 slices contain the game metadata.
 use std::path::{Path, PathBuf};
 
-use candle_core::{DType, Device, Module, Tensor};
+use candle_core::backprop::GradStore;
+use candle_core::{DType, Device, Module, ModuleT, Tensor};
 use candle_nn::ops::dropout;
 
 use candle_nn::{BatchNorm, Conv2d, Conv2dConfig, Linear, Optimizer, VarBuilder, VarMap};
 
+/// The number of 11 x 11 slices in the input image stack
+/// with `history_planes` positions of history
+pub const fn input_channels(history_planes: usize) -> usize {
+    3 * history_planes + 3
+}
+
+/// The number of 11 x 11 slices in the input image stack
+/// with only the current position
+#[allow(dead_code)]
+pub const INPUT_CHANNELS: usize = input_channels(1);
+
+/// How the learning rate changes as a model is trained
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum LrSchedule {
+    /// The learning rate never changes
+    #[default]
+    Constant,
+    /// Multiply the learning rate by `factor` every `every` calls to train
+    Step { every: usize, factor: f64 },
+    /// Multiply the learning rate by `factor` after every call to train
+    Exponential { factor: f64 },
+}
+
+impl LrSchedule {
+    /// The learning rate after the given number of calls to train
+    pub fn lr(&self, initial: f64, calls: usize) -> f64 {
+        match self {
+            LrSchedule::Constant => initial,
+            LrSchedule::Step { every, factor } => {
+                initial * factor.powi((calls / (*every).max(1)) as i32)
+            }
+            LrSchedule::Exponential { factor } => initial * factor.powi(calls as i32),
+        }
+    }
+}
+
+/// The parameters used to train a [`TaflNNet`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrainConfig {
+    /// The initial learning rate
+    pub lr: f64,
+    pub weight_decay: f64,
+    pub betas: (f64, f64),
+    /// The number of epochs for each call to train
+    pub epochs: usize,
+    pub schedule: LrSchedule,
+    /// Train on all symmetries of each input with the same target
+    pub symmetric: bool,
+    /// The number of positions, the current one and those before it,
+    /// in the input of the model
+    pub history_planes: usize,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        let defaults = candle_nn::ParamsAdamW::default();
+        Self {
+            lr: 1e-2,
+            weight_decay: defaults.weight_decay,
+            betas: (defaults.beta1, defaults.beta2),
+            epochs: 10,
+            schedule: Default::default(),
+            symmetric: false,
+            history_planes: 1,
+        }
+    }
+}
+
 /// A trainable DCNN for Hnefatafl
 pub struct TaflNNet {
     convolutions: [NormedConv2d; 4],
     linear_layers: [NormedLinear; 4],
     optimizer: candle_nn::AdamW,
-    #[allow(dead_code)]
     backend: PersistentVarMap,
+    config: TrainConfig,
+    /// The number of times train has been called
+    train_calls: usize,
 }
 
 impl TaflNNet {
     /// Initialize the DCNN architecture
-    pub fn new(model_files: impl AsRef<Path>) -> Self {
+    pub fn new(model_files: impl AsRef<Path>, config: TrainConfig) -> Self {
         let backend = PersistentVarMap::load_or_new(model_files);
         // the convolution layers
         let convolutions = [
-            NormedConv2d::new(4, 64, 1, &backend),
+            NormedConv2d::new(input_channels(config.history_planes), 64, 1, &backend),
             NormedConv2d::new(64, 128, 1, &backend),
             NormedConv2d::new(128, 256, 0, &backend),
             NormedConv2d::new(256, 512, 0, &backend),
@@ -50,7 +129,10 @@ impl TaflNNet {
         let optimizer = candle_nn::AdamW::new(
             backend.inner.all_vars(),
             candle_nn::ParamsAdamW {
-                lr: 1e-2,
+                lr: config.lr,
+                beta1: config.betas.0,
+                beta2: config.betas.1,
+                weight_decay: config.weight_decay,
                 ..Default::default()
             },
         )
@@ -60,56 +142,142 @@ impl TaflNNet {
             linear_layers,
             optimizer,
             backend,
+            config,
+            train_calls: 0,
         }
     }
 
+    /// The number of positions in the input of the model
+    pub fn history_planes(&self) -> usize {
+        self.config.history_planes
+    }
+
+    /// The learning rate that will be used by the next call to train
+    pub fn learning_rate(&self) -> f64 {
+        self.optimizer.learning_rate()
+    }
+
     /// Train the model with input compared against target for
-    /// the given number of epochs.
+    /// the configured number of epochs. Afterwards, the learning
+    /// rate is updated according to the schedule.
     ///
     /// The input is the above described Hnefatafl image stack.
     /// The output is an evaluation of the position for the
     /// current player, represented as a probability computed
     /// via an MCTS.
-    pub fn train(
+    ///
+    /// If training symmetrically, the loss is averaged over the
+    /// symmetries of the input so that the model learns to evaluate
+    /// them equally. The loss is scaled by `weight`.
+    pub fn train_weighted(
         &mut self,
         input: &Tensor,
         target: &Tensor,
-        epochs: usize,
+        weight: f64,
     ) -> candle_core::Result<()> {
-        for ep in 0..epochs {
-            let output = self
-                .forward(input)
-                .inspect_err(|e| println!("Could not train on input: {e}"))?;
-            let loss = candle_nn::loss::mse(&output, target)
-                .inspect_err(|e| println!("Could not compute loss: {e}"))?;
+        let inputs = if self.config.symmetric {
+            symmetries(input)?
+        } else {
+            vec![input.clone()]
+        };
+        // the gradients of each symmetry are accumulated separately
+        // as holding all of their graphs at once uses a lot of memory
+        let scale = inputs.len() as f64;
+        for ep in 0..self.config.epochs {
+            let mut grads: Option<GradStore> = None;
+            let mut output = 0.0;
+            let mut loss = 0.0;
+            for input in &inputs {
+                let o = self
+                    .forward_with(input, true)
+                    .inspect_err(|e| println!("Could not train on input: {e}"))?;
+                let l = (candle_nn::loss::mse(&o, target)
+                    .inspect_err(|e| println!("Could not compute loss: {e}"))?
+                    * (weight / scale))?;
+                output += to_value(&o)? / scale;
+                loss += l.to_scalar::<f64>()?;
+                let new = l.backward()?;
+                grads = Some(match grads {
+                    None => new,
+                    Some(mut acc) => {
+                        for var in self.backend.inner.all_vars() {
+                            if let Some(grad) = new.get(&var) {
+                                let sum = match acc.get(&var) {
+                                    Some(prev) => (prev + grad)?,
+                                    None => grad.clone(),
+                                };
+                                acc.insert(&var, sum);
+                            }
+                        }
+                        acc
+                    }
+                });
+            }
             if ep.rem_euclid(10) == 0 {
-                let o = output.max(0).unwrap().to_scalar::<f64>().unwrap();
-                let t = target.max(0).unwrap().to_scalar::<f64>().unwrap();
-                let l = loss.to_scalar::<f64>().unwrap();
-                println!("Output: {o}, target: {t}, loss: {l}")
+                let t = to_value(target).unwrap();
+                let lr = self.learning_rate();
+                println!("Output: {output}, target: {t}, loss: {loss}, lr: {lr}")
+            }
+            if let Some(grads) = grads {
+                self.optimizer
+                    .step(&grads)
+                    .inspect_err(|e| println!("Could not run optimizer: {e}"))?;
             }
-            self.optimizer
-                .backward_step(&loss)
-                .inspect_err(|e| println!("Could not run optimizer: {e}"))?;
         }
+        self.train_calls += 1;
+        self.optimizer
+            .set_learning_rate(self.config.schedule.lr(self.config.lr, self.train_calls));
         Ok(())
     }
 }
 
+/// Read the evaluation out of the single element output of the model,
+/// preserving its sign.
+pub fn to_value(output: &Tensor) -> candle_core::Result<f64> {
+    output.reshape(())?.to_scalar::<f64>()
+}
+
+/// The images of an input under the eight symmetries of the board.
+/// Flipping and transposing the board generate all of them.
+pub fn symmetries(input: &Tensor) -> candle_core::Result<Vec<Tensor>> {
+    let reversed = Tensor::new(&[10u32, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0], input.device())?;
+    let flip = |xs: &Tensor| xs.index_select(&reversed, 2);
+    let mut xs = input.reshape(((), 11, 11))?;
+    let mut images = Vec::with_capacity(8);
+    for _ in 0..4 {
+        let flipped = flip(&xs)?;
+        images.push(xs);
+        // flipping and then transposing is a rotation
+        xs = flipped.transpose(1, 2)?.contiguous()?;
+        images.push(flipped);
+    }
+    Ok(images)
+}
+
+/// Evaluating a position never applies dropout
 impl Module for TaflNNet {
     fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
-        let mut xs = xs.reshape(((), 4, 11, 11))?;
+        self.forward_with(xs, false)
+    }
+}
+
+impl TaflNNet {
+    /// Forward through the model, only applying dropout while `train` is
+    /// set so that evaluating the same position always gives the same result
+    fn forward_with(&self, xs: &Tensor, train: bool) -> candle_core::Result<Tensor> {
+        let channels = input_channels(self.config.history_planes);
+        let mut xs = xs.reshape(((), channels, 11, 11))?;
         //let mut xs = xs.clone();
         for conv in &self.convolutions {
             xs = conv.forward(&xs)?;
         }
         xs = xs.reshape((49, 512))?;
-        for (layer, ll) in self.linear_layers.iter().enumerate() {
-            xs = ll.forward(&xs)?;
-            if layer == 2 {
-                xs = xs.reshape((1, 49))?;
-            }
+        let (output, hidden) = self.linear_layers.split_last().unwrap();
+        for ll in hidden {
+            xs = ll.forward_t(&xs, train)?;
         }
+        xs = xs.reshape((1, 49))?;
+        xs = output.forward_output(&xs)?;
         xs = xs.reshape(1)?;
         xs.tanh()
     }
@@ -272,12 +440,21 @@ impl NormedLinear {
     }
 }
 
-impl Module for NormedLinear {
-    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+impl NormedLinear {
+    /// Forward as the output layer of a model. The ReLU and dropout are
+    /// skipped so that negative evaluations can be expressed, as is the
+    /// norm since normalizing a single output would cancel it out.
+    pub fn forward_output(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        self.layer.forward(xs)
+    }
+}
+
+impl ModuleT for NormedLinear {
+    fn forward_t(&self, xs: &Tensor, train: bool) -> candle_core::Result<Tensor> {
         let xs = self.layer.forward(xs)?;
         let mut xs = self.norm.forward_train(&xs)?;
         xs = xs.relu()?;
-        if self.dropout {
+        if self.dropout && train {
             xs = dropout(&xs, 0.2)?;
         }
         Ok(xs)
@@ -310,3 +487,257 @@ impl Drop for PersistentVarMap {
         _ = self.save();
     }
 }
+
+#[cfg(test)]
+mod test_nn {
+    use super::*;
+    use crate::game::board::Board;
+    use crate::game::space::Role;
+    use crate::game_tree::GameSummary;
+
+    fn tensor(board: Board) -> Tensor {
+        let game = GameSummary {
+            status: Default::default(),
+            moves: 3,
+            repetitions: 1,
+            turn: Role::Attacker,
+            current_board: board,
+            history: vec![],
+        };
+        Tensor::try_from(&game).expect("Test failed")
+    }
+
+    /// Test that the symmetries of an input are the inputs of the
+    /// symmetries of the board
+    #[test]
+    fn test_symmetries() {
+        let board = Board::try_from([
+            "...OOOOO...",
+            "...X....O..",
+            ".........O.",
+            "...O.X....O",
+            "O....XX...O",
+            "...O..XX..O",
+            "O.O.....O.O",
+            "OX.O.......",
+            "..........K",
+            ".....O.....",
+            "....OO.O...",
+        ])
+        .expect("Test failed");
+        let images = symmetries(&tensor(board.clone()))
+            .expect("Test failed")
+            .into_iter()
+            .map(|t| t.to_vec3::<f64>().expect("Test failed"))
+            .collect::<Vec<_>>();
+        let expected = board
+            .symmetries()
+            .into_iter()
+            .map(|b| tensor(b).to_vec3::<f64>().expect("Test failed"))
+            .collect::<Vec<_>>();
+        assert_eq!(images.len(), 8);
+        assert_eq!(expected.len(), 8);
+        for image in &expected {
+            assert!(images.contains(image));
+        }
+    }
+
+    /// Test that after training symmetrically, the model evaluates all
+    /// symmetries of the position the same
+    #[test]
+    #[ignore = "building and training the full network is slow"]
+    fn test_symmetric_training() {
+        let dir = tempfile::tempdir().expect("Test failed");
+        let mut nn = TaflNNet::new(
+            dir.path().join("test.model"),
+            TrainConfig {
+                epochs: 1,
+                symmetric: true,
+                ..Default::default()
+            },
+        );
+        let input = tensor(Board::default());
+        let target = Tensor::new(&[0.5f64], &Device::Cpu).expect("Test failed");
+        nn.train_weighted(&input, &target, 1.0)
+            .expect("Test failed");
+        let outputs = symmetries(&input)
+            .expect("Test failed")
+            .iter()
+            .map(|t| {
+                nn.forward(t)
+                    .and_then(|o| o.squeeze(0))
+                    .and_then(|o| o.to_scalar::<f64>())
+                    .expect("Test failed")
+            })
+            .collect::<Vec<_>>();
+        for output in &outputs {
+            assert!((output - outputs[0]).abs() < 1e-6);
+        }
+    }
+
+    /// Test that the input stack has a slice for each piece type and
+    /// piece of metadata
+    #[test]
+    fn test_input_shape() {
+        let input = tensor(Board::default());
+        assert_eq!(input.dims(), &[INPUT_CHANNELS, 11, 11]);
+        let slices = input.to_vec3::<f64>().expect("Test failed");
+        // the attackers' turn, the move count and the repetitions
+        for (slice, value) in slices[3..].iter().zip([1.0, 3.0, 1.0]) {
+            assert!(slice.iter().flatten().all(|v| *v == value));
+        }
+    }
+
+    /// Test that the pieces are encoded as disjoint binary masks
+    #[test]
+    fn test_piece_planes() {
+        let board = Board::default();
+        let slices = tensor(board.clone()).to_vec3::<f64>().expect("Test failed");
+        let counts = slices[..3]
+            .iter()
+            .map(|slice| {
+                assert!(slice.iter().flatten().all(|v| *v == 0.0 || *v == 1.0));
+                slice.iter().flatten().sum::<f64>()
+            })
+            .collect::<Vec<_>>();
+        let (attackers, defenders) = board.material();
+        assert_eq!(counts, vec![attackers as f64, defenders as f64 - 1.0, 1.0]);
+        for x in 0..11 {
+            for y in 0..11 {
+                assert!(slices[..3].iter().map(|slice| slice[x][y]).sum::<f64>() <= 1.0);
+            }
+        }
+    }
+
+    /// Test that the model accepts the input stack
+    #[test]
+    #[ignore = "building and training the full network is slow"]
+    fn test_forward_shape() {
+        let dir = tempfile::tempdir().expect("Test failed");
+        let nn = TaflNNet::new(dir.path().join("test.model"), Default::default());
+        let output = nn.forward(&tensor(Board::default())).expect("Test failed");
+        assert_eq!(output.dims(), &[1]);
+    }
+
+    /// Test that models taking positions of history accept the input
+    /// stack with that many positions
+    #[test]
+    #[ignore = "building and training the full network is slow"]
+    fn test_forward_shape_with_history() {
+        for history_planes in [2, 3] {
+            let dir = tempfile::tempdir().expect("Test failed");
+            let config = TrainConfig {
+                history_planes,
+                ..Default::default()
+            };
+            let nn = TaflNNet::new(dir.path().join("test.model"), config);
+            let input = Tensor::zeros(
+                (1, input_channels(history_planes), 11, 11),
+                DType::F64,
+                &Device::Cpu,
+            )
+            .expect("Test failed");
+            let output = nn.forward(&input).expect("Test failed");
+            assert_eq!(output.dims(), &[1]);
+        }
+    }
+
+    /// Test that the model can evaluate a position as losing
+    #[test]
+    #[ignore = "building and training the full network is slow"]
+    fn test_negative_output() {
+        let dir = tempfile::tempdir().expect("Test failed");
+        let nn = TaflNNet::new(dir.path().join("test.model"), Default::default());
+        // the output layer only sees the non-negative activations of the
+        // layers before it, so negative weights give a negative output
+        let weights = nn
+            .backend
+            .inner
+            .data()
+            .lock()
+            .unwrap()
+            .get("weight_linear_49_1")
+            .cloned()
+            .expect("Test failed");
+        weights
+            .set(&Tensor::full(-1f64, (1, 49), &Device::Cpu).expect("Test failed"))
+            .expect("Test failed");
+        let output = nn.forward(&tensor(Board::default())).expect("Test failed");
+        assert!(to_value(&output).expect("Test failed") < 0.0);
+    }
+
+    /// Test the learning rate schedules
+    #[test]
+    fn test_lr_schedule() {
+        assert_eq!(LrSchedule::Constant.lr(0.1, 5), 0.1);
+        let step = LrSchedule::Step {
+            every: 2,
+            factor: 0.5,
+        };
+        let rates = (0..5).map(|calls| step.lr(0.1, calls)).collect::<Vec<_>>();
+        assert_eq!(rates, vec![0.1, 0.1, 0.05, 0.05, 0.025]);
+        let exponential = LrSchedule::Exponential { factor: 0.5 };
+        let rates = (0..3)
+            .map(|calls| exponential.lr(0.1, calls))
+            .collect::<Vec<_>>();
+        assert_eq!(rates, vec![0.1, 0.05, 0.025]);
+    }
+
+    /// Test that the learning rate is configurable, follows the
+    /// schedule, and that a larger one makes larger updates
+    #[test]
+    #[ignore = "building and training the full network is slow"]
+    fn test_learning_rate() {
+        let input = tensor(Board::default());
+        let target = Tensor::new(&[0.5f64], &Device::Cpu).expect("Test failed");
+        let first_step = |lr: f64| {
+            let dir = tempfile::tempdir().expect("Test failed");
+            let mut nn = TaflNNet::new(
+                dir.path().join("test.model"),
+                TrainConfig {
+                    lr,
+                    epochs: 1,
+                    schedule: LrSchedule::Exponential { factor: 0.5 },
+                    ..Default::default()
+                },
+            );
+            assert_eq!(nn.learning_rate(), lr);
+            let before = nn
+                .backend
+                .inner
+                .all_vars()
+                .iter()
+                .map(|var| var.as_tensor().copy().expect("Test failed"))
+                .collect::<Vec<_>>();
+            nn.train_weighted(&input, &target, 1.0)
+                .expect("Test failed");
+            assert_eq!(nn.learning_rate(), lr / 2.0);
+            nn.backend
+                .inner
+                .all_vars()
+                .iter()
+                .zip(before)
+                .map(|(var, before)| {
+                    (var.as_tensor() - before)
+                        .and_then(|d| d.abs())
+                        .and_then(|d| d.sum_all())
+                        .and_then(|d| d.to_scalar::<f64>())
+                        .expect("Test failed")
+                })
+                // the final batch norm only ever sees batches of one,
+                // which can leave its parameters undefined
+                .filter(|change| change.is_finite())
+                .sum::<f64>()
+        };
+        assert!(first_step(1e-2) > first_step(1e-4));
+    }
+
+    /// Test that negative evaluations are read out unchanged
+    #[test]
+    fn test_to_value() {
+        let output = Tensor::new(&[-0.75f64], &Device::Cpu).expect("Test failed");
+        assert_eq!(to_value(&output).expect("Test failed"), -0.75);
+        let output = Tensor::new(&[0.25f64], &Device::Cpu).expect("Test failed");
+        assert_eq!(to_value(&output).expect("Test failed"), 0.25);
+    }
+}